@@ -0,0 +1,99 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2019 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+// Some toys have more than one kind of output, like a vibrator that also
+// rotates. In this example, we'll use the device command builder to set
+// everything a device can do in one go.
+
+use buttplug::client::{
+  ButtplugClient, ButtplugClientDevice, ButtplugClientDeviceMessageType, ButtplugClientEvent,
+  LinearCommand, RotateCommand, VibrateCommand,
+};
+use futures::StreamExt;
+use futures_timer::Delay;
+use std::{sync::Arc, time::Duration};
+
+async fn combined_commands_example() {
+  // Same setup as example #4.
+  let client = ButtplugClient::new("Example Client");
+  let mut event_stream = client.event_stream();
+  client.connect_in_process(None).await.unwrap();
+  if let Err(err) = client.start_scanning().await {
+    println!("Client errored when starting scan! {}", err);
+    return;
+  }
+
+  let control_device = |dev: Arc<ButtplugClientDevice>| async move {
+    // Calling command() on a device gives us a builder. We can set targets for
+    // any of the outputs the device has, using the same enums we'd pass to
+    // vibrate(), rotate(), etc, then send them all at once.
+    //
+    // Nothing is sent until we call send(), and if any of the targets don't
+    // work for the device (say we asked a vibrator to rotate), nothing is
+    // sent at all, so we never end up with a device half way through a
+    // command.
+    let mut builder = dev.command();
+    let mut outputs = vec![];
+    if dev
+      .allowed_messages
+      .contains_key(&ButtplugClientDeviceMessageType::VibrateCmd)
+    {
+      builder = builder.vibrate(VibrateCommand::Speed(0.5));
+      outputs.push("vibrating");
+    }
+    if dev
+      .allowed_messages
+      .contains_key(&ButtplugClientDeviceMessageType::RotateCmd)
+    {
+      builder = builder.rotate(RotateCommand::Rotate(0.5, true));
+      outputs.push("rotating");
+    }
+    if dev
+      .allowed_messages
+      .contains_key(&ButtplugClientDeviceMessageType::LinearCmd)
+    {
+      builder = builder.linear(LinearCommand::Linear(500, 1.0));
+      outputs.push("moving");
+    }
+    if outputs.is_empty() {
+      println!("{} doesn't have any outputs we know about!", dev.name);
+      return;
+    }
+    if let Err(err) = builder.send().await {
+      println!("Couldn't send commands to {}: {}", dev.name, err);
+      return;
+    }
+    println!("{} should be {}!", dev.name, outputs.join(" and "));
+    Delay::new(Duration::from_secs(1)).await;
+    dev.stop().await.unwrap();
+    println!("{} should stop!", dev.name);
+  };
+
+  loop {
+    match event_stream.next().await.unwrap() {
+      ButtplugClientEvent::DeviceAdded(dev) => {
+        println!("We got a device: {}", dev.name);
+        let fut = control_device(dev);
+        tokio::spawn(async move {
+          fut.await;
+        });
+      }
+      ButtplugClientEvent::ServerDisconnect => {
+        println!("Server disconnected!");
+        break;
+      }
+      _ => {}
+    }
+  }
+
+  println!("Exiting example");
+}
+
+#[tokio::main]
+async fn main() {
+  combined_commands_example().await;
+}
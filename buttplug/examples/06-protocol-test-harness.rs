@@ -0,0 +1,81 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2019 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+// Working on support for a new device, or want to make sure a protocol still
+// sends what you expect? The protocol test harness builds a device straight
+// from a protocol definition, backed by a fake in-memory device, and hands
+// back the exact bytes each command produces. No server, no client, no
+// hardware.
+//
+// This example needs the "test-harness" feature:
+//
+// cargo run --example 06-protocol-test-harness --features test-harness
+
+use buttplug::{
+  core::messages::{LinearCmd, VectorSubcommand, VibrateCmd, VibrateSubcommand},
+  device::{DeviceWriteCmd, Endpoint},
+  server::comm_managers::test::ProtocolTestHarness,
+  util::device_configuration::{load_protocol_config_from_json, DEVICE_CONFIGURATION_JSON},
+};
+
+async fn protocol_test_harness_example() {
+  // Protocol definitions come from the device configuration file. Here we'll
+  // use the one built into the library, but anything that parses as a device
+  // configuration will do, so you can try out changes to a definition before
+  // they ever make it into the real file.
+  let mut protocols = load_protocol_config_from_json(DEVICE_CONFIGURATION_JSON)
+    .unwrap()
+    .protocols;
+
+  // First up, vibration. The Aneros Vivi has two vibrators, each of which takes
+  // a speed from 0-127. We'll set the second one to half speed.
+  let harness = ProtocolTestHarness::new("aneros", protocols.remove("aneros").unwrap())
+    .await
+    .unwrap();
+  let writes = harness
+    .send_command(VibrateCmd::new(0, vec![VibrateSubcommand::new(1, 0.5)]).into())
+    .await
+    .unwrap();
+  println!("Aneros vibrate wrote {:?}", writes);
+  assert_eq!(
+    writes,
+    vec![DeviceWriteCmd::new(Endpoint::Tx, vec![0xF2, 64], false)]
+  );
+
+  // Now for something linear. Some protocols talk to the device while it's
+  // being set up. The harness keeps those writes separate, so they don't get
+  // mixed in with the output of the commands we send. We'll also pick which
+  // device name to advertise, since a definition can cover more than one.
+  let harness = ProtocolTestHarness::new_with_device_name(
+    "kiiroo-v2",
+    protocols.remove("kiiroo-v2").unwrap(),
+    "Launch",
+  )
+  .await
+  .unwrap();
+  println!(
+    "Kiiroo v2 initialization wrote {:?}",
+    harness.initialization_writes()
+  );
+  // Move to the middle of the stroke over half a second.
+  let writes = harness
+    .send_command(LinearCmd::new(0, vec![VectorSubcommand::new(0, 500, 0.5)]).into())
+    .await
+    .unwrap();
+  println!("Kiiroo v2 linear wrote {:?}", writes);
+  assert_eq!(writes.len(), 1);
+  assert_eq!(writes[0].endpoint, Endpoint::Tx);
+  // The first byte is the position, scaled to the 0-99 range the device uses.
+  assert_eq!(writes[0].data[0], 49);
+
+  println!("Exiting example");
+}
+
+#[tokio::main]
+async fn main() {
+  protocol_test_harness_example().await;
+}
@@ -9,28 +9,241 @@
 
 use super::{
   client_message_sorter::ClientMessageSorter,
-  device::{ButtplugClientDevice, ButtplugClientDeviceEvent},
-  ButtplugClientEvent, ButtplugClientMessageFuturePair,
+  device::{ButtplugClientDevice, ButtplugClientDeviceEvent, DeviceIndex},
+  ButtplugClientError, ButtplugClientEvent, ButtplugClientMessageFuturePair,
+  ButtplugClientPausedCommandPolicy, ButtplugClientPingTimeoutPolicy,
+  ButtplugClientUnknownMessagePolicy, ButtplugServerMessageFuture,
 };
 use crate::{
-  connector::{ButtplugConnector, ButtplugConnectorStateShared},
+  connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorStateShared},
   core::{
-    errors::{ButtplugDeviceError, ButtplugError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError, ButtplugPingError},
     messages::{
       ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage, ButtplugDeviceMessage,
-      ButtplugMessageValidator, DeviceList, DeviceMessageInfo,
+      ButtplugMessage, ButtplugMessageValidator, DeviceList, DeviceMessageInfo, StopAllDevices, StopDeviceCmd,
     },
   },
+  util::async_manager,
 };
 use dashmap::DashMap;
-use futures::FutureExt;
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+use futures::{
+  future::{self, AbortHandle, Aborted},
+  Future, FutureExt,
 };
-use tokio::sync::{broadcast, mpsc};
+use futures_timer::Delay;
+use std::{
+  collections::{HashMap, VecDeque},
+  panic::AssertUnwindSafe,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    Arc, Mutex, Weak,
+  },
+  time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing_futures::Instrument;
+
+/// Client settings the event loop reads while it runs. Each one is shared with
+/// the client, so changes made after connecting take effect right away.
+pub(super) struct ButtplugClientEventLoopSettings {
+  /// Maximum number of devices to keep in the device map. usize::MAX means
+  /// unlimited.
+  pub max_devices: Arc<AtomicUsize>,
+  /// How to handle messages from the server we don't know how to handle.
+  pub unknown_message_policy: Arc<Mutex<ButtplugClientUnknownMessagePolicy>>,
+  /// How to react to the server pinging out.
+  pub ping_timeout_policy: Arc<Mutex<ButtplugClientPingTimeoutPolicy>>,
+  /// Max device commands per second. 0 means unlimited.
+  pub max_command_rate: Arc<AtomicU32>,
+}
+
+/// Paces device commands across all devices, so the total rate stays under
+/// the client's max command rate.
+struct ClientCommandThrottle {
+  /// Max commands per second, shared with the client. 0 means unlimited.
+  max_rate: Arc<AtomicU32>,
+  last_sent: Option<Instant>,
+}
 
-/// Enum used for communication from the client to the event loop.
+impl ClientCommandThrottle {
+  fn new(max_rate: Arc<AtomicU32>) -> Self {
+    Self {
+      max_rate,
+      last_sent: None,
+    }
+  }
+
+  /// How long until the next command can be sent.
+  fn delay(&self) -> Duration {
+    match (self.max_rate.load(Ordering::SeqCst), self.last_sent) {
+      (0, _) | (_, None) => Duration::default(),
+      (max_rate, Some(last_sent)) => (Duration::from_secs(1) / max_rate)
+        .checked_sub(last_sent.elapsed())
+        .unwrap_or_default(),
+    }
+  }
+
+  fn mark_sent(&mut self) {
+    self.last_sent = Some(Instant::now());
+  }
+}
+
+/// Describes how a client event loop task exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtplugClientEventLoopExit {
+  /// The loop exited on its own, due to the client or server disconnecting.
+  Finished,
+  /// The loop was stopped via [ButtplugClientEventLoopHandle::abort].
+  Aborted,
+  /// The loop panicked.
+  Panicked,
+}
+
+/// Handle to the task running a [ButtplugClient][super::ButtplugClient]'s
+/// event loop.
+///
+/// Allows embedders to supervise the event loop, i.e. to find out when (and
+/// how) it has stopped, or to stop it themselves. Handles can be cloned, and
+/// dropping them does not affect the event loop.
+///
+/// Aborting the event loop effectively disconnects the client: the connector
+/// is dropped without a clean disconnect, the client and its devices are
+/// marked as disconnected, and a [ButtplugClientEvent::ServerDisconnect] event
+/// is emitted. The same cleanup happens if the loop panics.
+#[derive(Clone)]
+pub struct ButtplugClientEventLoopHandle {
+  abort_handle: AbortHandle,
+  exit_receiver: watch::Receiver<Option<ButtplugClientEventLoopExit>>,
+}
+
+impl ButtplugClientEventLoopHandle {
+  /// Spawns the event loop future, running `on_unexpected_exit` if the loop
+  /// stops due to being aborted or panicking.
+  pub(super) fn spawn<F, C>(event_loop_fut: F, on_unexpected_exit: C) -> Self
+  where
+    F: Future<Output = ()> + Send + 'static,
+    C: FnOnce() + Send + 'static,
+  {
+    let (event_loop_fut, abort_handle) = future::abortable(event_loop_fut);
+    let (exit_sender, exit_receiver) = watch::channel(None);
+    async_manager::spawn(
+      async move {
+        let exit = match AssertUnwindSafe(event_loop_fut).catch_unwind().await {
+          Ok(Ok(())) => ButtplugClientEventLoopExit::Finished,
+          Ok(Err(Aborted)) => ButtplugClientEventLoopExit::Aborted,
+          Err(_) => ButtplugClientEventLoopExit::Panicked,
+        };
+        if exit != ButtplugClientEventLoopExit::Finished {
+          error!("Client event loop exited unexpectedly: {:?}", exit);
+          on_unexpected_exit();
+        }
+        // We don't care if nobody is listening.
+        let _ = exit_sender.send(Some(exit));
+      }
+      .instrument(tracing::info_span!("Client Loop Span")),
+    )
+    .unwrap();
+    Self {
+      abort_handle,
+      exit_receiver,
+    }
+  }
+
+  /// Stops the event loop, disconnecting the client. See the
+  /// [ButtplugClientEventLoopHandle] docs for details.
+  pub fn abort(&self) {
+    self.abort_handle.abort();
+  }
+
+  /// Returns true if the event loop has exited.
+  pub fn is_finished(&self) -> bool {
+    self.exit_receiver.borrow().is_some()
+  }
+
+  /// Waits for the event loop to exit, returning how it exited.
+  pub async fn join(&self) -> ButtplugClientEventLoopExit {
+    let mut exit_receiver = self.exit_receiver.clone();
+    loop {
+      if let Some(exit) = *exit_receiver.borrow() {
+        return exit;
+      }
+      if exit_receiver.changed().await.is_err() {
+        // The task was dropped without reporting, which only happens if the
+        // runtime went away underneath it.
+        return exit_receiver
+          .borrow()
+          .unwrap_or(ButtplugClientEventLoopExit::Aborted);
+      }
+    }
+  }
+}
+
+/// Spawns a task that watches the event loop heartbeat, and tries to stop all
+/// devices if the event loop hasn't ticked within `timeout`.
+///
+/// The stop command is queued to the event loop, as the event loop owns the
+/// connector. If the loop is only stalled (i.e. waiting on a slow connector), it
+/// will be sent as soon as the loop recovers. If the loop is deadlocked for
+/// good, it will never be sent, so this is a best effort measure. Setting a
+/// max ping time on the server is the only way to guarantee devices stop if the
+/// client goes away.
+///
+/// Exits once the event loop exits.
+pub(super) fn spawn_event_loop_watchdog(
+  timeout: Duration,
+  heartbeat: Arc<Mutex<Instant>>,
+  event_loop_handle: ButtplugClientEventLoopHandle,
+  request_sender: mpsc::UnboundedSender<ButtplugClientRequest>,
+  event_sender: broadcast::Sender<ButtplugClientEvent>,
+) {
+  async_manager::spawn(
+    async move {
+      // Only trigger once per stall, rearm once the loop is ticking again.
+      let mut triggered = false;
+      loop {
+        select! {
+          _ = Delay::new(timeout / 4).fuse() => {},
+          _ = event_loop_handle.join().fuse() => {
+            debug!("Event loop exited, stopping watchdog.");
+            return;
+          }
+        };
+        let stalled_for = heartbeat.lock().unwrap().elapsed();
+        if stalled_for < timeout {
+          triggered = false;
+          continue;
+        }
+        if triggered {
+          continue;
+        }
+        triggered = true;
+        error!(
+          "Client event loop has not ticked in {:?}, trying to stop all devices.",
+          stalled_for
+        );
+        // Nothing waits on the reply, so the future can just drop.
+        let fut = ButtplugServerMessageFuture::default();
+        let _ = request_sender.send(ButtplugClientRequest::Message(
+          ButtplugClientMessageFuturePair::new(
+            StopAllDevices::default().into(),
+            fut.get_state_clone(),
+          ),
+        ));
+        // Nobody may be listening, which is fine.
+        let _ = event_sender.send(ButtplugClientEvent::EventLoopStalled);
+      }
+    }
+    .instrument(tracing::info_span!("Client Event Loop Watchdog")),
+  )
+  .unwrap();
+}
+
+/// Enum used for communication from the client (and its devices) to the event
+/// loop.
+///
+/// Everything goes through a single channel, so requests are handled in the
+/// order they were made, i.e. a [StopAllDevices] sent after a device command
+/// can never reach the server before it.
 #[derive(Clone)]
 pub(super) enum ButtplugClientRequest {
   /// Client request to disconnect, via already sent connector instance.
@@ -43,6 +256,15 @@ pub(super) enum ButtplugClientRequest {
   /// Bundled future should have reply set and waker called when this is
   /// finished.
   Message(ButtplugClientMessageFuturePair),
+  /// Device command from a [ButtplugClientDevice]. Sent via the connector like
+  /// [ButtplugClientRequest::Message], but subject to pausing and the command
+  /// rate limit.
+  DeviceMessage(ButtplugClientMessageFuturePair),
+  /// Client request to stop sending device commands and client events until
+  /// resumed, handling device commands sent in the meantime per the policy.
+  Pause(ButtplugClientPausedCommandPolicy),
+  /// Client request to resume after a pause.
+  Resume,
 }
 
 /// Event loop for running [ButtplugClient] connections.
@@ -85,15 +307,57 @@ where
   /// Receiver for messages send from the [ButtplugServer] via the connector.
   from_connector_receiver: mpsc::Receiver<ButtplugCurrentSpecServerMessage>,
   /// Map of devices shared between the client and the event loop
-  device_map: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+  device_map: Arc<DashMap<DeviceIndex, Arc<ButtplugClientDevice>>>,
+  /// Devices the server has removed, by index. If the server adds a matching
+  /// device again while the application still holds the old instance, that
+  /// instance is reused instead of creating a new one.
+  removed_devices: HashMap<DeviceIndex, Weak<ButtplugClientDevice>>,
+  /// Maximum number of devices to keep in the device map, shared with the
+  /// client. usize::MAX means unlimited.
+  max_devices: Arc<AtomicUsize>,
+  /// How to handle messages from the server we don't know how to handle,
+  /// shared with the client.
+  unknown_message_policy: Arc<Mutex<ButtplugClientUnknownMessagePolicy>>,
+  /// How to react to the server pinging out, shared with the client.
+  ping_timeout_policy: Arc<Mutex<ButtplugClientPingTimeoutPolicy>>,
   /// Sends events to the [ButtplugClient] instance.
   to_client_sender: broadcast::Sender<ButtplugClientEvent>,
-  /// Sends events to the client receiver. Stored here so it can be handed to
-  /// new ButtplugClientDevice instances.
-  from_client_sender: broadcast::Sender<ButtplugClientRequest>,
-  /// Receives incoming messages from client instances.
-  from_client_receiver: broadcast::Receiver<ButtplugClientRequest>,
+  /// Sends requests to the event loop. Stored here so it can be handed to the
+  /// client and to new ButtplugClientDevice instances.
+  request_sender: mpsc::UnboundedSender<ButtplugClientRequest>,
+  /// Receives requests from the client and ButtplugClientDevice instances.
+  /// Unbounded so requests are never dropped due to channel lag, and shared
+  /// so they are handled in the order they were issued.
+  request_receiver: mpsc::UnboundedReceiver<ButtplugClientRequest>,
+  /// Closes once the client that owns this loop is dropped. Nothing is ever
+  /// sent on it.
+  client_alive_receiver: mpsc::Receiver<()>,
   sorter: ClientMessageSorter,
+  /// Handed to reply futures, which signal on it if they're dropped before
+  /// their reply arrives.
+  reply_cancel_sender: mpsc::UnboundedSender<()>,
+  /// Receives reply future drop signals, so we can clean up the sorter.
+  reply_cancel_receiver: mpsc::UnboundedReceiver<()>,
+  /// Updated every time the loop runs, so a watchdog can tell if the loop has
+  /// stalled.
+  heartbeat: Arc<Mutex<Instant>>,
+  /// If set, the loop will wake up at least this often to update the
+  /// heartbeat, even if there's nothing to do.
+  heartbeat_interval: Option<Duration>,
+  /// Set while the client has paused the loop, to the policy for device
+  /// commands sent during the pause.
+  paused: Option<ButtplugClientPausedCommandPolicy>,
+  /// Device commands queued while paused, in the order they were sent.
+  paused_commands: VecDeque<ButtplugClientMessageFuturePair>,
+  /// Client events held while paused.
+  paused_events: VecDeque<ButtplugClientEvent>,
+  /// Limits how fast device commands go out.
+  command_throttle: ClientCommandThrottle,
+  /// Device commands waiting on the throttle, in the order they were sent.
+  throttled_commands: VecDeque<ButtplugClientMessageFuturePair>,
+  /// Number of devices added (or reconnected) over the life of the loop, so
+  /// the client can tell how many devices turned up during a scan.
+  added_device_count: Arc<AtomicUsize>,
 }
 
 impl<ConnectorType> ButtplugClientEventLoop<ConnectorType>
@@ -111,22 +375,69 @@ where
     connector: ConnectorType,
     from_connector_receiver: mpsc::Receiver<ButtplugCurrentSpecServerMessage>,
     to_client_sender: broadcast::Sender<ButtplugClientEvent>,
-    from_client_sender: broadcast::Sender<ButtplugClientRequest>,
-    device_map: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+    client_alive_receiver: mpsc::Receiver<()>,
+    device_map: Arc<DashMap<DeviceIndex, Arc<ButtplugClientDevice>>>,
+    settings: ButtplugClientEventLoopSettings,
   ) -> Self {
     trace!("Creating ButtplugClientEventLoop instance.");
+    let (request_sender, request_receiver) = mpsc::unbounded_channel();
+    let (reply_cancel_sender, reply_cancel_receiver) = mpsc::unbounded_channel();
     Self {
       connected_status,
       device_map,
-      from_client_receiver: from_client_sender.subscribe(),
-      from_client_sender,
+      removed_devices: HashMap::new(),
+      max_devices: settings.max_devices,
+      unknown_message_policy: settings.unknown_message_policy,
+      ping_timeout_policy: settings.ping_timeout_policy,
+      request_sender,
+      request_receiver,
+      client_alive_receiver,
       to_client_sender,
       from_connector_receiver,
       connector,
       sorter: ClientMessageSorter::default(),
+      reply_cancel_sender,
+      reply_cancel_receiver,
+      heartbeat: Arc::new(Mutex::new(Instant::now())),
+      heartbeat_interval: None,
+      paused: None,
+      paused_commands: VecDeque::new(),
+      paused_events: VecDeque::new(),
+      command_throttle: ClientCommandThrottle::new(settings.max_command_rate),
+      throttled_commands: VecDeque::new(),
+      added_device_count: Arc::new(AtomicUsize::new(0)),
     }
   }
 
+  /// Returns the heartbeat updated by the loop, and makes sure the loop
+  /// updates it at least every `interval`.
+  pub fn heartbeat(&mut self, interval: Duration) -> Arc<Mutex<Instant>> {
+    self.heartbeat_interval = Some(interval);
+    self.heartbeat.clone()
+  }
+
+  /// Returns a sender for requests to the event loop.
+  pub fn request_sender(&self) -> mpsc::UnboundedSender<ButtplugClientRequest> {
+    self.request_sender.clone()
+  }
+
+  /// Returns a sender for reply futures to signal on when they're dropped
+  /// before their reply arrives.
+  pub fn reply_cancel_sender(&self) -> mpsc::UnboundedSender<()> {
+    self.reply_cancel_sender.clone()
+  }
+
+  /// Returns the count of devices added to the client over the life of the
+  /// loop.
+  pub fn added_device_count(&self) -> Arc<AtomicUsize> {
+    self.added_device_count.clone()
+  }
+
+  /// Returns the count of requests waiting on replies from the server.
+  pub fn pending_request_count(&self) -> Arc<AtomicUsize> {
+    self.sorter.pending_request_count()
+  }
+
   /// Creates a [ButtplugClientDevice] from [DeviceMessageInfo].
   ///
   /// Given a [DeviceMessageInfo] from a [DeviceAdded] or [DeviceList] message,
@@ -137,7 +448,8 @@ where
       "Trying to create a client device from DeviceMessageInfo: {:?}",
       info
     );
-    match self.device_map.get(&info.device_index) {
+    let device_index = DeviceIndex(info.device_index);
+    match self.device_map.get(&device_index) {
       // If the device already exists in our map, clone it.
       Some(dev) => {
         debug!("Device already exists, creating clone.");
@@ -148,15 +460,86 @@ where
         debug!("Device does not exist, creating new entry.");
         let device = Arc::new(ButtplugClientDevice::new_from_device_info(
           info,
-          self.from_client_sender.clone(),
+          self.request_sender.clone(),
+          self.reply_cancel_sender.clone(),
+          &self.device_map,
         ));
-        self.device_map.insert(info.device_index, device.clone());
+        self.device_map.insert(device_index, device.clone());
         device
       }
     }
   }
 
+  /// Brings a removed device instance back, if the application still holds it
+  /// and `info` matches it.
+  fn reconnect_client_device(
+    &mut self,
+    info: &DeviceMessageInfo,
+  ) -> Option<Arc<ButtplugClientDevice>> {
+    let device_index = DeviceIndex(info.device_index);
+    let device = self.removed_devices.remove(&device_index)?.upgrade()?;
+    if !device.matches_device_info(info) {
+      debug!(
+        "Device at index {} does not match removed device, creating new entry.",
+        info.device_index
+      );
+      return None;
+    }
+    debug!("Device {} reconnected, reusing existing instance.", info.device_index);
+    device.clear_command_dedup();
+    device.update_add_sequence();
+    device.set_device_connected(true);
+    self.device_map.insert(device_index, device.clone());
+    device.queue_event(ButtplugClientDeviceEvent::DeviceReconnected);
+    Some(device)
+  }
+
+  /// Adds a device the server told us about, reusing a removed instance if
+  /// it has reconnected, and lets the client know.
+  fn add_client_device(&mut self, info: &DeviceMessageInfo) {
+    self.added_device_count.fetch_add(1, Ordering::SeqCst);
+    if let Some(device) = self.reconnect_client_device(info) {
+      self.send_client_event(ButtplugClientEvent::DeviceReconnected(device));
+    } else {
+      let device = self.create_client_device(info);
+      self.send_client_event(ButtplugClientEvent::DeviceAdded(device));
+    }
+  }
+
+  /// Returns true if the device map is full, per the client's device limit.
+  fn device_limit_reached(&self) -> bool {
+    self.device_map.len() >= self.max_devices.load(Ordering::SeqCst)
+  }
+
+  /// Handles a device the server told us about that we don't have room for.
+  ///
+  /// The device is not added to the map. Since the client will never be able
+  /// to send it a stop command itself, we ask the server to stop it, then let
+  /// the client know what happened.
+  async fn reject_device(&mut self, info: DeviceMessageInfo) {
+    warn!(
+      "Device limit of {} reached, rejecting device {} ({}).",
+      self.max_devices.load(Ordering::SeqCst),
+      info.device_name,
+      info.device_index
+    );
+    // Nothing is waiting on the reply here, so we just let the future drop.
+    let fut = ButtplugServerMessageFuture::default();
+    self
+      .send_message(ButtplugClientMessageFuturePair::new(
+        StopDeviceCmd::new(info.device_index).into(),
+        fut.get_state_clone(),
+      ))
+      .await;
+    self.send_client_event(ButtplugClientEvent::DeviceRejected(info));
+  }
+
   fn send_client_event(&mut self, event: ButtplugClientEvent) {
+    if self.paused.is_some() {
+      trace!("Paused, holding event {:?}", event);
+      self.paused_events.push_back(event);
+      return;
+    }
     trace!("Forwarding event {:?} to client", event);
 
     if self.to_client_sender.receiver_count() == 0 {
@@ -172,16 +555,38 @@ where
     self.to_client_sender.send(event).unwrap();
   }
 
-  fn disconnect_device(&mut self, device_index: u32) {
+  /// Handles a message from the server we don't know what to do with,
+  /// according to the client's [ButtplugClientUnknownMessagePolicy].
+  fn handle_unknown_message(&mut self, msg: String) {
+    let policy = *self.unknown_message_policy.lock().unwrap();
+    match policy {
+      ButtplugClientUnknownMessagePolicy::Ignore => {
+        warn!("Cannot process message, dropping: {}", msg)
+      }
+      ButtplugClientUnknownMessagePolicy::Event => {
+        self.send_client_event(ButtplugClientEvent::UnknownMessage(msg))
+      }
+      ButtplugClientUnknownMessagePolicy::Error => self.send_client_event(
+        ButtplugClientEvent::Error(ButtplugMessageError::UnknownMessage(msg).into()),
+      ),
+    }
+  }
+
+  fn disconnect_device(&mut self, device_index: DeviceIndex) {
     if !self.device_map.contains_key(&device_index) {
       return;
     }
     // Checked for device index existence, can unwrap here.
     let device = (*self.device_map.get(&device_index).unwrap()).clone();
     device.set_device_connected(false);
+    device.clear_command_dedup();
     device.queue_event(ButtplugClientDeviceEvent::DeviceRemoved);
     // Then remove it from our storage map
     self.device_map.remove(&device_index);
+    // Hold on to it in case it comes back. Forget about removed devices the
+    // application has dropped while we're at it.
+    self.removed_devices.retain(|_, device| device.strong_count() > 0);
+    self.removed_devices.insert(device_index, Arc::downgrade(&device));
     self.send_client_event(ButtplugClientEvent::DeviceRemoved(device));
   }
 
@@ -191,15 +596,23 @@ where
   /// server, it will catch [DeviceAdded]/[DeviceList]/[DeviceRemoved] messages
   /// and update its map accordingly. After that, it will pass the information
   /// on as a [ButtplugClientEvent] to the [ButtplugClient].
-  async fn parse_connector_message(&mut self, msg: ButtplugCurrentSpecServerMessage) {
+  ///
+  /// Returns false if the loop should exit.
+  async fn parse_connector_message(&mut self, msg: ButtplugCurrentSpecServerMessage) -> bool {
     if self.sorter.maybe_resolve_result(&msg) {
       trace!("Message future found, returning");
-      return;
+      return true;
+    }
+    if msg.id() != 0 {
+      // Most likely a reply to a message whose future was dropped before the
+      // reply showed up.
+      debug!("No future waiting on message id {}, dropping: {:?}", msg.id(), msg);
+      return true;
     }
     if let Err(e) = msg.is_valid() {
       error!("Message not valid: {:?} - Error: {}", msg, e);
       self.send_client_event(ButtplugClientEvent::Error(ButtplugError::from(e)));
-      return;
+      return true;
     }
     trace!("Message future not found, assuming server event.");
     info!("{:?}", msg);
@@ -208,23 +621,28 @@ where
         trace!("Device added, updating map and sending to client");
         // We already have this device. Emit an error to let the client know the
         // server is being weird.
-        if self.device_map.get(&dev.device_index()).is_some() {
+        let device_index = DeviceIndex(dev.device_index());
+        if self.device_map.contains_key(&device_index) {
           self.send_client_event(ButtplugClientEvent::Error(
             ButtplugDeviceError::DeviceConnectionError(
               "Device already exists in client. Server may be in a weird state.".to_owned(),
             )
             .into(),
           ));
-          return;
+          return true;
         }
         let info = DeviceMessageInfo::from(dev);
-        let device = self.create_client_device(&info);
-        self.send_client_event(ButtplugClientEvent::DeviceAdded(device));
+        if self.device_limit_reached() {
+          self.reject_device(info).await;
+          return true;
+        }
+        self.add_client_device(&info);
       }
       ButtplugCurrentSpecServerMessage::DeviceRemoved(dev) => {
-        if self.device_map.contains_key(&dev.device_index()) {
+        let device_index = DeviceIndex(dev.device_index());
+        if self.device_map.contains_key(&device_index) {
           trace!("Device removed, updating map and sending to client");
-          self.disconnect_device(dev.device_index());
+          self.disconnect_device(device_index);
         } else {
           error!("Received DeviceRemoved for non-existent device index");
           self.send_client_event(ButtplugClientEvent::Error(ButtplugDeviceError::DeviceConnectionError("Device removal requested for a device the client does not know about. Server may be in a weird state.".to_owned()).into()));
@@ -235,8 +653,9 @@ where
         self.send_client_event(ButtplugClientEvent::ScanningFinished);
       }
       ButtplugCurrentSpecServerMessage::RawReading(msg) => {
-        let device_idx = msg.device_index();
+        let device_idx = DeviceIndex(msg.device_index());
         if let Some(device) = self.device_map.get(&device_idx) {
+          device.value().update_last_seen();
           device
             .value()
             .queue_event(ButtplugClientDeviceEvent::Message(
@@ -245,9 +664,48 @@ where
         }
       }
       ButtplugCurrentSpecServerMessage::Error(e) => {
-        self.send_client_event(ButtplugClientEvent::Error(e.into()));
+        // The client serializer stands in errors for messages it couldn't
+        // deserialize because it doesn't know their type.
+        match e.original_error() {
+          ButtplugError::ButtplugMessageError(ButtplugMessageError::UnknownMessage(msg)) => {
+            self.handle_unknown_message(msg)
+          }
+          ButtplugError::ButtplugPingError(ButtplugPingError::PingedOut) => {
+            return self.handle_ping_timeout().await;
+          }
+          _ => self.send_client_event(ButtplugClientEvent::Error(e.into())),
+        }
+      }
+      msg => self.handle_unknown_message(format!("{:?}", msg)),
+    }
+    true
+  }
+
+  /// Reacts to the server pinging out, per the ping timeout policy. Returns
+  /// false if the loop should exit.
+  async fn handle_ping_timeout(&mut self) -> bool {
+    let policy = *self.ping_timeout_policy.lock().unwrap();
+    warn!("Server reported a ping timeout, handling with policy {:?}.", policy);
+    // The server should have stopped everything already, but make sure, no
+    // matter what the policy says. Nothing is waiting on the reply here.
+    let fut = ButtplugServerMessageFuture::default();
+    self
+      .send_message(ButtplugClientMessageFuturePair::new(
+        StopAllDevices::default().into(),
+        fut.get_state_clone(),
+      ))
+      .await;
+    self.send_client_event(ButtplugClientEvent::PingTimeout);
+    match policy {
+      ButtplugClientPingTimeoutPolicy::Notify => true,
+      ButtplugClientPingTimeoutPolicy::Disconnect => {
+        info!("Disconnecting due to ping timeout.");
+        if let Err(e) = self.connector.disconnect().await {
+          error!("Error disconnecting after ping timeout: {:?}", e);
+        }
+        self.connected_status.store(false, Ordering::SeqCst);
+        false
       }
-      _ => error!("Cannot process message, dropping: {:?}", msg),
     }
   }
 
@@ -261,10 +719,142 @@ where
       return;
     }
 
+    if let ButtplugCurrentSpecClientMessage::StopAllDevices(_) = msg_fut.msg {
+      self.cancel_waiting_device_commands();
+    }
+
     trace!("Sending message to connector: {:?}", msg_fut.msg);
     self.sorter.register_future(&mut msg_fut);
+    let ButtplugClientMessageFuturePair { msg, waker } = msg_fut;
+    // The caller may have dropped its reply future before we got the message,
+    // in which case we've already handled its cancel signal. Now that the
+    // state is registered, clean it up.
+    drop(waker);
+    self.sorter.remove_abandoned_futures();
     // TODO What happens if the connector isn't connected?
-    self.connector.send(msg_fut.msg).await.unwrap();
+    self.connector.send(msg).await.unwrap();
+  }
+
+  /// Fails device commands that are still waiting on the throttle or a pause.
+  ///
+  /// Called before StopAllDevices goes out. Commands waiting here were
+  /// issued before the stop, so sending them after it would start devices back
+  /// up.
+  fn cancel_waiting_device_commands(&mut self) {
+    let count = self.throttled_commands.len() + self.paused_commands.len();
+    if count == 0 {
+      return;
+    }
+    info!(
+      "Stopping all devices, cancelling {} waiting device commands.",
+      count
+    );
+    for msg_fut in self
+      .throttled_commands
+      .drain(..)
+      .chain(self.paused_commands.drain(..))
+    {
+      msg_fut
+        .waker
+        .set_reply(Err(ButtplugClientError::CommandCancelled));
+    }
+  }
+
+  /// Sends a device command, unless we're paused, in which case it is queued
+  /// or failed per the pause policy.
+  async fn send_device_message(&mut self, msg_fut: ButtplugClientMessageFuturePair) {
+    // The caller may have dropped the reply future before we got here, in
+    // which case its cancel signal could've been handled already. Nobody's
+    // waiting on it, so don't bother sending or queueing it.
+    if msg_fut.waker.is_abandoned() {
+      debug!(
+        "Device message {:?} was abandoned before sending, dropping.",
+        msg_fut.msg
+      );
+      return;
+    }
+    match self.paused {
+      None => {
+        trace!("Sending device message through connector: {:?}", msg_fut.msg);
+        self.throttled_commands.push_back(msg_fut);
+        self.send_throttled_commands().await;
+      }
+      Some(ButtplugClientPausedCommandPolicy::Queue) => {
+        trace!("Paused, queueing device message: {:?}", msg_fut.msg);
+        self.paused_commands.push_back(msg_fut);
+      }
+      Some(ButtplugClientPausedCommandPolicy::Drop) => {
+        trace!("Paused, dropping device message: {:?}", msg_fut.msg);
+        msg_fut.waker.set_reply(Err(ButtplugClientError::ClientPaused));
+      }
+    }
+  }
+
+  /// Cleans up after reply futures dropped by their callers. Besides the
+  /// sorter, device commands still waiting on the throttle or a pause are
+  /// dropped, since nothing is waiting on their replies anymore.
+  fn remove_abandoned_futures(&mut self) {
+    self.sorter.remove_abandoned_futures();
+    let still_wanted = |msg_fut: &ButtplugClientMessageFuturePair| {
+      if msg_fut.waker.is_abandoned() {
+        debug!(
+          "Device message {:?} was abandoned while waiting, dropping.",
+          msg_fut.msg
+        );
+        false
+      } else {
+        true
+      }
+    };
+    self.throttled_commands.retain(still_wanted);
+    self.paused_commands.retain(still_wanted);
+  }
+
+  /// Ends a pause, sending queued device commands, then emitting held events.
+  async fn resume(&mut self) {
+    if self.paused.take().is_none() {
+      return;
+    }
+    info!(
+      "Resuming, sending {} queued commands and {} held events.",
+      self.paused_commands.len(),
+      self.paused_events.len()
+    );
+    self.throttled_commands.extend(self.paused_commands.drain(..));
+    self.send_throttled_commands().await;
+    while let Some(event) = self.paused_events.pop_front() {
+      self.send_client_event(event);
+    }
+  }
+
+  /// Sends as many throttled device commands as the throttle currently
+  /// allows. The rest are sent as the loop wakes up for them.
+  async fn send_throttled_commands(&mut self) {
+    while self.command_throttle.delay() == Duration::default() {
+      let msg_fut = match self.throttled_commands.pop_front() {
+        Some(msg_fut) => msg_fut,
+        None => break,
+      };
+      self.command_throttle.mark_sent();
+      self.send_message(msg_fut).await;
+    }
+  }
+
+  /// Ends a pause because the loop is exiting. Queued device commands can no
+  /// longer be sent, so they fail, but held events are still emitted so the
+  /// client sees everything that happened before the disconnect.
+  fn abandon_pause(&mut self) {
+    self.paused = None;
+    // Throttled commands can't be sent anymore either.
+    self.paused_commands.extend(self.throttled_commands.drain(..));
+    while let Some(msg_fut) = self.paused_commands.pop_front() {
+      msg_fut.waker.set_reply(Err(
+        ButtplugConnectorError::ConnectorNotConnected.into(),
+      ));
+    }
+    while let Some(event) = self.paused_events.pop_front() {
+      self.send_client_event(event);
+    }
   }
 
   /// Parses message types from the client, returning false when disconnect
@@ -282,6 +872,10 @@ where
         self.send_message(msg_fut).await;
         true
       }
+      ButtplugClientRequest::DeviceMessage(msg_fut) => {
+        self.send_device_message(msg_fut).await;
+        true
+      }
       ButtplugClientRequest::Disconnect(state) => {
         trace!("Client requested disconnect");
         state.set_reply(self.connector.disconnect().await);
@@ -290,14 +884,26 @@ where
       ButtplugClientRequest::HandleDeviceList(device_list) => {
         trace!("Device list received, updating map.");
         for d in device_list.devices() {
-          if self.device_map.contains_key(&d.device_index) {
+          if self.device_map.contains_key(&DeviceIndex(d.device_index)) {
             continue;
           }
-          let device = self.create_client_device(d);
-          self.send_client_event(ButtplugClientEvent::DeviceAdded(device));
+          if self.device_limit_reached() {
+            self.reject_device(d.clone()).await;
+            continue;
+          }
+          self.add_client_device(d);
         }
         true
       }
+      ButtplugClientRequest::Pause(policy) => {
+        info!("Client requested pause, paused command policy is {:?}.", policy);
+        self.paused = Some(policy);
+        true
+      }
+      ButtplugClientRequest::Resume => {
+        self.resume().await;
+        true
+      }
     }
   }
 
@@ -305,26 +911,60 @@ where
   pub async fn run(&mut self) {
     debug!("Running client event loop.");
     loop {
+      *self.heartbeat.lock().unwrap() = Instant::now();
+      let heartbeat_interval = self.heartbeat_interval;
+      let heartbeat_tick = async move {
+        match heartbeat_interval {
+          Some(interval) => Delay::new(interval).await,
+          None => future::pending::<()>().await,
+        }
+      };
+      // Wake up when the throttle lets the next waiting command go out.
+      let throttle_delay = if self.throttled_commands.is_empty() {
+        None
+      } else {
+        Some(self.command_throttle.delay())
+      };
+      let throttle_tick = async move {
+        match throttle_delay {
+          Some(delay) => Delay::new(delay).await,
+          None => future::pending::<()>().await,
+        }
+      };
       select! {
+        _ = heartbeat_tick.fuse() => {},
+        _ = throttle_tick.fuse() => self.send_throttled_commands().await,
         event = self.from_connector_receiver.recv().fuse() => match event {
           None => {
             info!("Connector disconnected, exiting loop.");
+            self.abandon_pause();
+            self.connected_status.store(false, Ordering::SeqCst);
+            self.device_map.iter().for_each(|val| val.value().set_client_connected(false));
             self.send_client_event(ButtplugClientEvent::ServerDisconnect);
             return;
           }
           Some(msg) => {
-            self.parse_connector_message(msg).await;
+            if !self.parse_connector_message(msg).await {
+              break;
+            }
           }
         },
-        client = self.from_client_receiver.recv().fuse() => match client {
-          Err(_) => {
-            info!("Client disconnected, exiting loop.");
-            self.connected_status.store(false, Ordering::SeqCst);
-            self.device_map.iter().for_each(|val| val.value().set_client_connected(false));
-            self.send_client_event(ButtplugClientEvent::ServerDisconnect);
-            return;
-          }
-          Ok(msg) => {
+        _ = self.reply_cancel_receiver.recv().fuse() => {
+          // We hold a sender for this channel too, so it never closes.
+          self.remove_abandoned_futures();
+        },
+        _ = self.client_alive_receiver.recv().fuse() => {
+          info!("Client disconnected, exiting loop.");
+          self.abandon_pause();
+          self.connected_status.store(false, Ordering::SeqCst);
+          self.device_map.iter().for_each(|val| val.value().set_client_connected(false));
+          self.send_client_event(ButtplugClientEvent::ServerDisconnect);
+          return;
+        },
+        request = self.request_receiver.recv().fuse() => {
+          // We hold a sender for this channel, so it will never close while
+          // the loop is running.
+          if let Some(msg) = request {
             if !self.parse_client_request(msg).await {
               break;
             }
@@ -333,7 +973,8 @@ where
       };
     }
 
-    let device_indexes: Vec<u32> = self.device_map.iter().map(|k| *k.key()).collect();
+    self.abandon_pause();
+    let device_indexes: Vec<DeviceIndex> = self.device_map.iter().map(|k| *k.key()).collect();
     device_indexes
       .iter()
       .for_each(|k| self.disconnect_device(*k));
@@ -9,12 +9,19 @@
 
 use crate::{
   client::{
-    ButtplugClientError, ButtplugClientMessageFuturePair, ButtplugServerMessageStateShared,
+    ButtplugClientError, ButtplugClientMessageFuturePair, ButtplugServerMessageFuture,
+    ButtplugServerMessageResult, ButtplugServerMessageStateShared,
   },
   core::messages::{ButtplugCurrentSpecServerMessage, ButtplugMessage, ButtplugMessageValidator},
 };
+use core::pin::Pin;
 use dashmap::DashMap;
-use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+use futures::{
+  task::{Context, Poll},
+  Future,
+};
+use std::sync::{Arc, atomic::{AtomicU32, AtomicUsize, Ordering}};
+use tokio::sync::mpsc;
 
 /// Message sorting and pairing for remote client connectors.
 ///
@@ -69,6 +76,10 @@ pub struct ClientMessageSorter {
   /// `id`. We assume that unsigned 2^32 will be enough (Buttplug isn't THAT
   /// chatty), and use it as a monotonically increasing counter for setting `id`s.
   current_id: Arc<AtomicU32>,
+
+  /// Number of entries in the future_map, kept up to date so it can be read
+  /// from outside the event loop without touching the map.
+  pending_request_count: Arc<AtomicUsize>,
 }
 
 impl ClientMessageSorter {
@@ -84,6 +95,19 @@ impl ClientMessageSorter {
       .future_map
       .insert(id, msg_fut.waker.clone());
     self.current_id.store(id + 1, Ordering::SeqCst);
+    self.update_pending_request_count();
+  }
+
+  /// Returns a handle to the number of requests still waiting on a reply from
+  /// the server. A count that keeps growing means replies are backing up.
+  pub fn pending_request_count(&self) -> Arc<AtomicUsize> {
+    self.pending_request_count.clone()
+  }
+
+  fn update_pending_request_count(&self) {
+    self
+      .pending_request_count
+      .store(self.future_map.len(), Ordering::SeqCst);
   }
 
   /// Given a response message from the server, resolve related future if we
@@ -98,6 +122,7 @@ impl ClientMessageSorter {
     match self.future_map.remove(&id) {
       Some((_, state)) => {
         trace!("Resolved id {} to a future.", id);
+        self.update_pending_request_count();
         if let Err(e) = msg.is_valid() {
           error!("Message not valid: {:?} - Error: {}", msg, e);
           state.set_reply(Err(ButtplugClientError::ButtplugError(e.into())));
@@ -114,6 +139,83 @@ impl ClientMessageSorter {
       }
     }
   }
+
+  /// Drops reply state for futures nobody is waiting on anymore.
+  ///
+  /// Run by the event loop when it's told a [ClientMessageReplyFuture] was
+  /// dropped before its reply came in. If the reply does eventually show up,
+  /// it'll be treated as an event.
+  pub fn remove_abandoned_futures(&self) {
+    self.future_map.retain(|id, state| {
+      if state.is_abandoned() {
+        debug!("Future for message id {} was dropped, removing.", id);
+        false
+      } else {
+        true
+      }
+    });
+    self.update_pending_request_count();
+  }
+
+  #[cfg(test)]
+  fn pending_count(&self) -> usize {
+    self.future_map.len()
+  }
+}
+
+/// Future handed back to callers waiting on a reply from the server.
+///
+/// If it's dropped before the reply arrives (i.e. the caller used it in a
+/// `select!` with a timeout), it signals the event loop, which drops the reply
+/// state from the [ClientMessageSorter] instead of holding on to it until the
+/// server replies, which may be never.
+pub(super) struct ClientMessageReplyFuture {
+  fut: Option<ButtplugServerMessageFuture>,
+  cancel_sender: Option<mpsc::UnboundedSender<()>>,
+}
+
+impl ClientMessageReplyFuture {
+  pub fn new(
+    fut: ButtplugServerMessageFuture,
+    cancel_sender: Option<mpsc::UnboundedSender<()>>,
+  ) -> Self {
+    Self {
+      fut: Some(fut),
+      cancel_sender,
+    }
+  }
+}
+
+impl Future for ClientMessageReplyFuture {
+  type Output = ButtplugServerMessageResult;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+    let poll = Pin::new(
+      self
+        .fut
+        .as_mut()
+        .expect("Reply future only taken on drop."),
+    )
+    .poll(cx);
+    if poll.is_ready() {
+      // Reply is in, nothing left to clean up.
+      self.cancel_sender = None;
+    }
+    poll
+  }
+}
+
+impl Drop for ClientMessageReplyFuture {
+  fn drop(&mut self) {
+    if let Some(sender) = self.cancel_sender.take() {
+      // Let go of the reply state before signalling, so the sorter sees it as
+      // abandoned.
+      self.fut.take();
+      // If the event loop is gone, so is the sorter, so there's nothing to
+      // clean up.
+      let _ = sender.send(());
+    }
+  }
 }
 
 impl Default for ClientMessageSorter {
@@ -125,6 +227,41 @@ impl Default for ClientMessageSorter {
     Self {
       future_map: DashMap::new(),
       current_id: Arc::new(AtomicU32::new(1)),
+      pending_request_count: Arc::new(AtomicUsize::new(0)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::core::messages::Ping;
+
+  #[test]
+  fn test_dropped_reply_future_removed_from_sorter() {
+    let sorter = ClientMessageSorter::default();
+    let (cancel_sender, mut cancel_receiver) = mpsc::unbounded_channel();
+    let mut reply_futs = vec![];
+    for _ in 0..2 {
+      let fut = ButtplugServerMessageFuture::default();
+      let mut msg_fut =
+        ButtplugClientMessageFuturePair::new(Ping::default().into(), fut.get_state_clone());
+      sorter.register_future(&mut msg_fut);
+      reply_futs.push(ClientMessageReplyFuture::new(
+        fut,
+        Some(cancel_sender.clone()),
+      ));
     }
+    assert_eq!(sorter.pending_count(), 2);
+    assert_eq!(sorter.pending_request_count().load(Ordering::SeqCst), 2);
+    // Act like the caller gave up on the second message.
+    reply_futs.pop();
+    assert!(cancel_receiver.try_recv().is_ok());
+    sorter.remove_abandoned_futures();
+    assert_eq!(sorter.pending_count(), 1);
+    assert_eq!(sorter.pending_request_count().load(Ordering::SeqCst), 1);
+    // The future that's still around keeps its place.
+    sorter.remove_abandoned_futures();
+    assert_eq!(sorter.pending_count(), 1);
   }
 }
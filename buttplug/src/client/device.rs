@@ -7,7 +7,10 @@
 
 //! Representation and management of devices connected to the server.
 
-use super::{ButtplugClientError, ButtplugClientRequest, ButtplugClientResultFuture};
+use super::{
+  client_event_loop::ButtplugClientRequest, client_message_sorter::ClientMessageReplyFuture,
+  ButtplugClientError, ButtplugClientResult, ButtplugClientResultFuture,
+};
 use crate::{
   client::{ButtplugClientMessageFuturePair, ButtplugServerMessageFuture},
   connector::ButtplugConnectorError,
@@ -16,25 +19,31 @@ use crate::{
     messages::{
       BatteryLevelCmd, ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecDeviceMessageType,
       ButtplugCurrentSpecServerMessage, ButtplugMessage, DeviceMessageAttributes,
-      DeviceMessageAttributesMap, DeviceMessageInfo, LinearCmd, RSSILevelCmd, RawReadCmd,
+      DeviceMessageAttributesMap, DeviceMessageInfo, LinearCmd, OscillateCmd,
+      OscillateSubcommand, RSSILevelCmd, RawReadCmd,
       RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd, RotateCmd, RotationSubcommand,
       StopDeviceCmd, VectorSubcommand, VibrateCmd, VibrateSubcommand,
     },
   },
   device::Endpoint,
-  util::stream::convert_broadcast_receiver_to_stream,
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
 };
-use futures::{future, Stream};
+use dashmap::DashMap;
+use futures::{future, FutureExt, Stream, StreamExt};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
 use std::{
-  collections::HashMap,
+  collections::{BTreeMap, HashMap},
   convert::TryFrom,
   fmt,
   sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex, Weak,
   },
+  time::{Duration, Instant},
 };
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio_util::sync::CancellationToken;
 use tracing_futures::Instrument;
 
 /// Enum for messages going to a [ButtplugClientDevice] instance.
@@ -42,6 +51,9 @@ use tracing_futures::Instrument;
 pub enum ButtplugClientDeviceEvent {
   /// Device has disconnected from server.
   DeviceRemoved,
+  /// Device has reconnected to the server after being removed, and this
+  /// instance can be used to control it again.
+  DeviceReconnected,
   /// Client has disconnected from server.
   ClientDisconnect,
   /// Message was received from server for that specific device.
@@ -65,6 +77,23 @@ pub enum VibrateCommand {
   SpeedMap(HashMap<u32, f64>),
 }
 
+/// Convenience enum for forming [OscillateCmd] commands.
+///
+/// Allows users to easily specify speeds across different oscillation features
+/// in a device. Units are in absolute speed values (0.0-1.0).
+pub enum OscillateCommand {
+  /// Sets all oscillation features of a device to the same speed.
+  Speed(f64),
+  /// Sets oscillation features to speed based on the index of the speed in the
+  /// vec (i.e. oscillator 0 is set to `SpeedVec[0]`, oscillator 1 is set to
+  /// `SpeedVec[1]`, etc...)
+  SpeedVec(Vec<f64>),
+  /// Sets oscillation features indicated by index to requested speed. For
+  /// instance, if the map has an entry of (1, 0.5), it will set oscillator 1 to
+  /// a speed of 0.5.
+  SpeedMap(HashMap<u32, f64>),
+}
+
 /// Convenience enum for forming [RotateCmd] commands.
 ///
 /// Allows users to easily specify speeds/directions across different rotation
@@ -101,6 +130,87 @@ pub enum LinearCommand {
   LinearMap(HashMap<u32, (u32, f64)>),
 }
 
+/// Sensors that can be subscribed to via
+/// [ButtplugClientDevice::subscribe_sensor].
+///
+/// In the current version of the protocol, the only subscribable sensor data
+/// is raw endpoint notifications, so this requires the server to allow raw
+/// messages. Sensors with a standard data format are decoded on the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ButtplugClientDeviceSensorType {
+  /// Notifications from a device endpoint.
+  Raw(Endpoint),
+  /// Battery level notifications from the standard BLE Battery Level
+  /// characteristic ([Endpoint::RxBLEBattery]).
+  Battery,
+}
+
+/// Readings emitted by a sensor subscription stream.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ButtplugClientDeviceSensorReading {
+  /// Notification data from a device endpoint.
+  Raw(Endpoint, Vec<u8>),
+  /// Battery level, from 0.0 (empty) to 1.0 (full).
+  Battery(f64),
+}
+
+impl ButtplugClientDeviceSensorType {
+  /// Endpoint the sensor's notifications arrive on.
+  fn endpoint(&self) -> Endpoint {
+    match self {
+      Self::Raw(endpoint) => *endpoint,
+      Self::Battery => Endpoint::RxBLEBattery,
+    }
+  }
+
+  /// Decodes a message from the server into a reading for this sensor type,
+  /// if it belongs to this sensor.
+  fn decode(
+    &self,
+    msg: &ButtplugCurrentSpecServerMessage,
+  ) -> Option<ButtplugClientDeviceSensorReading> {
+    match (self, msg) {
+      (Self::Raw(endpoint), ButtplugCurrentSpecServerMessage::RawReading(reading))
+        if reading.endpoint() == *endpoint =>
+      {
+        Some(ButtplugClientDeviceSensorReading::Raw(
+          *endpoint,
+          reading.data().clone(),
+        ))
+      }
+      // Battery Level is a single byte percentage, per the BLE Battery
+      // Service spec.
+      (Self::Battery, ButtplugCurrentSpecServerMessage::RawReading(reading))
+        if reading.endpoint() == Endpoint::RxBLEBattery =>
+      {
+        reading
+          .data()
+          .first()
+          .map(|level| ButtplugClientDeviceSensorReading::Battery((*level).min(100) as f64 / 100.0))
+      }
+      _ => None,
+    }
+  }
+}
+
+// Raw messages have to be allowed by the server. Same early return trick as
+// check_message_support.
+macro_rules! check_raw_message_support {
+  ($self:ident) => {
+    if !$self.raw_messages_allowed() {
+      return $self.create_boxed_future_client_error(
+        ButtplugDeviceError::DevicePermissionError(format!(
+          "Raw messages not allowed for device {}, server must allow raw messages.",
+          $self.name
+        ))
+        .into(),
+      );
+    }
+  };
+}
+
 // Using a macro here so we can encabe the return statement. Otherwise we'd have
 // to do validity checks on every call since we return futures, not results.
 macro_rules! check_message_support {
@@ -113,6 +223,51 @@ macro_rules! check_message_support {
   };
 }
 
+// Rejects NaN, infinite, and out of range values before we build a message.
+// Protocols assume values are already in [0.0, 1.0] when converting to device
+// steps, so anything else would turn into garbage bytes on the wire.
+fn check_command_values(values: impl Iterator<Item = (u32, f64)>) -> Result<(), ButtplugError> {
+  for (index, value) in values {
+    if !(0.0..=1.0).contains(&value) {
+      return Err(ButtplugDeviceError::DeviceCommandValueError(index, value).into());
+    }
+  }
+  Ok(())
+}
+
+/// Stepped values for a single command, stored as (feature index, step,
+/// clockwise) triples. Vibration and oscillation values always store clockwise
+/// as false.
+type DedupCommandValues = Vec<(u32, u64, bool)>;
+
+/// Storage for "only send on change" mode on a [ButtplugClientDevice].
+///
+/// Keeps the last values we sent for each command type, so that we can skip
+/// sending commands that wouldn't change anything on the device.
+#[derive(Default)]
+struct CommandDedupState {
+  enabled: bool,
+  /// If set, resend a duplicate command once this much time has passed since
+  /// the last time it was sent, for servers/devices that require periodic
+  /// commands.
+  keepalive: Option<Duration>,
+  last_sent: HashMap<ButtplugCurrentSpecDeviceMessageType, (DedupCommandValues, Option<Instant>)>,
+}
+
+/// Handle to a running command watchdog task, as set up by
+/// [ButtplugClientDevice::command_watchdog]. Cancels the task when dropped.
+struct CommandWatchdog {
+  /// Notified whenever an output command is sent, to rearm the timer.
+  feed: Arc<Notify>,
+  cancel_token: CancellationToken,
+}
+
+impl Drop for CommandWatchdog {
+  fn drop(&mut self) {
+    self.cancel_token.cancel();
+  }
+}
+
 pub type ButtplugClientDeviceMessageType = ButtplugCurrentSpecDeviceMessageType;
 pub type ClientDeviceMessageAttributesMap =
   HashMap<ButtplugCurrentSpecDeviceMessageType, DeviceMessageAttributes>;
@@ -129,6 +284,82 @@ fn convert_to_client_device_map(
   current_map
 }
 
+/// Point in time copy of what a client knows about a device, as returned by
+/// [ButtplugClientDevice::snapshot].
+///
+/// Serializable, so applications can dump it (i.e. as JSON) when reporting
+/// device issues.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ButtplugClientDeviceSnapshot {
+  /// Index of the device on the server.
+  pub index: u32,
+  /// Name of the device, as reported by the server.
+  pub name: String,
+  /// Serial number of the device, if [ButtplugClientDevice::device_info] has
+  /// read one. The server doesn't send protocol identifiers to clients, so
+  /// this is the only way to tell apart devices with the same name.
+  pub identifier: Option<String>,
+  /// Whether the device was connected when the snapshot was taken.
+  pub connected: bool,
+  /// Messages the device supports, along with their feature attributes
+  /// (feature counts, step counts, etc...).
+  pub messages: ClientDeviceMessageAttributesMap,
+}
+
+/// Device details read from the standard BLE Device Information Service, as
+/// returned by [ButtplugClientDevice::device_info].
+///
+/// Fields are None if the device doesn't expose them, or they couldn't be
+/// read.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInformation {
+  pub manufacturer: Option<String>,
+  pub model: Option<String>,
+  pub serial_number: Option<String>,
+  pub firmware_revision: Option<String>,
+  pub hardware_revision: Option<String>,
+}
+
+/// Parses a Device Information Service string characteristic. These are
+/// UTF-8, but some firmware pads them with nulls or spaces.
+fn parse_device_information_string(data: &[u8]) -> Option<String> {
+  let value = String::from_utf8_lossy(data)
+    .trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+    .to_owned();
+  if value.is_empty() {
+    None
+  } else {
+    Some(value)
+  }
+}
+
+/// Index the server assigned to a device.
+///
+/// Wraps the raw `u32` used on the wire, so server device indexes can't be
+/// mixed up with other integers (i.e. positions in the list returned by
+/// [ButtplugClient::devices][super::ButtplugClient::devices]) when passed to
+/// client APIs. Converts to and from `u32` via [From]/[Into].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceIndex(pub u32);
+
+impl From<u32> for DeviceIndex {
+  fn from(index: u32) -> Self {
+    Self(index)
+  }
+}
+
+impl From<DeviceIndex> for u32 {
+  fn from(index: DeviceIndex) -> Self {
+    index.0
+  }
+}
+
+impl fmt::Display for DeviceIndex {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
 /// Client-usable representation of device connected to the corresponding
 /// [ButtplugServer][crate::server::ButtplugServer]
 ///
@@ -148,8 +379,13 @@ pub struct ButtplugClientDevice {
   /// Sends commands from the [ButtplugClientDevice] instance to the
   /// [ButtplugClient][super::ButtplugClient]'s event loop, which will then send
   /// the message on to the [ButtplugServer][crate::server::ButtplugServer]
-  /// through the connector.
-  event_loop_sender: broadcast::Sender<ButtplugClientRequest>,
+  /// through the connector. Shared with the client and all of its devices,
+  /// and unbounded, so commands are never dropped or reordered on the way to
+  /// the event loop.
+  event_loop_sender: mpsc::UnboundedSender<ButtplugClientRequest>,
+  /// Handed to reply futures so they can tell the event loop when they're
+  /// dropped early.
+  reply_cancel_sender: mpsc::UnboundedSender<()>,
   internal_event_sender: broadcast::Sender<ButtplugClientDeviceEvent>,
   /// True if this [ButtplugClientDevice] is currently connected to the
   /// [ButtplugServer][crate::server::ButtplugServer].
@@ -158,8 +394,32 @@ pub struct ButtplugClientDevice {
   /// [ButtplugClientDevice] instance is still connected to the
   /// [ButtplugServer][crate::server::ButtplugServer].
   client_connected: Arc<AtomicBool>,
+  /// Device map of the [ButtplugClient][super::ButtplugClient] that generated
+  /// this instance. Used to check that this instance hasn't been removed
+  /// before sending commands. Weak since the map holds the device.
+  device_map: Weak<DashMap<DeviceIndex, Arc<ButtplugClientDevice>>>,
+  /// Last sent command values, used for command deduplication if it is turned
+  /// on.
+  command_dedup: Arc<Mutex<CommandDedupState>>,
+  /// Auto-stop watchdog, if one has been set up.
+  command_watchdog: Mutex<Option<CommandWatchdog>>,
+  /// Time of the last event received for this device, either a message from
+  /// the server or a successful reply to a command.
+  last_seen: Arc<Mutex<Instant>>,
+  /// Serial number from the last successful
+  /// [ButtplugClientDevice::device_info] read, used to identify the device in
+  /// snapshots.
+  serial_number: Arc<Mutex<Option<String>>>,
+  /// Order in which this instance was added (or last reconnected) relative
+  /// to all other [ButtplugClientDevice] instances, used to list devices in
+  /// the order they were added.
+  add_sequence: AtomicU64,
 }
 
+/// Source for [ButtplugClientDevice] add sequence numbers. Shared across all
+/// clients, since we only care about relative order within a client.
+static NEXT_DEVICE_ADD_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
 unsafe impl Send for ButtplugClientDevice {}
 unsafe impl Sync for ButtplugClientDevice {}
 
@@ -181,7 +441,9 @@ impl ButtplugClientDevice {
     name: &str,
     index: u32,
     allowed_messages: ClientDeviceMessageAttributesMap,
-    message_sender: broadcast::Sender<ButtplugClientRequest>,
+    message_sender: mpsc::UnboundedSender<ButtplugClientRequest>,
+    reply_cancel_sender: mpsc::UnboundedSender<()>,
+    device_map: &Arc<DashMap<DeviceIndex, Arc<ButtplugClientDevice>>>,
   ) -> Self {
     info!(
       "Creating client device {} with index {} and messages {:?}.",
@@ -196,21 +458,32 @@ impl ButtplugClientDevice {
       index,
       allowed_messages,
       event_loop_sender: message_sender,
+      reply_cancel_sender,
       internal_event_sender: event_sender,
       device_connected,
       client_connected,
+      device_map: Arc::downgrade(device_map),
+      command_dedup: Arc::new(Mutex::new(CommandDedupState::default())),
+      command_watchdog: Mutex::new(None),
+      last_seen: Arc::new(Mutex::new(Instant::now())),
+      serial_number: Arc::new(Mutex::new(None)),
+      add_sequence: AtomicU64::new(NEXT_DEVICE_ADD_SEQUENCE.fetch_add(1, Ordering::SeqCst)),
     }
   }
 
   pub(super) fn new_from_device_info(
     info: &DeviceMessageInfo,
-    sender: broadcast::Sender<ButtplugClientRequest>,
+    sender: mpsc::UnboundedSender<ButtplugClientRequest>,
+    reply_cancel_sender: mpsc::UnboundedSender<()>,
+    device_map: &Arc<DashMap<DeviceIndex, Arc<ButtplugClientDevice>>>,
   ) -> Self {
     ButtplugClientDevice::new(
       &*info.device_name,
       info.device_index,
       convert_to_client_device_map(&info.device_messages),
       sender,
+      reply_cancel_sender,
+      device_map,
     )
   }
 
@@ -218,45 +491,319 @@ impl ButtplugClientDevice {
     self.device_connected.load(Ordering::SeqCst)
   }
 
+  /// Returns the time of the last event received for this device.
+  ///
+  /// Updated whenever the server sends a message for the device (sensor
+  /// readings, etc...) or successfully replies to a command sent to the
+  /// device. Starts out as the time the device was added to the client. Apps
+  /// can use this to implement their own staleness checks, separate from
+  /// whatever keepalive the transport may have.
+  pub fn last_seen(&self) -> Instant {
+    *self.last_seen.lock().unwrap()
+  }
+
+  pub(super) fn update_last_seen(&self) {
+    *self.last_seen.lock().unwrap() = Instant::now();
+  }
+
+  pub(super) fn add_sequence(&self) -> u64 {
+    self.add_sequence.load(Ordering::SeqCst)
+  }
+
+  /// Moves this instance to the end of the add order, for when it is reused
+  /// after the device reconnects.
+  pub(super) fn update_add_sequence(&self) {
+    self.add_sequence.store(
+      NEXT_DEVICE_ADD_SEQUENCE.fetch_add(1, Ordering::SeqCst),
+      Ordering::SeqCst,
+    );
+  }
+
+  /// Returns true if `info` describes the same device as this instance (same
+  /// index, name and messages). The server keeps device indexes stable across
+  /// reconnects, so this is how we recognize a device coming back.
+  pub(super) fn matches_device_info(&self, info: &DeviceMessageInfo) -> bool {
+    self.index == info.device_index
+      && self.name == info.device_name
+      && self.allowed_messages == convert_to_client_device_map(&info.device_messages)
+  }
+
+  /// Returns true if this instance is still the one held by the client for
+  /// its index. Once the device is removed (or the client reconnects and
+  /// creates new device instances), commands sent through this instance
+  /// would go nowhere useful.
+  fn in_device_map(&self) -> bool {
+    self.device_map.upgrade().map_or(false, |map| {
+      map
+        .get(&self.device_index())
+        .map_or(false, |dev| std::ptr::eq(dev.value().as_ref(), self))
+    })
+  }
+
+  /// Turns "only send on change" mode on or off.
+  ///
+  /// When on, [vibrate][ButtplugClientDevice::vibrate],
+  /// [oscillate][ButtplugClientDevice::oscillate] and
+  /// [rotate][ButtplugClientDevice::rotate] commands that would set the device
+  /// to the same values (after conversion to the step resolution of the device)
+  /// as the last command sent will resolve immediately without being sent to
+  /// the server. Off by default.
+  pub fn set_command_dedup(&self, enabled: bool) {
+    let mut dedup = self.command_dedup.lock().unwrap();
+    dedup.enabled = enabled;
+    dedup.last_sent.clear();
+  }
+
+  /// Returns true if "only send on change" mode is on.
+  pub fn command_dedup(&self) -> bool {
+    self.command_dedup.lock().unwrap().enabled
+  }
+
+  /// Sets how often a deduplicated command will still be resent, for servers
+  /// or devices that require periodic commands to keep running. If None (the
+  /// default), duplicate commands are never resent.
+  pub fn set_command_dedup_keepalive(&self, keepalive: Option<Duration>) {
+    self.command_dedup.lock().unwrap().keepalive = keepalive;
+  }
+
+  /// Sets up a watchdog that stops the device if no output command is sent to
+  /// it within `timeout`.
+  ///
+  /// The timer is armed by [vibrate][ButtplugClientDevice::vibrate],
+  /// [oscillate][ButtplugClientDevice::oscillate],
+  /// [linear][ButtplugClientDevice::linear] and
+  /// [rotate][ButtplugClientDevice::rotate] calls (including ones skipped by
+  /// command deduplication), and rearmed by every call after that. If it
+  /// elapses, a [StopDeviceCmd] is sent, and the watchdog waits for the next
+  /// command before arming again. Calling [stop][ButtplugClientDevice::stop]
+  /// does not arm the timer.
+  ///
+  /// Off by default. Passing None turns the watchdog off, as does dropping the
+  /// device. Replaces any previously set watchdog.
+  pub fn command_watchdog(&self, timeout: Option<Duration>) {
+    let mut watchdog = self.command_watchdog.lock().unwrap();
+    // Dropping the old watchdog cancels its task.
+    *watchdog = None;
+    let timeout = if let Some(timeout) = timeout {
+      timeout
+    } else {
+      return;
+    };
+    let feed = Arc::new(Notify::new());
+    let cancel_token = CancellationToken::new();
+    let task_feed = feed.clone();
+    let task_token = cancel_token.clone();
+    let event_loop_sender = self.event_loop_sender.clone();
+    let device_connected = self.device_connected.clone();
+    let command_dedup = self.command_dedup.clone();
+    let index = self.index;
+    let name = self.name.clone();
+    async_manager::spawn(
+      async move {
+        loop {
+          // Wait for a command to arm the timer.
+          select! {
+            _ = task_token.cancelled().fuse() => return,
+            _ = task_feed.notified().fuse() => {},
+          };
+          // Keep rearming as long as commands come in before the timeout.
+          loop {
+            select! {
+              _ = task_token.cancelled().fuse() => return,
+              _ = task_feed.notified().fuse() => continue,
+              _ = Delay::new(timeout).fuse() => break,
+            };
+          }
+          // The device may reconnect and reuse this instance, so keep the
+          // watchdog around, just don't bother sending anything.
+          if !device_connected.load(Ordering::SeqCst) {
+            debug!("Device {} disconnected, skipping command watchdog stop.", name);
+            continue;
+          }
+          warn!(
+            "No command sent to device {} in {:?}, stopping device.",
+            name, timeout
+          );
+          command_dedup.lock().unwrap().last_sent.clear();
+          let fut = ButtplugServerMessageFuture::default();
+          if event_loop_sender
+            .send(ButtplugClientRequest::DeviceMessage(
+              ButtplugClientMessageFuturePair::new(
+                StopDeviceCmd::new(index).into(),
+                fut.get_state_clone(),
+              ),
+            ))
+            .is_err()
+          {
+            debug!("Client event loop closed, stopping command watchdog.");
+            return;
+          }
+          // The event loop drops commands whose reply futures are gone, so
+          // hold on to this one until the stop goes through. Waiting on it
+          // in its own task keeps a slow reply from holding up the timer.
+          let stop_name = name.clone();
+          async_manager::spawn(async move {
+            if let Err(err) = fut.await {
+              warn!(
+                "Command watchdog stop for device {} failed: {:?}",
+                stop_name, err
+              );
+            }
+          })
+          .unwrap();
+        }
+      }
+      .instrument(tracing::info_span!("Client Device Command Watchdog")),
+    )
+    .unwrap();
+    *watchdog = Some(CommandWatchdog { feed, cancel_token });
+  }
+
+  /// Rearms the command watchdog, if there is one.
+  fn feed_command_watchdog(&self) {
+    if let Some(watchdog) = self.command_watchdog.lock().unwrap().as_ref() {
+      watchdog.feed.notify_one();
+    }
+  }
+
+  /// Converts a command speed to the step resolution of the device, if we
+  /// know it, so that values that map to the same step compare as equal.
+  fn dedup_step_value(
+    &self,
+    msg_type: ButtplugCurrentSpecDeviceMessageType,
+    index: u32,
+    speed: f64,
+  ) -> u64 {
+    let step_count = self
+      .allowed_messages
+      .get(&msg_type)
+      .and_then(|attrs| attrs.step_count.as_ref())
+      .and_then(|steps| steps.get(index as usize));
+    match step_count {
+      // Round up, same as the server does when converting to steps.
+      Some(steps) => (speed * *steps as f64).ceil() as u64,
+      None => speed.to_bits(),
+    }
+  }
+
+  /// Records the values for a command, returning false if dedup is on and the
+  /// command doesn't need to be sent.
+  fn dedup_check(
+    &self,
+    msg_type: ButtplugCurrentSpecDeviceMessageType,
+    values: DedupCommandValues,
+  ) -> bool {
+    let mut dedup = self.command_dedup.lock().unwrap();
+    if !dedup.enabled {
+      return true;
+    }
+    let keepalive = dedup.keepalive;
+    let now = keepalive.map(|_| Instant::now());
+    if let Some((last_values, last_time)) = dedup.last_sent.get(&msg_type) {
+      let keepalive_expired = match (keepalive, now) {
+        (Some(keepalive), Some(now)) => {
+          last_time.map_or(true, |last_time| now.duration_since(last_time) >= keepalive)
+        }
+        _ => false,
+      };
+      // Commands may only address some features, so only compare the ones
+      // being set.
+      let unchanged = values.iter().all(|value| last_values.contains(value));
+      if unchanged && !keepalive_expired {
+        trace!(
+          "Device {} command {:?} unchanged, not sending.",
+          self.index,
+          msg_type
+        );
+        return false;
+      }
+    }
+    let entry = dedup
+      .last_sent
+      .entry(msg_type)
+      .or_insert_with(|| (vec![], None));
+    for value in values {
+      entry.0.retain(|last| last.0 != value.0);
+      entry.0.push(value);
+    }
+    entry.0.sort_unstable_by_key(|v| v.0);
+    entry.1 = now;
+    true
+  }
+
+  /// Sends a deduplicated command. If the command fails, we forget what we
+  /// last sent, so that the next command will always go through.
+  fn send_dedup_message_expect_ok(
+    &self,
+    msg_type: ButtplugCurrentSpecDeviceMessageType,
+    msg: ButtplugCurrentSpecClientMessage,
+  ) -> ButtplugClientResultFuture {
+    let send_fut = self.send_message_expect_ok(msg);
+    let command_dedup = self.command_dedup.clone();
+    Box::pin(async move {
+      let result = send_fut.await;
+      if result.is_err() {
+        command_dedup.lock().unwrap().last_sent.remove(&msg_type);
+      }
+      result
+    })
+  }
+
   /// Sends a message through the owning
   /// [ButtplugClient][super::ButtplugClient].
   ///
   /// Performs the send/receive flow for send a device command and receiving the
   /// response from the server.
+  ///
+  /// # Ordering
+  ///
+  /// The message is queued to the event loop when this is called, not when the
+  /// returned future is first polled. Device commands go through a dedicated
+  /// ordered channel to the event loop, so commands for a device reach the
+  /// server in the order the command methods were called, regardless of the
+  /// order their futures are awaited in.
   fn send_message(
     &self,
     msg: ButtplugCurrentSpecClientMessage,
   ) -> ButtplugClientResultFuture<ButtplugCurrentSpecServerMessage> {
-    let message_sender = self.event_loop_sender.clone();
-    let client_connected = self.client_connected.clone();
-    let device_connected = self.device_connected.clone();
+    if !self.client_connected.load(Ordering::SeqCst) {
+      error!("Client not connected, cannot run device command");
+      return Box::pin(future::ready(Err(ButtplugClientError::from(
+        ButtplugConnectorError::ConnectorNotConnected,
+      ))));
+    } else if !self.device_connected.load(Ordering::SeqCst) || !self.in_device_map() {
+      // Fail fast if we've been removed, instead of sending the message to the
+      // server and waiting for an error back.
+      error!(
+        "Device {} is no longer available, cannot run device command",
+        self.index
+      );
+      return self.create_boxed_future_client_error(
+        ButtplugDeviceError::DeviceNotConnected(self.name.clone()).into(),
+      );
+    }
     let id = msg.id();
-    let device_name = self.name.clone();
+    let fut = ButtplugServerMessageFuture::default();
+    if self
+      .event_loop_sender
+      .send(ButtplugClientRequest::DeviceMessage(
+        ButtplugClientMessageFuturePair::new(msg, fut.get_state_clone()),
+      ))
+      .is_err()
+    {
+      return Box::pin(future::ready(Err(ButtplugClientError::from(
+        ButtplugConnectorError::ConnectorChannelClosed,
+      ))));
+    }
+    let fut = ClientMessageReplyFuture::new(fut, Some(self.reply_cancel_sender.clone()));
+    let last_seen = self.last_seen.clone();
     Box::pin(
       async move {
-        if !client_connected.load(Ordering::SeqCst) {
-          error!("Client not connected, cannot run device command");
-          return Err(ButtplugConnectorError::ConnectorNotConnected.into());
-        } else if !device_connected.load(Ordering::SeqCst) {
-          error!("Device not connected, cannot run device command");
-          return Err(
-            ButtplugError::from(ButtplugDeviceError::DeviceNotConnected(device_name)).into(),
-          );
-        }
-        let fut = ButtplugServerMessageFuture::default();
-        message_sender
-          .send(ButtplugClientRequest::Message(
-            ButtplugClientMessageFuturePair::new(msg.clone(), fut.get_state_clone()),
-          ))
-          .map_err(|_| {
-            ButtplugClientError::ButtplugConnectorError(
-              ButtplugConnectorError::ConnectorChannelClosed,
-            )
-          })?;
         let msg = fut.await?;
         if let ButtplugCurrentSpecServerMessage::Error(_err) = msg {
           Err(ButtplugError::from(_err).into())
         } else {
+          *last_seen.lock().unwrap() = Instant::now();
           Ok(msg)
         }
       }
@@ -299,18 +846,36 @@ impl ButtplugClientDevice {
     })
   }
 
-  /// Commands device to vibrate, assuming it has the features to do so.
-  pub fn vibrate(&self, speed_cmd: VibrateCommand) -> ButtplugClientResultFuture {
-    check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::VibrateCmd);
-    let mut vibrator_count: u32 = 0;
-    if let Some(features) = self
-      .allowed_messages
-      .get(&ButtplugCurrentSpecDeviceMessageType::VibrateCmd)
-    {
-      if let Some(v) = features.feature_count {
-        vibrator_count = v;
-      }
+  /// Checks that the device supports a message type.
+  fn check_message_supported(
+    &self,
+    msg_type: ButtplugCurrentSpecDeviceMessageType,
+  ) -> Result<(), ButtplugError> {
+    if self.allowed_messages.contains_key(&msg_type) {
+      Ok(())
+    } else {
+      Err(ButtplugDeviceError::MessageNotSupported(msg_type.into()).into())
     }
+  }
+
+  /// Returns the feature count the device reports for a message type, or 0 if
+  /// it doesn't report one.
+  fn feature_count(&self, msg_type: ButtplugCurrentSpecDeviceMessageType) -> u32 {
+    self
+      .allowed_messages
+      .get(&msg_type)
+      .and_then(|attributes| attributes.feature_count)
+      .unwrap_or(0)
+  }
+
+  /// Validates a [VibrateCommand] against the device's attributes, turning it
+  /// into subcommands.
+  fn vibrate_subcommands(
+    &self,
+    speed_cmd: VibrateCommand,
+  ) -> Result<Vec<VibrateSubcommand>, ButtplugError> {
+    self.check_message_supported(ButtplugCurrentSpecDeviceMessageType::VibrateCmd)?;
+    let vibrator_count = self.feature_count(ButtplugCurrentSpecDeviceMessageType::VibrateCmd);
     let mut speed_vec: Vec<VibrateSubcommand>;
     match speed_cmd {
       VibrateCommand::Speed(speed) => {
@@ -321,7 +886,7 @@ impl ButtplugClientDevice {
       }
       VibrateCommand::SpeedMap(map) => {
         if map.len() as u32 > vibrator_count {
-          return self.create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(vibrator_count, map.len() as u32)
               .into(),
           );
@@ -329,16 +894,14 @@ impl ButtplugClientDevice {
         speed_vec = Vec::with_capacity(map.len() as usize);
         for (idx, speed) in map {
           if idx > vibrator_count - 1 {
-            return self.create_boxed_future_client_error(
-              ButtplugDeviceError::DeviceFeatureIndexError(vibrator_count, idx).into(),
-            );
+            return Err(ButtplugDeviceError::DeviceFeatureIndexError(vibrator_count, idx).into());
           }
           speed_vec.push(VibrateSubcommand::new(idx, speed));
         }
       }
       VibrateCommand::SpeedVec(vec) => {
         if vec.len() as u32 > vibrator_count {
-          return self.create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(vibrator_count, vec.len() as u32)
               .into(),
           );
@@ -349,22 +912,137 @@ impl ButtplugClientDevice {
         }
       }
     }
+    check_command_values(speed_vec.iter().map(|cmd| (cmd.index(), cmd.speed())))?;
+    Ok(speed_vec)
+  }
+
+  /// Sends already validated vibration subcommands.
+  fn send_vibrate_subcommands(&self, speed_vec: Vec<VibrateSubcommand>) -> ButtplugClientResultFuture {
+    let msg_type = ButtplugCurrentSpecDeviceMessageType::VibrateCmd;
+    let dedup_values = speed_vec
+      .iter()
+      .map(|cmd| {
+        (
+          cmd.index(),
+          self.dedup_step_value(msg_type, cmd.index(), cmd.speed()),
+          false,
+        )
+      })
+      .collect();
+    self.feed_command_watchdog();
+    if !self.dedup_check(msg_type, dedup_values) {
+      return Box::pin(future::ready(Ok(())));
+    }
     let msg = VibrateCmd::new(self.index, speed_vec).into();
-    self.send_message_expect_ok(msg)
+    self.send_dedup_message_expect_ok(msg_type, msg)
   }
 
-  /// Commands device to move linearly, assuming it has the features to do so.
-  pub fn linear(&self, linear_cmd: LinearCommand) -> ButtplugClientResultFuture {
-    check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::LinearCmd);
-    let mut linear_count: u32 = 0;
-    if let Some(features) = self
+  /// Commands device to vibrate, assuming it has the features to do so.
+  pub fn vibrate(&self, speed_cmd: VibrateCommand) -> ButtplugClientResultFuture {
+    match self.vibrate_subcommands(speed_cmd) {
+      Ok(speed_vec) => self.send_vibrate_subcommands(speed_vec),
+      Err(err) => self.create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Returns the attributes for oscillation features of the device, or None if
+  /// the device cannot oscillate.
+  pub fn oscillate_attributes(&self) -> Option<&DeviceMessageAttributes> {
+    self
       .allowed_messages
-      .get(&ButtplugCurrentSpecDeviceMessageType::LinearCmd)
-    {
-      if let Some(v) = features.feature_count {
-        linear_count = v;
+      .get(&ButtplugCurrentSpecDeviceMessageType::OscillateCmd)
+  }
+
+  /// Validates an [OscillateCommand] against the device's attributes, turning
+  /// it into subcommands.
+  fn oscillate_subcommands(
+    &self,
+    speed_cmd: OscillateCommand,
+  ) -> Result<Vec<OscillateSubcommand>, ButtplugError> {
+    self.check_message_supported(ButtplugCurrentSpecDeviceMessageType::OscillateCmd)?;
+    let oscillator_count = self.feature_count(ButtplugCurrentSpecDeviceMessageType::OscillateCmd);
+    let mut speed_vec: Vec<OscillateSubcommand>;
+    match speed_cmd {
+      OscillateCommand::Speed(speed) => {
+        speed_vec = Vec::with_capacity(oscillator_count as usize);
+        for i in 0..oscillator_count {
+          speed_vec.push(OscillateSubcommand::new(i, speed));
+        }
       }
+      OscillateCommand::SpeedMap(map) => {
+        if map.len() as u32 > oscillator_count {
+          return Err(
+            ButtplugDeviceError::DeviceFeatureCountMismatch(oscillator_count, map.len() as u32)
+              .into(),
+          );
+        }
+        speed_vec = Vec::with_capacity(map.len() as usize);
+        for (idx, speed) in map {
+          if idx > oscillator_count - 1 {
+            return Err(
+              ButtplugDeviceError::DeviceFeatureIndexError(oscillator_count, idx).into(),
+            );
+          }
+          speed_vec.push(OscillateSubcommand::new(idx, speed));
+        }
+      }
+      OscillateCommand::SpeedVec(vec) => {
+        if vec.len() as u32 > oscillator_count {
+          return Err(
+            ButtplugDeviceError::DeviceFeatureCountMismatch(oscillator_count, vec.len() as u32)
+              .into(),
+          );
+        }
+        speed_vec = Vec::with_capacity(vec.len() as usize);
+        for (i, v) in vec.iter().enumerate() {
+          speed_vec.push(OscillateSubcommand::new(i as u32, *v));
+        }
+      }
+    }
+    check_command_values(speed_vec.iter().map(|cmd| (cmd.index(), cmd.speed())))?;
+    Ok(speed_vec)
+  }
+
+  /// Sends already validated oscillation subcommands.
+  fn send_oscillate_subcommands(
+    &self,
+    speed_vec: Vec<OscillateSubcommand>,
+  ) -> ButtplugClientResultFuture {
+    let msg_type = ButtplugCurrentSpecDeviceMessageType::OscillateCmd;
+    let dedup_values = speed_vec
+      .iter()
+      .map(|cmd| {
+        (
+          cmd.index(),
+          self.dedup_step_value(msg_type, cmd.index(), cmd.speed()),
+          false,
+        )
+      })
+      .collect();
+    self.feed_command_watchdog();
+    if !self.dedup_check(msg_type, dedup_values) {
+      return Box::pin(future::ready(Ok(())));
+    }
+    let msg = OscillateCmd::new(self.index, speed_vec).into();
+    self.send_dedup_message_expect_ok(msg_type, msg)
+  }
+
+  /// Commands device to oscillate, assuming it has the features to do so.
+  pub fn oscillate(&self, speed_cmd: OscillateCommand) -> ButtplugClientResultFuture {
+    match self.oscillate_subcommands(speed_cmd) {
+      Ok(speed_vec) => self.send_oscillate_subcommands(speed_vec),
+      Err(err) => self.create_boxed_future_client_error(err),
     }
+  }
+
+  /// Validates a [LinearCommand] against the device's attributes, turning it
+  /// into subcommands.
+  fn linear_subcommands(
+    &self,
+    linear_cmd: LinearCommand,
+  ) -> Result<Vec<VectorSubcommand>, ButtplugError> {
+    self.check_message_supported(ButtplugCurrentSpecDeviceMessageType::LinearCmd)?;
+    let linear_count = self.feature_count(ButtplugCurrentSpecDeviceMessageType::LinearCmd);
     let mut linear_vec: Vec<VectorSubcommand>;
     match linear_cmd {
       LinearCommand::Linear(dur, pos) => {
@@ -375,23 +1053,21 @@ impl ButtplugClientDevice {
       }
       LinearCommand::LinearMap(map) => {
         if map.len() as u32 > linear_count {
-          return self.create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(linear_count, map.len() as u32).into(),
           );
         }
         linear_vec = Vec::with_capacity(map.len() as usize);
         for (idx, (dur, pos)) in map {
           if idx > linear_count - 1 {
-            return self.create_boxed_future_client_error(
-              ButtplugDeviceError::DeviceFeatureIndexError(linear_count, idx).into(),
-            );
+            return Err(ButtplugDeviceError::DeviceFeatureIndexError(linear_count, idx).into());
           }
           linear_vec.push(VectorSubcommand::new(idx, dur, pos));
         }
       }
       LinearCommand::LinearVec(vec) => {
         if vec.len() as u32 > linear_count {
-          return self.create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(linear_count, vec.len() as u32).into(),
           );
         }
@@ -401,22 +1077,41 @@ impl ButtplugClientDevice {
         }
       }
     }
+    check_command_values(linear_vec.iter().map(|cmd| (cmd.index(), *cmd.position())))?;
+    Ok(linear_vec)
+  }
+
+  /// Sends already validated linear subcommands.
+  fn send_linear_subcommands(&self, linear_vec: Vec<VectorSubcommand>) -> ButtplugClientResultFuture {
+    self.feed_command_watchdog();
     let msg = LinearCmd::new(self.index, linear_vec).into();
     self.send_message_expect_ok(msg)
   }
 
-  /// Commands device to rotate, assuming it has the features to do so.
-  pub fn rotate(&self, rotate_cmd: RotateCommand) -> ButtplugClientResultFuture {
-    check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::RotateCmd);
-    let mut rotate_count: u32 = 0;
-    if let Some(features) = self
+  /// Commands device to move linearly, assuming it has the features to do so.
+  pub fn linear(&self, linear_cmd: LinearCommand) -> ButtplugClientResultFuture {
+    match self.linear_subcommands(linear_cmd) {
+      Ok(linear_vec) => self.send_linear_subcommands(linear_vec),
+      Err(err) => self.create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Returns the attributes for rotation features of the device, or None if
+  /// the device cannot rotate.
+  pub fn rotate_attributes(&self) -> Option<&DeviceMessageAttributes> {
+    self
       .allowed_messages
       .get(&ButtplugCurrentSpecDeviceMessageType::RotateCmd)
-    {
-      if let Some(v) = features.feature_count {
-        rotate_count = v;
-      }
-    }
+  }
+
+  /// Validates a [RotateCommand] against the device's attributes, turning it
+  /// into subcommands.
+  fn rotate_subcommands(
+    &self,
+    rotate_cmd: RotateCommand,
+  ) -> Result<Vec<RotationSubcommand>, ButtplugError> {
+    self.check_message_supported(ButtplugCurrentSpecDeviceMessageType::RotateCmd)?;
+    let rotate_count = self.feature_count(ButtplugCurrentSpecDeviceMessageType::RotateCmd);
     let mut rotate_vec: Vec<RotationSubcommand>;
     match rotate_cmd {
       RotateCommand::Rotate(speed, clockwise) => {
@@ -427,23 +1122,21 @@ impl ButtplugClientDevice {
       }
       RotateCommand::RotateMap(map) => {
         if map.len() as u32 > rotate_count {
-          return self.create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(rotate_count, map.len() as u32).into(),
           );
         }
         rotate_vec = Vec::with_capacity(map.len() as usize);
         for (idx, (speed, clockwise)) in map {
-          if idx > rotate_count - 1 {
-            return self.create_boxed_future_client_error(
-              ButtplugDeviceError::DeviceFeatureIndexError(rotate_count, idx).into(),
-            );
+          if idx >= rotate_count {
+            return Err(ButtplugDeviceError::DeviceFeatureIndexError(rotate_count, idx).into());
           }
           rotate_vec.push(RotationSubcommand::new(idx, speed, clockwise));
         }
       }
       RotateCommand::RotateVec(vec) => {
         if vec.len() as u32 > rotate_count {
-          return self.create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(rotate_count, vec.len() as u32).into(),
           );
         }
@@ -453,8 +1146,46 @@ impl ButtplugClientDevice {
         }
       }
     }
+    check_command_values(rotate_vec.iter().map(|cmd| (cmd.index(), cmd.speed())))?;
+    Ok(rotate_vec)
+  }
+
+  /// Sends already validated rotation subcommands.
+  fn send_rotate_subcommands(&self, rotate_vec: Vec<RotationSubcommand>) -> ButtplugClientResultFuture {
+    let msg_type = ButtplugCurrentSpecDeviceMessageType::RotateCmd;
+    let dedup_values = rotate_vec
+      .iter()
+      .map(|cmd| {
+        (
+          cmd.index(),
+          self.dedup_step_value(msg_type, cmd.index(), cmd.speed()),
+          cmd.clockwise(),
+        )
+      })
+      .collect();
+    self.feed_command_watchdog();
+    if !self.dedup_check(msg_type, dedup_values) {
+      return Box::pin(future::ready(Ok(())));
+    }
     let msg = RotateCmd::new(self.index, rotate_vec).into();
-    self.send_message_expect_ok(msg)
+    self.send_dedup_message_expect_ok(msg_type, msg)
+  }
+
+  /// Commands device to rotate, assuming it has the features to do so.
+  ///
+  /// Each rotation feature can be given its own speed and direction using
+  /// [RotateCommand::RotateVec] or [RotateCommand::RotateMap].
+  pub fn rotate(&self, rotate_cmd: RotateCommand) -> ButtplugClientResultFuture {
+    match self.rotate_subcommands(rotate_cmd) {
+      Ok(rotate_vec) => self.send_rotate_subcommands(rotate_vec),
+      Err(err) => self.create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Starts building a set of output commands (vibrate, oscillate, rotate,
+  /// linear) to send to the device together. See [DeviceCommandBuilder].
+  pub fn command(&self) -> DeviceCommandBuilder<'_> {
+    DeviceCommandBuilder::new(self)
   }
 
   pub fn battery_level(&self) -> ButtplugClientResultFuture<f64> {
@@ -497,12 +1228,28 @@ impl ButtplugClientDevice {
     })
   }
 
+  /// Returns true if the server allows raw messages to be sent to this device.
+  ///
+  /// Raw message support is configured on the server, and is reported per
+  /// device via the raw message types in the device's message attributes.
+  pub fn raw_messages_allowed(&self) -> bool {
+    [
+      ButtplugCurrentSpecDeviceMessageType::RawWriteCmd,
+      ButtplugCurrentSpecDeviceMessageType::RawReadCmd,
+      ButtplugCurrentSpecDeviceMessageType::RawSubscribeCmd,
+      ButtplugCurrentSpecDeviceMessageType::RawUnsubscribeCmd,
+    ]
+    .iter()
+    .any(|msg_type| self.allowed_messages.contains_key(msg_type))
+  }
+
   pub fn raw_write(
     &self,
     endpoint: Endpoint,
     data: Vec<u8>,
     write_with_response: bool,
   ) -> ButtplugClientResultFuture {
+    check_raw_message_support!(self);
     check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::RawWriteCmd);
     let msg = ButtplugCurrentSpecClientMessage::RawWriteCmd(RawWriteCmd::new(
       self.index,
@@ -519,6 +1266,7 @@ impl ButtplugClientDevice {
     expected_length: u32,
     timeout: u32,
   ) -> ButtplugClientResultFuture<Vec<u8>> {
+    check_raw_message_support!(self);
     check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::RawReadCmd);
     let msg = ButtplugCurrentSpecClientMessage::RawReadCmd(RawReadCmd::new(
       self.index,
@@ -542,7 +1290,92 @@ impl ButtplugClientDevice {
     })
   }
 
+  /// Reads several endpoints at once, returning each endpoint's result in the
+  /// same order as `endpoints`.
+  ///
+  /// All reads are sent before waiting on any replies, so this takes about as
+  /// long as the slowest read, rather than the sum of all of them. A failed
+  /// read only fails its own entry.
+  pub fn raw_read_batch(
+    &self,
+    endpoints: &[Endpoint],
+    expected_length: u32,
+    timeout: u32,
+  ) -> ButtplugClientResultFuture<Vec<(Endpoint, ButtplugClientResult<Vec<u8>>)>> {
+    check_raw_message_support!(self);
+    check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::RawReadCmd);
+    let read_futs: Vec<_> = endpoints
+      .iter()
+      .map(|endpoint| {
+        let read_fut = self.raw_read(*endpoint, expected_length, timeout);
+        let endpoint = *endpoint;
+        async move { (endpoint, read_fut.await) }
+      })
+      .collect();
+    Box::pin(async move { Ok(future::join_all(read_futs).await) })
+  }
+
+  /// Reads the manufacturer, model, serial number and firmware and hardware
+  /// revisions of devices that expose them via the standard BLE Device
+  /// Information Service.
+  ///
+  /// The values are read in one [ButtplugClientDevice::raw_read_batch] call,
+  /// so the server must allow raw messages. See [DeviceInformation] for what
+  /// happens to values the device doesn't have.
+  pub fn device_info(&self) -> ButtplugClientResultFuture<DeviceInformation> {
+    check_raw_message_support!(self);
+    let available = self
+      .allowed_messages
+      .get(&ButtplugCurrentSpecDeviceMessageType::RawReadCmd)
+      .and_then(|attrs| attrs.endpoints.clone())
+      .unwrap_or_default();
+    let endpoints: Vec<Endpoint> = [
+      Endpoint::RxBLEManufacturer,
+      Endpoint::RxBLEModel,
+      Endpoint::RxBLESerialNumber,
+      Endpoint::RxBLEFirmwareRevision,
+      Endpoint::RxBLEHardwareRevision,
+    ]
+    .iter()
+    .filter(|endpoint| available.contains(*endpoint))
+    .cloned()
+    .collect();
+    if endpoints.is_empty() {
+      return Box::pin(future::ready(Ok(DeviceInformation::default())));
+    }
+    let batch_fut = self.raw_read_batch(&endpoints, 0, 0);
+    let serial_number = self.serial_number.clone();
+    Box::pin(async move {
+      let mut info = DeviceInformation::default();
+      for (endpoint, result) in batch_fut.await? {
+        let value = match result {
+          Ok(data) => parse_device_information_string(&data),
+          Err(err) => {
+            debug!(
+              "Cannot read device information from {}: {:?}",
+              endpoint, err
+            );
+            None
+          }
+        };
+        match endpoint {
+          Endpoint::RxBLEManufacturer => info.manufacturer = value,
+          Endpoint::RxBLEModel => info.model = value,
+          Endpoint::RxBLESerialNumber => info.serial_number = value,
+          Endpoint::RxBLEFirmwareRevision => info.firmware_revision = value,
+          Endpoint::RxBLEHardwareRevision => info.hardware_revision = value,
+          _ => {}
+        }
+      }
+      if info.serial_number.is_some() {
+        *serial_number.lock().unwrap() = info.serial_number.clone();
+      }
+      Ok(info)
+    })
+  }
+
   pub fn raw_subscribe(&self, endpoint: Endpoint) -> ButtplugClientResultFuture {
+    check_raw_message_support!(self);
     check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::RawSubscribeCmd);
     let msg =
       ButtplugCurrentSpecClientMessage::RawSubscribeCmd(RawSubscribeCmd::new(self.index, endpoint));
@@ -550,6 +1383,7 @@ impl ButtplugClientDevice {
   }
 
   pub fn raw_unsubscribe(&self, endpoint: Endpoint) -> ButtplugClientResultFuture {
+    check_raw_message_support!(self);
     check_message_support!(
       self,
       ButtplugCurrentSpecDeviceMessageType::RawUnsubscribeCmd
@@ -560,18 +1394,90 @@ impl ButtplugClientDevice {
     self.send_message_expect_ok(msg)
   }
 
+  /// Subscribes to a sensor on the device, returning a stream of readings.
+  ///
+  /// The stream ends when the device or client disconnects. Dropping the
+  /// stream does not unsubscribe on the server, use
+  /// [ButtplugClientDevice::unsubscribe_sensor] for that.
+  pub fn subscribe_sensor(
+    &self,
+    sensor_type: ButtplugClientDeviceSensorType,
+  ) -> ButtplugClientResultFuture<
+    Box<dyn Stream<Item = ButtplugClientDeviceSensorReading> + Send + Unpin>,
+  > {
+    // Subscribe to our events before sending the command, so we can't miss
+    // any readings that show up right after the server replies.
+    let event_receiver = self.internal_event_sender.subscribe();
+    let subscribe_fut = self.raw_subscribe(sensor_type.endpoint());
+    Box::pin(async move {
+      subscribe_fut.await?;
+      let stream = convert_broadcast_receiver_to_stream(event_receiver)
+        .take_while(|event| {
+          future::ready(!matches!(
+            event,
+            ButtplugClientDeviceEvent::DeviceRemoved | ButtplugClientDeviceEvent::ClientDisconnect
+          ))
+        })
+        .filter_map(move |event| {
+          future::ready(match event {
+            ButtplugClientDeviceEvent::Message(msg) => sensor_type.decode(&msg),
+            _ => None,
+          })
+        });
+      Ok(Box::new(Box::pin(stream))
+        as Box<dyn Stream<Item = ButtplugClientDeviceSensorReading> + Send + Unpin>)
+    })
+  }
+
+  /// Unsubscribes from a sensor on the device. Streams from
+  /// [ButtplugClientDevice::subscribe_sensor] stay open, but will no longer
+  /// receive readings.
+  pub fn unsubscribe_sensor(
+    &self,
+    sensor_type: ButtplugClientDeviceSensorType,
+  ) -> ButtplugClientResultFuture {
+    self.raw_unsubscribe(sensor_type.endpoint())
+  }
+
   /// Commands device to stop all movement.
   pub fn stop(&self) -> ButtplugClientResultFuture {
     // Everything *should* support StopDeviceCmd but let's just make sure.
     check_message_support!(self, ButtplugCurrentSpecDeviceMessageType::StopDeviceCmd);
+    self.clear_command_dedup();
     // All devices accept StopDeviceCmd
     self.send_message_expect_ok(StopDeviceCmd::new(self.index).into())
   }
 
+  /// Returns the raw server index of the device. Prefer
+  /// [ButtplugClientDevice::device_index] when passing the index back to
+  /// client APIs.
   pub fn index(&self) -> u32 {
     self.index
   }
 
+  /// Returns the server index of the device.
+  pub fn device_index(&self) -> DeviceIndex {
+    DeviceIndex(self.index)
+  }
+
+  /// Returns a serializable copy of the device's index, name, identifier,
+  /// connection status and message attributes.
+  pub fn snapshot(&self) -> ButtplugClientDeviceSnapshot {
+    ButtplugClientDeviceSnapshot {
+      index: self.index,
+      name: self.name.clone(),
+      identifier: self.serial_number.lock().unwrap().clone(),
+      connected: self.connected(),
+      messages: self.allowed_messages.clone(),
+    }
+  }
+
+  /// Forget the last values sent, so that the next command will go through
+  /// even if it matches them. Used when the device has been stopped.
+  pub(super) fn clear_command_dedup(&self) {
+    self.command_dedup.lock().unwrap().last_sent.clear();
+  }
+
   pub(super) fn set_device_connected(&self, connected: bool) {
     self.device_connected.store(connected, Ordering::SeqCst);
   }
@@ -607,3 +1513,142 @@ impl fmt::Debug for ButtplugClientDevice {
       .finish()
   }
 }
+
+/// Merges subcommands for the same message type into one list, with later
+/// subcommands for a feature index replacing earlier ones.
+fn merge_subcommands<T>(commands: Vec<Vec<T>>, index: impl Fn(&T) -> u32) -> Vec<T> {
+  let mut merged = BTreeMap::new();
+  for cmd in commands.into_iter().flatten() {
+    merged.insert(index(&cmd), cmd);
+  }
+  merged.into_iter().map(|(_, cmd)| cmd).collect()
+}
+
+/// Builds a set of output commands for a [ButtplugClientDevice], sending them
+/// together.
+///
+/// Obtained via [ButtplugClientDevice::command]. Targets are set using the
+/// same command enums as the single command methods, and can be chained:
+///
+/// ```no_run
+/// # use buttplug::client::{ButtplugClientDevice, RotateCommand, VibrateCommand};
+/// # async fn example(device: &ButtplugClientDevice) {
+/// device
+///   .command()
+///   .vibrate(VibrateCommand::Speed(0.5))
+///   .rotate(RotateCommand::Rotate(0.25, true))
+///   .send()
+///   .await
+///   .unwrap();
+/// # }
+/// ```
+///
+/// On [send][DeviceCommandBuilder::send], all targets are validated against
+/// the device's attributes before anything goes out. If any target is invalid
+/// (i.e. the device doesn't support the command, or a feature index or value
+/// is out of range), nothing is sent. Otherwise, targets are coalesced into at
+/// most one message per command type, so setting a target for the same
+/// feature twice only sends the last value.
+///
+/// The current message spec has no generic scalar command, so scalar outputs
+/// are set via [vibrate][DeviceCommandBuilder::vibrate] and
+/// [oscillate][DeviceCommandBuilder::oscillate].
+pub struct DeviceCommandBuilder<'a> {
+  device: &'a ButtplugClientDevice,
+  vibrate: Vec<VibrateCommand>,
+  oscillate: Vec<OscillateCommand>,
+  rotate: Vec<RotateCommand>,
+  linear: Vec<LinearCommand>,
+}
+
+impl<'a> DeviceCommandBuilder<'a> {
+  fn new(device: &'a ButtplugClientDevice) -> Self {
+    Self {
+      device,
+      vibrate: vec![],
+      oscillate: vec![],
+      rotate: vec![],
+      linear: vec![],
+    }
+  }
+
+  /// Sets vibration targets.
+  pub fn vibrate(mut self, cmd: VibrateCommand) -> Self {
+    self.vibrate.push(cmd);
+    self
+  }
+
+  /// Sets oscillation targets.
+  pub fn oscillate(mut self, cmd: OscillateCommand) -> Self {
+    self.oscillate.push(cmd);
+    self
+  }
+
+  /// Sets rotation targets.
+  pub fn rotate(mut self, cmd: RotateCommand) -> Self {
+    self.rotate.push(cmd);
+    self
+  }
+
+  /// Sets linear movement targets.
+  pub fn linear(mut self, cmd: LinearCommand) -> Self {
+    self.linear.push(cmd);
+    self
+  }
+
+  /// Validates all targets, then sends them to the device. Resolves once the
+  /// server has acknowledged every message sent. Sending a builder with no
+  /// targets does nothing.
+  pub fn send(self) -> ButtplugClientResultFuture {
+    let device = self.device;
+    let prepare = || -> Result<_, ButtplugError> {
+      let vibrate = self
+        .vibrate
+        .into_iter()
+        .map(|cmd| device.vibrate_subcommands(cmd))
+        .collect::<Result<Vec<_>, _>>()?;
+      let oscillate = self
+        .oscillate
+        .into_iter()
+        .map(|cmd| device.oscillate_subcommands(cmd))
+        .collect::<Result<Vec<_>, _>>()?;
+      let rotate = self
+        .rotate
+        .into_iter()
+        .map(|cmd| device.rotate_subcommands(cmd))
+        .collect::<Result<Vec<_>, _>>()?;
+      let linear = self
+        .linear
+        .into_iter()
+        .map(|cmd| device.linear_subcommands(cmd))
+        .collect::<Result<Vec<_>, _>>()?;
+      Ok((
+        merge_subcommands(vibrate, VibrateSubcommand::index),
+        merge_subcommands(oscillate, OscillateSubcommand::index),
+        merge_subcommands(rotate, RotationSubcommand::index),
+        merge_subcommands(linear, VectorSubcommand::index),
+      ))
+    };
+    let (vibrate, oscillate, rotate, linear) = match prepare() {
+      Ok(subcommands) => subcommands,
+      Err(err) => return device.create_boxed_future_client_error(err),
+    };
+    let mut send_futs = vec![];
+    if !vibrate.is_empty() {
+      send_futs.push(device.send_vibrate_subcommands(vibrate));
+    }
+    if !oscillate.is_empty() {
+      send_futs.push(device.send_oscillate_subcommands(oscillate));
+    }
+    if !rotate.is_empty() {
+      send_futs.push(device.send_rotate_subcommands(rotate));
+    }
+    if !linear.is_empty() {
+      send_futs.push(device.send_linear_subcommands(linear));
+    }
+    Box::pin(async move {
+      future::try_join_all(send_futs).await?;
+      Ok(())
+    })
+  }
+}
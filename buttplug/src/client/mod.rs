@@ -13,39 +13,57 @@ pub mod device;
 use crate::{
   connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorFuture},
   core::{
-    errors::{ButtplugError, ButtplugHandshakeError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
     messages::{
-      ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage,
-      ButtplugMessageSpecVersion, Ping, RequestDeviceList, RequestServerInfo, StartScanning,
-      StopAllDevices, StopScanning,
+      ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecDeviceMessageType,
+      ButtplugCurrentSpecServerMessage, DeviceMessageInfo, DisconnectDevice, Ping,
+      ReconnectDevice, RequestDeviceList, RequestServerInfo, StartScanning, StartScanningManagers,
+      StopAllDevices, StopScanning, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
   util::{
-    async_manager,
     future::{ButtplugFuture, ButtplugFutureStateShared},
     stream::convert_broadcast_receiver_to_stream,
   },
 };
 #[cfg(feature = "server")]
-use crate::server::ButtplugServer;
-use client_event_loop::{ButtplugClientEventLoop, ButtplugClientRequest};
+use crate::server::{
+  comm_managers::DeviceCommunicationManagerBuilder, device_manager::DeviceManager, ButtplugServer,
+};
+use client_event_loop::{
+  spawn_event_loop_watchdog, ButtplugClientEventLoop, ButtplugClientEventLoopSettings,
+  ButtplugClientRequest,
+};
+use client_message_sorter::ClientMessageReplyFuture;
+pub use client_event_loop::{ButtplugClientEventLoopExit, ButtplugClientEventLoopHandle};
+use async_stream::stream;
 use dashmap::DashMap;
 pub use device::{
-  ButtplugClientDevice, ButtplugClientDeviceEvent, ButtplugClientDeviceMessageType, LinearCommand,
+  ButtplugClientDevice, ButtplugClientDeviceEvent, ButtplugClientDeviceMessageType,
+  ButtplugClientDeviceSensorReading, ButtplugClientDeviceSensorType, ButtplugClientDeviceSnapshot,
+  DeviceCommandBuilder, DeviceIndex, DeviceInformation, LinearCommand, OscillateCommand,
   RotateCommand, VibrateCommand,
 };
 use futures::{
   future::{self, BoxFuture},
-  Stream,
+  FutureExt, Stream,
 };
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+use futures_timer::Delay;
+use std::{
+  collections::{BTreeSet, VecDeque},
+  sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
 };
 use thiserror::Error;
-use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::sync::{
+  broadcast::{self, error::RecvError},
+  mpsc, Mutex,
+};
+use tokio_util::sync::CancellationToken;
 use tracing::{span::Span, Level};
-use tracing_futures::Instrument;
 
 /// Result type used for public APIs.
 ///
@@ -112,6 +130,16 @@ pub enum ButtplugClientError {
   /// Protocol error
   #[error(transparent)]
   ButtplugError(#[from] ButtplugError),
+  /// Device command dropped because the client was paused
+  #[error("Client is paused, device command dropped")]
+  ClientPaused,
+  /// Device command cancelled because all devices were stopped before it was
+  /// sent
+  #[error("All devices were stopped before the device command was sent")]
+  CommandCancelled,
+  /// Scan finished without finding any devices
+  #[error("No devices found while scanning")]
+  NoDevicesFound,
 }
 
 /// Enum representing different events that can be emitted by a client.
@@ -130,8 +158,24 @@ pub enum ButtplugClientEvent {
   /// Emitted when a device has been removed from the server. Includes a
   /// [ButtplugClientDevice] object representing the device.
   DeviceRemoved(Arc<ButtplugClientDevice>),
+  /// Emitted instead of [ButtplugClientEvent::DeviceAdded] when a device that
+  /// was removed comes back with the same index, name and messages, while
+  /// the application still holds its [ButtplugClientDevice]. Includes that
+  /// same instance, which is connected and usable again.
+  DeviceReconnected(Arc<ButtplugClientDevice>),
+  /// Emitted instead of [ButtplugClientEvent::DeviceAdded] when a device is
+  /// added to the server while the client is already tracking the maximum
+  /// number of devices set via [ButtplugClient::set_max_devices]. The device
+  /// is not added to the client, and the server is asked to stop it.
+  DeviceRejected(DeviceMessageInfo),
+  /// Emitted when the event loop watchdog (see
+  /// [ButtplugClient::set_watchdog_timeout]) notices the client event loop has
+  /// stalled. A stop command for all devices has been queued when this is
+  /// emitted.
+  EventLoopStalled,
   /// Emitted when a client has not pinged the server in a sufficient amount of
-  /// time.
+  /// time. What else happens depends on the client's
+  /// [ButtplugClientPingTimeoutPolicy].
   PingTimeout,
   /// Emitted when the client successfully connects to a server.
   ServerConnect,
@@ -140,10 +184,111 @@ pub enum ButtplugClientEvent {
   /// Emitted when an error that cannot be matched to a request is received from
   /// the server.
   Error(ButtplugError),
+  /// Emitted when the server sends a message the client doesn't know how to
+  /// handle, if the client's [ButtplugClientUnknownMessagePolicy] is set to
+  /// [Event][ButtplugClientUnknownMessagePolicy::Event]. Contains the message
+  /// as it was received, if it couldn't be deserialized, or its debug
+  /// representation otherwise.
+  UnknownMessage(String),
 }
 
 impl Unpin for ButtplugClientEvent {}
 
+/// What the client does with messages from the server it doesn't know how to
+/// handle, i.e. new message types from a newer server.
+///
+/// Set via [ButtplugClient::set_unknown_message_policy].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtplugClientUnknownMessagePolicy {
+  /// Log the message and drop it.
+  Ignore,
+  /// Emit the message as a [ButtplugClientEvent::UnknownMessage].
+  Event,
+  /// Emit a [ButtplugClientEvent::Error] containing a
+  /// [ButtplugMessageError::UnknownMessage][crate::core::errors::ButtplugMessageError::UnknownMessage].
+  Error,
+}
+
+impl Default for ButtplugClientUnknownMessagePolicy {
+  /// Ignores unknown messages, so older clients keep working with newer
+  /// servers.
+  fn default() -> Self {
+    ButtplugClientUnknownMessagePolicy::Ignore
+  }
+}
+
+/// What the client does when the server reports that it wasn't pinged in time.
+///
+/// Whatever the policy, the client first asks the server to stop all devices,
+/// then emits [ButtplugClientEvent::PingTimeout]. Set via
+/// [ButtplugClient::set_ping_timeout_policy].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtplugClientPingTimeoutPolicy {
+  /// Leave the connection up, letting the application decide what to do.
+  Notify,
+  /// Disconnect from the server. The server won't accept any more commands
+  /// after a ping timeout, so there's little use in staying connected.
+  Disconnect,
+}
+
+impl Default for ButtplugClientPingTimeoutPolicy {
+  fn default() -> Self {
+    ButtplugClientPingTimeoutPolicy::Notify
+  }
+}
+
+/// What the client does with device commands sent while it is paused.
+///
+/// Passed to [ButtplugClient::pause].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtplugClientPausedCommandPolicy {
+  /// Hold commands, and send them in order on resume. Their futures resolve
+  /// once the server replies after resume.
+  Queue,
+  /// Fail commands immediately with [ButtplugClientError::ClientPaused].
+  Drop,
+}
+
+impl Default for ButtplugClientPausedCommandPolicy {
+  fn default() -> Self {
+    ButtplugClientPausedCommandPolicy::Queue
+  }
+}
+
+/// Optional features a server reported supporting during the handshake.
+///
+/// Servers list their capabilities by name in the
+/// [ServerInfo][crate::core::messages::ServerInfo] message, which can be
+/// checked with [contains][ButtplugServerCapabilities::contains]. Servers that
+/// don't report capabilities have none.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ButtplugServerCapabilities {
+  capabilities: BTreeSet<String>,
+}
+
+impl ButtplugServerCapabilities {
+  pub fn new<T: ToString>(capabilities: &[T]) -> Self {
+    Self {
+      capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+    }
+  }
+
+  /// Returns true if the server reported the named capability.
+  pub fn contains(&self, capability: &str) -> bool {
+    self.capabilities.contains(capability)
+  }
+
+  /// Iterates over the names of all capabilities the server reported, in
+  /// sorted order.
+  pub fn iter(&self) -> impl Iterator<Item = &String> {
+    self.capabilities.iter()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.capabilities.is_empty()
+  }
+}
+
 /// Struct used by applications to communicate with a Buttplug Server.
 ///
 /// Buttplug Clients provide an API layer on top of the Buttplug Protocol that
@@ -161,17 +306,94 @@ impl Unpin for ButtplugClientEvent {}
 /// handles spinning up the event loop and connecting the client to the server.
 /// Closures passed to the run() method can access and use the Client object.
 pub struct ButtplugClient {
-  /// The client name. Depending on the connection type and server being used,
-  /// this name is sometimes shown on the server logs or GUI.
+  /// The client name, used as a label for the client instance.
   client_name: String,
+  /// The client name sent to the server during the handshake. Depending on the
+  /// connection type and server being used, this name is sometimes shown on the
+  /// server logs or GUI.
+  handshake_client_name: String,
   /// The server name that we're current connected to.
   server_name: Arc<Mutex<Option<String>>>,
+  /// Capabilities reported by the server we're currently connected to.
+  server_capabilities: Arc<std::sync::Mutex<ButtplugServerCapabilities>>,
   event_stream: broadcast::Sender<ButtplugClientEvent>,
-  // Sender to relay messages to the internal client loop
-  message_sender: broadcast::Sender<ButtplugClientRequest>,
   connected: Arc<AtomicBool>,
   _client_span: Arc<Mutex<Option<Span>>>,
-  device_map: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+  device_map: Arc<DashMap<DeviceIndex, Arc<ButtplugClientDevice>>>,
+  /// Names of the device communication managers added by
+  /// [ButtplugClient::connect_in_process].
+  in_process_comm_managers: Arc<Mutex<Vec<String>>>,
+  /// Handle to the event loop task of the current (or most recent)
+  /// connection.
+  event_loop_handle: Arc<Mutex<Option<ButtplugClientEventLoopHandle>>>,
+  /// Most recent round trip times measured by
+  /// [ButtplugClient::measure_latency], oldest first.
+  latency_samples: Arc<std::sync::Mutex<VecDeque<Duration>>>,
+  /// Maximum number of devices the client will track. usize::MAX means
+  /// unlimited, so that 0 can mean no devices at all.
+  max_devices: Arc<AtomicUsize>,
+  /// Maximum device commands per second, across all devices. 0 means
+  /// unlimited.
+  max_command_rate: Arc<AtomicU32>,
+  /// If set, a watchdog will try to stop all devices if the event loop stalls
+  /// for longer than this.
+  watchdog_timeout: Arc<std::sync::Mutex<Option<Duration>>>,
+  /// How the event loop treats messages from the server it can't handle.
+  unknown_message_policy: Arc<std::sync::Mutex<ButtplugClientUnknownMessagePolicy>>,
+  /// How to react to the server pinging out, shared with the event loop.
+  ping_timeout_policy: Arc<std::sync::Mutex<ButtplugClientPingTimeoutPolicy>>,
+  /// True while the event loop is paused via [ButtplugClient::pause].
+  paused: Arc<AtomicBool>,
+  /// State shared with the event loop of the current (or most recent)
+  /// connection. None until the first connect.
+  connection: Arc<std::sync::Mutex<Option<ButtplugClientConnection>>>,
+}
+
+/// State shared between a [ButtplugClient] and the event loop of a single
+/// connection. Replaced as a whole on every connect, so nothing from an old
+/// event loop leaks into a new one.
+#[derive(Clone)]
+struct ButtplugClientConnection {
+  /// Relays requests to the event loop. Device commands go through the same
+  /// channel, so everything reaches the event loop in the order it was sent.
+  request_sender: mpsc::UnboundedSender<ButtplugClientRequest>,
+  /// Never sent on. Once the client (and with it, this) is dropped, the event
+  /// loop sees the channel close and shuts down.
+  _client_alive_sender: mpsc::Sender<()>,
+  /// Lets reply futures tell the event loop when they're dropped before their
+  /// reply arrives.
+  reply_cancel_sender: mpsc::UnboundedSender<()>,
+  /// Count of devices added by the event loop, used to report scan results.
+  added_device_count: Arc<AtomicUsize>,
+  /// Count of requests the event loop is waiting on replies for.
+  pending_request_count: Arc<AtomicUsize>,
+  /// Liveness flag of the connector's transport, if it has one.
+  transport_connected: Option<Arc<AtomicBool>>,
+}
+
+impl ButtplugClientConnection {
+  /// Sends a ButtplugMessage to this connection's event loop right away.
+  /// Returns a future that resolves to the server's reply.
+  fn send_message(
+    &self,
+    msg: ButtplugCurrentSpecClientMessage,
+  ) -> ButtplugServerMessageResultFuture {
+    // Create a future to pair with the message being resolved.
+    let fut = ButtplugServerMessageFuture::default();
+    let internal_msg = ButtplugClientRequest::Message(ButtplugClientMessageFuturePair::new(
+      msg,
+      fut.get_state_clone(),
+    ));
+    let fut = ClientMessageReplyFuture::new(fut, Some(self.reply_cancel_sender.clone()));
+    let send_result: Result<(), ButtplugClientError> = self
+      .request_sender
+      .send(internal_msg)
+      .map_err(|_| ButtplugConnectorError::ConnectorChannelClosed.into());
+    Box::pin(async move {
+      send_result?;
+      fut.await
+    })
+  }
 }
 
 unsafe impl Send for ButtplugClient {}
@@ -179,24 +401,113 @@ unsafe impl Send for ButtplugClient {}
 // without it.
 unsafe impl Sync for ButtplugClient {}
 
+/// Number of latency measurements kept for
+/// [ButtplugClient::average_latency].
+pub const LATENCY_SAMPLE_WINDOW: usize = 10;
+
+/// Maximum length, in characters, of the client name sent to the server during
+/// the handshake.
+pub const MAX_CLIENT_NAME_LENGTH: usize = 256;
+
+/// Shortest timeout [ButtplugClient::set_watchdog_timeout] accepts. The
+/// watchdog checks the event loop several times per timeout, so anything
+/// shorter would just have it spin.
+pub const MIN_WATCHDOG_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Checks that a client name can be sent to the server during the handshake.
+///
+/// Client names must not be empty or whitespace only, must be at most
+/// [MAX_CLIENT_NAME_LENGTH] characters long, and must not contain control
+/// characters (newlines, tabs, nulls, etc...).
+pub fn validate_client_name(name: &str) -> Result<(), ButtplugHandshakeError> {
+  if name.trim().is_empty() {
+    return Err(ButtplugHandshakeError::InvalidClientName(
+      "Client name cannot be empty.".to_owned(),
+    ));
+  }
+  let length = name.chars().count();
+  if length > MAX_CLIENT_NAME_LENGTH {
+    return Err(ButtplugHandshakeError::InvalidClientName(format!(
+      "Client name is {} characters long, maximum is {}.",
+      length, MAX_CLIENT_NAME_LENGTH
+    )));
+  }
+  if name.chars().any(char::is_control) {
+    return Err(ButtplugHandshakeError::InvalidClientName(
+      "Client name cannot contain control characters.".to_owned(),
+    ));
+  }
+  Ok(())
+}
+
 impl ButtplugClient {
+  /// Creates a new client, using `name` both as the client label and as the
+  /// name sent to the server during the handshake.
   pub fn new(name: &str) -> Self {
-    let (message_sender, _) = broadcast::channel(256);
+    Self::new_with_handshake_name(name, name)
+  }
+
+  /// Creates a new client, with a label separate from the name sent to the
+  /// server during the handshake.
+  ///
+  /// The handshake name is validated using [validate_client_name] when
+  /// [connect][ButtplugClient::connect] is called.
+  pub fn new_with_handshake_name(name: &str, handshake_name: &str) -> Self {
     let (event_stream, _) = broadcast::channel(256);
     Self {
       client_name: name.to_owned(),
+      handshake_client_name: handshake_name.to_owned(),
       server_name: Arc::new(Mutex::new(None)),
+      server_capabilities: Arc::new(std::sync::Mutex::new(ButtplugServerCapabilities::default())),
       event_stream,
-      message_sender,
       _client_span: Arc::new(Mutex::new(None)),
       connected: Arc::new(AtomicBool::new(false)),
       device_map: Arc::new(DashMap::new()),
+      in_process_comm_managers: Arc::new(Mutex::new(vec![])),
+      event_loop_handle: Arc::new(Mutex::new(None)),
+      latency_samples: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+      max_devices: Arc::new(AtomicUsize::new(usize::MAX)),
+      max_command_rate: Arc::new(AtomicU32::new(0)),
+      watchdog_timeout: Arc::new(std::sync::Mutex::new(None)),
+      unknown_message_policy: Arc::new(std::sync::Mutex::new(
+        ButtplugClientUnknownMessagePolicy::default(),
+      )),
+      ping_timeout_policy: Arc::new(std::sync::Mutex::new(
+        ButtplugClientPingTimeoutPolicy::default(),
+      )),
+      paused: Arc::new(AtomicBool::new(false)),
+      connection: Arc::new(std::sync::Mutex::new(None)),
     }
   }
 
   pub async fn connect<ConnectorType>(
+    &self,
+    connector: ConnectorType,
+  ) -> Result<(), ButtplugClientError>
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+  {
+    self
+      .connect_with_cancellation(connector, CancellationToken::new())
+      .await
+  }
+
+  /// Connects to a server, aborting the connection attempt if `cancel_token`
+  /// is cancelled before it finishes.
+  ///
+  /// Connecting may take a while (binding sockets, probing hardware, running
+  /// the handshake), and simply dropping the future returned by
+  /// [connect][ButtplugClient::connect] can leave a connector or event loop
+  /// half set up. If the token is cancelled while the connector is connecting,
+  /// the connector is disconnected. If it is cancelled during the handshake,
+  /// the client event loop is told to disconnect and shut down. In both cases,
+  /// this returns [ButtplugConnectorError::ConnectorCancelled] and the client
+  /// is left disconnected, ready for another connection attempt.
+  pub async fn connect_with_cancellation<ConnectorType>(
     &self,
     mut connector: ConnectorType,
+    cancel_token: CancellationToken,
   ) -> Result<(), ButtplugClientError>
   where
     ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
@@ -208,6 +519,13 @@ impl ButtplugClient {
       ));
     }
 
+    // Check the name before we connect, otherwise the server may reject it
+    // during the handshake without much explanation.
+    validate_client_name(&self.handshake_client_name).map_err(|e| {
+      error!("Client name is invalid: {:?}", e);
+      ButtplugClientError::from(ButtplugError::from(e))
+    })?;
+
     // TODO I cannot remember why this is here or what it does.
     *self._client_span.lock().await = {
       let span = span!(Level::INFO, "Client");
@@ -215,30 +533,95 @@ impl ButtplugClient {
       Some(span)
     };
     info!("Connecting to server.");
+    // Latency from a previous connection says nothing about this one.
+    self.latency_samples.lock().unwrap().clear();
+    // New event loops always start unpaused.
+    self.paused.store(false, Ordering::SeqCst);
     let (connector_sender, connector_receiver) = mpsc::channel(256);
-    connector.connect(connector_sender).await.map_err(|e| {
+    let connect_result = select! {
+      result = connector.connect(connector_sender).fuse() => result,
+      _ = cancel_token.cancelled().fuse() => {
+        info!("Connection cancelled while connecting, disconnecting connector.");
+        // The connector may or may not have finished coming up, so we don't
+        // care whether this succeeds.
+        let _ = connector.disconnect().await;
+        Err(ButtplugConnectorError::ConnectorCancelled)
+      }
+    };
+    connect_result.map_err(|e| {
       error!("Connection to server failed: {:?}", e);
       ButtplugClientError::from(e)
     })?;
     info!("Connection to server succeeded.");
+    let transport_connected = connector.transport_connected_status();
+    let (client_alive_sender, client_alive_receiver) = mpsc::channel(1);
     let mut client_event_loop = ButtplugClientEventLoop::new(
       self.connected.clone(),
       connector,
       connector_receiver,
       self.event_stream.clone(),
-      self.message_sender.clone(),
+      client_alive_receiver,
       self.device_map.clone(),
+      ButtplugClientEventLoopSettings {
+        max_devices: self.max_devices.clone(),
+        unknown_message_policy: self.unknown_message_policy.clone(),
+        ping_timeout_policy: self.ping_timeout_policy.clone(),
+        max_command_rate: self.max_command_rate.clone(),
+      },
     );
+    let request_sender = client_event_loop.request_sender();
+    *self.connection.lock().unwrap() = Some(ButtplugClientConnection {
+      request_sender: request_sender.clone(),
+      _client_alive_sender: client_alive_sender,
+      reply_cancel_sender: client_event_loop.reply_cancel_sender(),
+      added_device_count: client_event_loop.added_device_count(),
+      pending_request_count: client_event_loop.pending_request_count(),
+      transport_connected,
+    });
+    let watchdog_timeout = *self.watchdog_timeout.lock().unwrap();
+    // Check in a few times per timeout, so a stall is noticed reasonably close
+    // to when the timeout is up.
+    let heartbeat = watchdog_timeout.map(|timeout| client_event_loop.heartbeat(timeout / 4));
 
-    // Start the event loop before we run the handshake.
-    async_manager::spawn(
+    // Start the event loop before we run the handshake. If the loop gets
+    // aborted or panics, it won't get the chance to clean up after itself, so
+    // do that here.
+    let connected = self.connected.clone();
+    let device_map = self.device_map.clone();
+    let event_stream = self.event_stream.clone();
+    let event_loop_handle = ButtplugClientEventLoopHandle::spawn(
       async move {
         client_event_loop.run().await;
+      },
+      move || {
+        connected.store(false, Ordering::SeqCst);
+        device_map.iter().for_each(|device| {
+          device.value().set_client_connected(false);
+          device.value().set_device_connected(false);
+        });
+        device_map.clear();
+        // Nobody may be listening, which is fine.
+        let _ = event_stream.send(ButtplugClientEvent::ServerDisconnect);
+      },
+    );
+    if let (Some(timeout), Some(heartbeat)) = (watchdog_timeout, heartbeat) {
+      spawn_event_loop_watchdog(
+        timeout,
+        heartbeat,
+        event_loop_handle.clone(),
+        request_sender,
+        self.event_stream.clone(),
+      );
+    }
+    *self.event_loop_handle.lock().await = Some(event_loop_handle);
+    select! {
+      result = self.run_handshake().fuse() => result,
+      _ = cancel_token.cancelled().fuse() => {
+        info!("Connection cancelled during handshake, shutting down event loop.");
+        self.shutdown_event_loop().await;
+        Err(ButtplugConnectorError::ConnectorCancelled.into())
       }
-      .instrument(tracing::info_span!("Client Loop Span")),
-    )
-    .unwrap();
-    self.run_handshake().await
+    }
   }
 
   /// Convenience function for creating in-process connectors.
@@ -268,9 +651,17 @@ impl ButtplugClient {
   ///
   /// # Errors
   ///
-  /// If the library was compiled without any device managers, the
-  /// [ButtplugClient] will have nothing to do. This is considered a
-  /// catastrophic failure and the library will return an error.
+  /// If a device manager fails to initialize (for instance, if it was already
+  /// added to the server passed in, or it panics while starting up because its
+  /// backend is broken on the current machine), a warning is logged and
+  /// connection continues with the device managers that did initialize. The
+  /// names of those managers are available via
+  /// [ButtplugClient::in_process_comm_managers] after connecting.
+  ///
+  /// If the library was compiled without any device managers, or none of them
+  /// could be initialized, the [ButtplugClient] will have nothing to do. This
+  /// is considered a catastrophic failure and the library will return an
+  /// error.
   ///
   /// If the library is using outside device managers, it is recommended to
   /// build your own connector, add your device manager to those, and use the
@@ -280,72 +671,75 @@ impl ButtplugClient {
     &self,
     server: Option<ButtplugServer>
   ) -> Result<(), ButtplugClientError> {
-    use crate::connector::ButtplugInProcessClientConnector;
+    use crate::{connector::ButtplugInProcessClientConnector, core::errors::ButtplugUnknownError};
 
     let connector = ButtplugInProcessClientConnector::new(server);
+    let device_manager = connector.server_ref().device_manager();
+    // The server passed in may already have managers of its own, which we
+    // didn't initialize.
+    let existing_comm_managers = device_manager.comm_manager_names();
     #[cfg(feature = "btleplug-manager")]
     {
       use crate::server::comm_managers::btleplug::BtlePlugCommunicationManagerBuilder;
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(BtlePlugCommunicationManagerBuilder::default())
-        .unwrap();
+      add_in_process_comm_manager(device_manager, BtlePlugCommunicationManagerBuilder::default());
     }
     #[cfg(feature = "websocket-server-manager")]
     {
       use crate::server::comm_managers::websocket_server::websocket_server_comm_manager::WebsocketServerDeviceCommunicationManagerBuilder;
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(
-          WebsocketServerDeviceCommunicationManagerBuilder::default().listen_on_all_interfaces(true),
-        )
-        .unwrap();
+      add_in_process_comm_manager(
+        device_manager,
+        WebsocketServerDeviceCommunicationManagerBuilder::default().listen_on_all_interfaces(true),
+      );
     }
     #[cfg(feature = "serial-manager")]
     {
       use crate::server::comm_managers::serialport::SerialPortCommunicationManagerBuilder;
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(SerialPortCommunicationManagerBuilder::default())
-        .unwrap();
+      add_in_process_comm_manager(device_manager, SerialPortCommunicationManagerBuilder::default());
     }
     #[cfg(feature = "lovense-connect-service-manager")]
     {
       use crate::server::comm_managers::lovense_connect_service::LovenseConnectServiceCommunicationManagerBuilder;
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(LovenseConnectServiceCommunicationManagerBuilder::default())
-        .unwrap();
+      add_in_process_comm_manager(
+        device_manager,
+        LovenseConnectServiceCommunicationManagerBuilder::default(),
+      );
     }
     #[cfg(feature = "lovense-dongle-manager")]
     {
       use crate::server::comm_managers::lovense_dongle::{
         LovenseHIDDongleCommunicationManagerBuilder, LovenseSerialDongleCommunicationManagerBuilder,
       };
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(LovenseHIDDongleCommunicationManagerBuilder::default())
-        .unwrap();
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(LovenseSerialDongleCommunicationManagerBuilder::default())
-        .unwrap();
+      add_in_process_comm_manager(
+        device_manager,
+        LovenseHIDDongleCommunicationManagerBuilder::default(),
+      );
+      add_in_process_comm_manager(
+        device_manager,
+        LovenseSerialDongleCommunicationManagerBuilder::default(),
+      );
     }
     #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
     {
       use crate::server::comm_managers::xinput::XInputDeviceCommunicationManagerBuilder;
-      connector
-        .server_ref()
-        .device_manager()
-        .add_comm_manager(XInputDeviceCommunicationManagerBuilder::default())
-        .unwrap();
+      add_in_process_comm_manager(
+        device_manager,
+        XInputDeviceCommunicationManagerBuilder::default(),
+      );
     }
+    // Comm managers that failed to come up have already been logged. As long as
+    // we have something to scan with, keep going.
+    let comm_managers = device_manager.comm_manager_names();
+    if comm_managers.is_empty() {
+      error!("No device communication managers could be initialized, cannot connect.");
+      return Err(ButtplugClientError::from(ButtplugError::from(
+        ButtplugUnknownError::NoDeviceCommManagers,
+      )));
+    }
+    info!("Using device communication managers: {:?}", comm_managers);
+    *self.in_process_comm_managers.lock().await = comm_managers
+      .into_iter()
+      .filter(|name| !existing_comm_managers.contains(name))
+      .collect();
     self.connect(connector).await
   }
 
@@ -360,7 +754,8 @@ impl ButtplugClient {
     info!("Running handshake with server.");
     let msg = self
       .send_message_ignore_connect_status(
-        RequestServerInfo::new(&self.client_name, ButtplugMessageSpecVersion::Version2).into(),
+        RequestServerInfo::new(&self.handshake_client_name, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
       )
       .await?;
 
@@ -368,6 +763,11 @@ impl ButtplugClient {
     if let ButtplugCurrentSpecServerMessage::ServerInfo(server_info) = msg {
       info!("Connected to {}", server_info.server_name());
       *self.server_name.lock().await = Some(server_info.server_name().clone());
+      if !server_info.capabilities().is_empty() {
+        info!("Server capabilities: {:?}", server_info.capabilities());
+      }
+      *self.server_capabilities.lock().unwrap() =
+        ButtplugServerCapabilities::new(server_info.capabilities());
       // Don't set ourselves as connected until after ServerInfo has been
       // received. This means we avoid possible races with the RequestServerInfo
       // handshake.
@@ -386,42 +786,92 @@ impl ButtplugClient {
       }
       Ok(())
     } else {
-      self.disconnect().await?;
+      // We never set ourselves connected, so disconnect() would be a no-op.
+      self.shutdown_event_loop().await;
       Err(ButtplugClientError::ButtplugError(
         ButtplugHandshakeError::UnexpectedHandshakeMessageReceived(format!("{:?}", msg)).into(),
       ))
     }
   }
 
+  /// Returns the label of the client.
+  pub fn client_name(&self) -> &str {
+    &self.client_name
+  }
+
+  /// Returns the client name that is sent to the server during the handshake.
+  pub fn handshake_client_name(&self) -> &str {
+    &self.handshake_client_name
+  }
+
   /// Returns true if client is currently connected.
   pub fn connected(&self) -> bool {
     self.connected.load(Ordering::SeqCst)
   }
 
+  /// Returns true if the connector's transport reports its link as alive.
+  ///
+  /// Unlike [ButtplugClient::connected], which tracks the protocol level
+  /// connection, this reflects what the transport itself knows, i.e. whether
+  /// a websocket is still open and answering pings. The transport may notice a
+  /// dead link before the client does, so `connected()` can be true while this
+  /// is false. For connectors without a transport that tracks liveness (i.e.
+  /// in-process connectors), this is the same as `connected()`.
+  pub fn transport_connected(&self) -> bool {
+    match self
+      .connection()
+      .and_then(|connection| connection.transport_connected)
+    {
+      Some(transport_connected) => transport_connected.load(Ordering::SeqCst),
+      None => self.connected(),
+    }
+  }
+
   /// Disconnects from server, if connected.
   ///
-  /// Returns Err(ButtplugClientError) if disconnection fails. It can be assumed
-  /// that even on failure, the client will be disconnected.
+  /// Idempotent: if the client is already disconnected (or another disconnect,
+  /// or the server going away, got there first), resolves Ok without doing
+  /// anything. Only one teardown ever happens per connection, so
+  /// [ButtplugClientEvent::ServerDisconnect] is only emitted once.
   pub fn disconnect(&self) -> ButtplugClientResultFuture {
-    if !self.connected() {
-      return Box::pin(future::ready(Err(
-        ButtplugConnectorError::ConnectorNotConnected.into(),
-      )));
+    // Whoever flips connected gets to do the teardown.
+    if self
+      .connected
+      .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
+      .is_err()
+    {
+      debug!("Client already disconnected, ignoring disconnect request.");
+      return Box::pin(future::ready(Ok(())));
     }
-    // Send the connector to the internal loop for management. Once we throw
-    // the connector over, the internal loop will handle connecting and any
-    // further communications with the server, if connection is successful.
+    *self.server_capabilities.lock().unwrap() = ButtplugServerCapabilities::default();
+    // Ask the event loop to disconnect the connector and shut down.
     let fut = ButtplugConnectorFuture::default();
     let msg = ButtplugClientRequest::Disconnect(fut.get_state_clone());
     let send_fut = self.send_message_to_event_loop(msg);
-    let connected = self.connected.clone();
     Box::pin(async move {
-      send_fut.await?;
-      connected.store(false, Ordering::SeqCst);
+      // If the event loop is already gone, the server disconnected while we
+      // were getting here, which is the state we wanted anyways.
+      if send_fut.await.is_err() {
+        debug!("Event loop already exited, nothing to disconnect.");
+      }
       Ok(())
     })
   }
 
+  /// Tells the event loop to disconnect the connector and exit, regardless of
+  /// whether the handshake has finished. Used to tear down cancelled connection
+  /// attempts.
+  async fn shutdown_event_loop(&self) {
+    let fut = ButtplugConnectorFuture::default();
+    let msg = ButtplugClientRequest::Disconnect(fut.get_state_clone());
+    if self.send_message_to_event_loop(msg).await.is_ok() {
+      if let Err(e) = fut.await {
+        error!("Error disconnecting connector during shutdown: {:?}", e);
+      }
+    }
+    self.connected.store(false, Ordering::SeqCst);
+  }
+
   /// Tells server to start scanning for devices.
   ///
   /// Returns Err([ButtplugClientError]) if request fails due to issues with
@@ -430,6 +880,20 @@ impl ButtplugClient {
     self.send_message_expect_ok(StartScanning::default().into())
   }
 
+  /// Tells server to start scanning for devices, using only the named device
+  /// communication managers. Managers that aren't named are left alone.
+  ///
+  /// Useful for in-process servers with many comm managers (see
+  /// [ButtplugClient::in_process_comm_managers] for names), when the app
+  /// knows which bus its devices are on.
+  ///
+  /// Returns Err([ButtplugClientError]) if `names` is empty, if any of the
+  /// names don't match a comm manager on the server, or if any of the named
+  /// managers are already scanning.
+  pub fn start_scanning_managers(&self, names: Vec<String>) -> ButtplugClientResultFuture {
+    self.send_message_expect_ok(StartScanningManagers::new(names).into())
+  }
+
   /// Tells server to stop scanning for devices.
   ///
   /// Returns Err([ButtplugClientError]) if request fails due to issues with
@@ -438,14 +902,199 @@ impl ButtplugClient {
     self.send_message_expect_ok(StopScanning::default().into())
   }
 
+  /// Scans for devices for `duration`, then stops scanning and returns the
+  /// number of devices that were added during the scan.
+  ///
+  /// Devices that reconnect during the scan are counted, devices rejected due
+  /// to the device limit are not. If scanning finishes on its own before
+  /// `duration` is up, this still waits out the rest of `duration`, as some
+  /// devices may still be connecting.
+  ///
+  /// Returns Err([ButtplugClientError]) if scanning can't be started or
+  /// stopped.
+  pub fn scan_for_duration(&self, duration: Duration) -> ButtplugClientResultFuture<usize> {
+    let connection = self.connection();
+    let connected = self.connected.clone();
+    let added_device_count = connection
+      .as_ref()
+      .map(|connection| connection.added_device_count.clone());
+    let start_fut = self.start_scanning();
+    Box::pin(async move {
+      let added_before = added_device_count
+        .as_ref()
+        .map_or(0, |count| count.load(Ordering::SeqCst));
+      start_fut.await?;
+      Delay::new(duration).await;
+      // Messages go out as soon as they're created, so StopScanning can't be
+      // built until the scan is actually over.
+      let stop_fut: ButtplugClientResultFuture = match connection {
+        Some(connection) if connected.load(Ordering::SeqCst) => connection
+          .send_message(StopScanning::default().into())
+          .map(|result| result.map(|_| ()))
+          .boxed(),
+        _ => future::ready(Err(ButtplugConnectorError::ConnectorNotConnected.into())).boxed(),
+      };
+      match stop_fut.await {
+        // Scanning may have finished on its own, which is fine.
+        Err(ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::DeviceScanningAlreadyStopped,
+        ))) => {}
+        result => result?,
+      }
+      Ok(
+        added_device_count
+          .map_or(0, |count| count.load(Ordering::SeqCst))
+          .saturating_sub(added_before),
+      )
+    })
+  }
+
+  /// Scans for devices for `duration`, like
+  /// [ButtplugClient::scan_for_duration], but returns
+  /// Err([ButtplugClientError::NoDevicesFound]) if no devices were added
+  /// during the scan.
+  ///
+  /// Useful for the common "scan for a bit, and tell the user if nothing
+  /// turned up" flow.
+  pub fn scan_with_timeout(&self, duration: Duration) -> ButtplugClientResultFuture<usize> {
+    let scan_fut = self.scan_for_duration(duration);
+    Box::pin(async move {
+      match scan_fut.await? {
+        0 => Err(ButtplugClientError::NoDevicesFound),
+        count => Ok(count),
+      }
+    })
+  }
+
+  /// Tells server to try reconnecting a single device that was previously
+  /// connected, without scanning for new devices.
+  ///
+  /// Device indexes stay the same across reconnects, so `device_index` is the
+  /// index the device had before it disconnected. Resolves once the server has
+  /// found the device, and a [ButtplugClientEvent::DeviceAdded] event will
+  /// follow once it is connected. Returns Err([ButtplugClientError]) if the
+  /// server does not know of the device, cannot reconnect it, or does not find
+  /// it in time (i.e. it is no longer in range).
+  pub fn reconnect_device(&self, device_index: DeviceIndex) -> ButtplugClientResultFuture {
+    self.send_message_expect_ok(ReconnectDevice::new(device_index.into()).into())
+  }
+
+  /// Tells server to forcibly drop the connection to a device and remove it,
+  /// for devices that are stuck and no longer respond to stop commands.
+  ///
+  /// Unlike [ButtplugClientDevice::stop], this doesn't just stop the device,
+  /// it disconnects it. A [ButtplugClientEvent::DeviceRemoved] event will
+  /// follow once the server has removed the device. Returns
+  /// Err([ButtplugClientError]) if there is no device with this index.
+  pub fn force_disconnect(&self, device_index: DeviceIndex) -> ButtplugClientResultFuture {
+    if !self.device_map.contains_key(&device_index) {
+      return Box::pin(future::ready(Err(
+        ButtplugError::from(ButtplugDeviceError::DeviceNotAvailable(device_index.into())).into(),
+      )));
+    }
+    self.send_message_expect_ok(DisconnectDevice::new(device_index.into()).into())
+  }
+
   /// Tells server to stop all devices.
   ///
+  /// Device commands that haven't been sent yet, because they're waiting on
+  /// the [command rate limit][ButtplugClient::set_max_command_rate] or queued
+  /// while [paused][ButtplugClient::pause], are cancelled and fail with
+  /// [ButtplugClientError::CommandCancelled], so they can't start devices back
+  /// up after the stop.
+  ///
   /// Returns Err([ButtplugClientError]) if request fails due to issues with
   /// DeviceManagers on the server, disconnection, etc.
   pub fn stop_all_devices(&self) -> ButtplugClientResultFuture {
+    for device in self.device_map.iter() {
+      device.value().clear_command_dedup();
+    }
     self.send_message_expect_ok(StopAllDevices::default().into())
   }
 
+  /// Pauses the event loop without disconnecting, i.e. while a mobile app is
+  /// in the background.
+  ///
+  /// While paused:
+  ///
+  /// - Client events are held, and emitted in order on
+  ///   [resume][ButtplugClient::resume].
+  /// - Device commands are queued or dropped, depending on `policy`.
+  /// - Client messages (pings, scanning, [StopAllDevices], etc...) are still
+  ///   sent, so the connection stays up.
+  /// - Replies to commands sent before pausing still resolve their futures.
+  ///
+  /// If `stop_devices` is true, a [StopAllDevices] command is sent once the
+  /// loop is paused, and the returned future resolves after the server
+  /// replies to it. Pausing an already paused client updates the policy.
+  pub fn pause(
+    &self,
+    policy: ButtplugClientPausedCommandPolicy,
+    stop_devices: bool,
+  ) -> ButtplugClientResultFuture {
+    if !self.connected() {
+      return Box::pin(future::ready(Err(
+        ButtplugConnectorError::ConnectorNotConnected.into(),
+      )));
+    }
+    // Requests are handled in order, so the stop command is sent after the
+    // loop has paused, and device commands can't sneak in after it.
+    let send_fut = self.send_message_to_event_loop(ButtplugClientRequest::Pause(policy));
+    let stop_fut = if stop_devices {
+      Some(self.stop_all_devices())
+    } else {
+      None
+    };
+    let paused = self.paused.clone();
+    Box::pin(async move {
+      send_fut.await?;
+      paused.store(true, Ordering::SeqCst);
+      if let Some(stop_fut) = stop_fut {
+        stop_fut.await?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Resumes an event loop paused with [pause][ButtplugClient::pause]. Sends
+  /// any queued device commands, then emits any held events. Does nothing if
+  /// the client isn't paused.
+  pub fn resume(&self) -> ButtplugClientResultFuture {
+    if !self.connected() {
+      return Box::pin(future::ready(Err(
+        ButtplugConnectorError::ConnectorNotConnected.into(),
+      )));
+    }
+    let send_fut = self.send_message_to_event_loop(ButtplugClientRequest::Resume);
+    let paused = self.paused.clone();
+    Box::pin(async move {
+      send_fut.await?;
+      paused.store(false, Ordering::SeqCst);
+      Ok(())
+    })
+  }
+
+  /// Returns the number of requests sent to the server that are still waiting
+  /// on a reply.
+  ///
+  /// Useful for diagnosing lag. This should stay low, a count that keeps
+  /// growing means the server or its devices aren't keeping up. Returns 0 if
+  /// the client isn't connected.
+  pub fn pending_request_count(&self) -> usize {
+    if !self.connected() {
+      return 0;
+    }
+    self
+      .connection()
+      .map_or(0, |connection| connection.pending_request_count.load(Ordering::SeqCst))
+  }
+
+  /// Returns true if the client has been paused with
+  /// [pause][ButtplugClient::pause] and not resumed since.
+  pub fn paused(&self) -> bool {
+    self.paused.load(Ordering::SeqCst)
+  }
+
   pub fn event_stream(&self) -> impl Stream<Item = ButtplugClientEvent> {
     let stream = convert_broadcast_receiver_to_stream(self.event_stream.subscribe());
     // We can either Box::pin here or force the user to pin_mut!() on their
@@ -456,6 +1105,66 @@ impl ButtplugClient {
     Box::pin(stream)
   }
 
+  /// Returns a stream of battery readings for all connected devices that
+  /// support [BatteryLevelCmd][crate::core::messages::BatteryLevelCmd].
+  ///
+  /// Every `interval`, each device with battery support is queried and its
+  /// reading is yielded as a `(DeviceIndex, level)` pair. Devices that fail to
+  /// respond are skipped for that round. The stream ends once the client
+  /// disconnects, without waiting out the rest of the interval.
+  pub fn battery_stream(&self, interval: Duration) -> impl Stream<Item = (DeviceIndex, f64)> {
+    let device_map = self.device_map.clone();
+    let connected = self.connected.clone();
+    let mut event_receiver = self.event_stream.subscribe();
+    Box::pin(stream! {
+      while connected.load(Ordering::SeqCst) {
+        let mut devices: Vec<Arc<ButtplugClientDevice>> = device_map
+          .iter()
+          .map(|map_pair| map_pair.value().clone())
+          .filter(|device| {
+            device
+              .allowed_messages
+              .contains_key(&ButtplugCurrentSpecDeviceMessageType::BatteryLevelCmd)
+          })
+          .collect();
+        devices.sort_by_key(|device| device.add_sequence());
+        for device in devices {
+          if !connected.load(Ordering::SeqCst) {
+            return;
+          }
+          match device.battery_level().await {
+            Ok(level) => yield (device.device_index(), level),
+            Err(err) => debug!(
+              "Cannot get battery level for device {}, skipping: {:?}",
+              device.index(),
+              err
+            ),
+          }
+        }
+        let disconnected = async {
+          loop {
+            match event_receiver.recv().await {
+              Ok(ButtplugClientEvent::ServerDisconnect) | Err(RecvError::Closed) => return,
+              // We may have missed the disconnect, so check for it directly.
+              Err(RecvError::Lagged(_)) if !connected.load(Ordering::SeqCst) => return,
+              _ => {}
+            }
+          }
+        };
+        select! {
+          _ = Delay::new(interval).fuse() => {},
+          _ = disconnected.fuse() => return,
+        }
+      }
+    })
+  }
+
+  /// Returns the state shared with the event loop of the current (or most
+  /// recent) connection, or None if the client has never connected.
+  fn connection(&self) -> Option<ButtplugClientConnection> {
+    self.connection.lock().unwrap().clone()
+  }
+
   /// Send message to the internal event loop.
   ///
   /// Mostly for handling boilerplate around possible send errors.
@@ -463,19 +1172,20 @@ impl ButtplugClient {
     &self,
     msg: ButtplugClientRequest,
   ) -> BoxFuture<'static, Result<(), ButtplugClientError>> {
-    // If we're running the event loop, we should have a message_sender.
+    // If we're running the event loop, we should have a request sender.
     // Being connected to the server doesn't matter here yet because we use
     // this function in order to connect also.
     //
-    // The message sender doesn't require an async send now, but we still want
-    // to delay execution as part of our future in order to keep task coherency.
-    let message_sender = self.message_sender.clone();
-    Box::pin(async move {
-      message_sender
+    // Send right away instead of when the future is polled, so requests reach
+    // the event loop in the order they were made, same as device commands.
+    let result = match self.connection() {
+      Some(connection) => connection
+        .request_sender
         .send(msg)
-        .map_err(|_| ButtplugConnectorError::ConnectorChannelClosed)?;
-      Ok(())
-    })
+        .map_err(|_| ButtplugConnectorError::ConnectorChannelClosed.into()),
+      None => Err(ButtplugConnectorError::ConnectorChannelClosed.into()),
+    };
+    Box::pin(future::ready(result))
   }
 
   fn send_message(
@@ -497,19 +1207,12 @@ impl ButtplugClient {
     &self,
     msg: ButtplugCurrentSpecClientMessage,
   ) -> ButtplugServerMessageResultFuture {
-    // Create a future to pair with the message being resolved.
-    let fut = ButtplugServerMessageFuture::default();
-    let internal_msg = ButtplugClientRequest::Message(ButtplugClientMessageFuturePair::new(
-      msg,
-      fut.get_state_clone(),
-    ));
-
-    // Send message to internal loop and wait for return.
-    let send_fut = self.send_message_to_event_loop(internal_msg);
-    Box::pin(async move {
-      send_fut.await?;
-      fut.await
-    })
+    match self.connection() {
+      Some(connection) => connection.send_message(msg),
+      None => Box::pin(future::ready(Err(
+        ButtplugConnectorError::ConnectorChannelClosed.into(),
+      ))),
+    }
   }
 
   /// Sends a ButtplugMessage from client to server. Expects to receive an [Ok]
@@ -522,12 +1225,146 @@ impl ButtplugClient {
     Box::pin(async move { send_fut.await.map(|_| ()).map_err(|err| err) })
   }
 
-  /// Retreives a list of currently connected devices.
-  pub fn devices(&self) -> Vec<Arc<ButtplugClientDevice>> {
+  /// Sets the maximum number of devices the client will track, or None for no
+  /// limit (the default).
+  ///
+  /// Once the limit is reached, devices added by the server are rejected:
+  /// they are not added to the client, the server is asked to stop them, and
+  /// a [ButtplugClientEvent::DeviceRejected] event is emitted instead of
+  /// [ButtplugClientEvent::DeviceAdded]. A limit of 0 rejects every device.
+  /// Lowering the limit does not remove devices the client is already
+  /// tracking.
+  pub fn set_max_devices(&self, max_devices: Option<usize>) {
     self
+      .max_devices
+      .store(max_devices.unwrap_or(usize::MAX), Ordering::SeqCst);
+  }
+
+  /// Returns the maximum number of devices the client will track, or None if
+  /// there is no limit.
+  pub fn max_devices(&self) -> Option<usize> {
+    match self.max_devices.load(Ordering::SeqCst) {
+      usize::MAX => None,
+      max_devices => Some(max_devices),
+    }
+  }
+
+  /// Limits how many device commands per second the client sends, across all
+  /// devices, or removes the limit if None. Defaults to no limit.
+  ///
+  /// For radios where the total command rate matters, i.e. several BLE devices
+  /// on one adapter. Commands over the limit are held and sent in order as the
+  /// rate allows, so their futures resolve later, but none are dropped. Can be
+  /// changed while connected.
+  pub fn set_max_command_rate(&self, commands_per_second: Option<u32>) {
+    self
+      .max_command_rate
+      .store(commands_per_second.unwrap_or(0), Ordering::SeqCst);
+  }
+
+  /// Returns the maximum number of device commands per second the client will
+  /// send, or None if there is no limit.
+  pub fn max_command_rate(&self) -> Option<u32> {
+    match self.max_command_rate.load(Ordering::SeqCst) {
+      0 => None,
+      max_command_rate => Some(max_command_rate),
+    }
+  }
+
+  /// Sets the timeout for the event loop watchdog, or None to turn the
+  /// watchdog off (the default). Takes effect on the next connect.
+  ///
+  /// The client event loop is the only path commands take to the server. If
+  /// it stalls (i.e. the connector hangs, or a bug deadlocks it), devices that
+  /// are already running will keep running. With the watchdog on, a separate
+  /// task checks that the event loop is still ticking, and if it hasn't for
+  /// `timeout`, queues a [StopAllDevices] command and emits
+  /// [ButtplugClientEvent::EventLoopStalled].
+  ///
+  /// # Limitations
+  ///
+  /// The event loop owns the connector, so the stop command can only go out
+  /// once the loop starts processing again. This helps with stalls the loop
+  /// recovers from, but if the loop is deadlocked for good, the command is
+  /// never sent. For a hard guarantee that devices stop when the client stops
+  /// responding, set a max ping time on the server and
+  /// [ping][ButtplugClient::ping] regularly.
+  ///
+  /// Timeouts shorter than [MIN_WATCHDOG_TIMEOUT] are raised to it.
+  pub fn set_watchdog_timeout(&self, timeout: Option<Duration>) {
+    let timeout = timeout.map(|timeout| {
+      if timeout < MIN_WATCHDOG_TIMEOUT {
+        warn!(
+          "Watchdog timeout {:?} is too short, using {:?} instead.",
+          timeout, MIN_WATCHDOG_TIMEOUT
+        );
+        MIN_WATCHDOG_TIMEOUT
+      } else {
+        timeout
+      }
+    });
+    *self.watchdog_timeout.lock().unwrap() = timeout;
+  }
+
+  /// Returns the event loop watchdog timeout, or None if the watchdog is off.
+  pub fn watchdog_timeout(&self) -> Option<Duration> {
+    *self.watchdog_timeout.lock().unwrap()
+  }
+
+  /// Sets how the client handles messages from the server it doesn't know how
+  /// to handle. Defaults to [ButtplugClientUnknownMessagePolicy::Ignore].
+  pub fn set_unknown_message_policy(&self, policy: ButtplugClientUnknownMessagePolicy) {
+    *self.unknown_message_policy.lock().unwrap() = policy;
+  }
+
+  /// Returns how the client handles messages from the server it doesn't know
+  /// how to handle.
+  pub fn unknown_message_policy(&self) -> ButtplugClientUnknownMessagePolicy {
+    *self.unknown_message_policy.lock().unwrap()
+  }
+
+  /// Sets how the client reacts to the server reporting a ping timeout.
+  /// Defaults to [ButtplugClientPingTimeoutPolicy::Notify].
+  pub fn set_ping_timeout_policy(&self, policy: ButtplugClientPingTimeoutPolicy) {
+    *self.ping_timeout_policy.lock().unwrap() = policy;
+  }
+
+  /// Returns how the client reacts to the server reporting a ping timeout.
+  pub fn ping_timeout_policy(&self) -> ButtplugClientPingTimeoutPolicy {
+    *self.ping_timeout_policy.lock().unwrap()
+  }
+
+  /// Retreives a list of currently connected devices, in the order they were
+  /// added.
+  pub fn devices(&self) -> Vec<Arc<ButtplugClientDevice>> {
+    let mut devices: Vec<Arc<ButtplugClientDevice>> = self
       .device_map
       .iter()
       .map(|map_pair| map_pair.value().clone())
+      .collect();
+    devices.sort_by_key(|device| device.add_sequence());
+    devices
+  }
+
+  /// Returns the currently connected device with the given server index, if
+  /// there is one.
+  pub fn device(&self, device_index: DeviceIndex) -> Option<Arc<ButtplugClientDevice>> {
+    self
+      .device_map
+      .get(&device_index)
+      .map(|device| device.value().clone())
+  }
+
+  /// Returns snapshots of all devices the client currently knows about, in
+  /// the same order as [ButtplugClient::devices].
+  ///
+  /// Meant for diagnostics, i.e. letting users copy device information into a
+  /// bug report. See [ButtplugClientDeviceSnapshot].
+  pub fn device_snapshot(&self) -> Vec<ButtplugClientDeviceSnapshot> {
+    self
+      .devices()
+      .iter()
+      .map(|device| device.snapshot())
       .collect()
   }
 
@@ -536,6 +1373,42 @@ impl ButtplugClient {
     Box::pin(async move { ping_fut.await })
   }
 
+  /// Measures the round trip time to the server.
+  ///
+  /// Sends a [Ping] and times how long it takes for the server to reply, which
+  /// includes the time spent in the connector and transport, so this works
+  /// over any connector type. The result is also added to the samples used by
+  /// [ButtplugClient::average_latency].
+  pub fn measure_latency(&self) -> ButtplugClientResultFuture<Duration> {
+    // The ping goes out as soon as the future is created, so start timing
+    // before that happens.
+    let start = Instant::now();
+    let ping_fut = self.send_message_expect_ok(Ping::default().into());
+    let latency_samples = self.latency_samples.clone();
+    Box::pin(async move {
+      ping_fut.await?;
+      let latency = start.elapsed();
+      let mut samples = latency_samples.lock().unwrap();
+      if samples.len() == LATENCY_SAMPLE_WINDOW {
+        samples.pop_front();
+      }
+      samples.push_back(latency);
+      Ok(latency)
+    })
+  }
+
+  /// Returns the average of the last [LATENCY_SAMPLE_WINDOW] latency
+  /// measurements taken via [ButtplugClient::measure_latency] during the
+  /// current connection, or None if no measurements have been taken.
+  pub fn average_latency(&self) -> Option<Duration> {
+    let samples = self.latency_samples.lock().unwrap();
+    if samples.is_empty() {
+      None
+    } else {
+      Some(samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
+  }
+
   pub fn server_name(&self) -> Option<String> {
     // We'd have to be calling server_name in an extremely tight, asynchronous
     // loop for this to return None, so we'll treat this as lockless.
@@ -549,4 +1422,57 @@ impl ButtplugClient {
       None
     }
   }
+
+  /// Returns the optional capabilities the server reported during the
+  /// handshake. Empty while disconnected, and for servers that don't report
+  /// capabilities.
+  pub fn server_capabilities(&self) -> ButtplugServerCapabilities {
+    // The server may have gone away without us calling disconnect, in which
+    // case whatever it reported no longer applies.
+    if !self.connected() {
+      return ButtplugServerCapabilities::default();
+    }
+    self.server_capabilities.lock().unwrap().clone()
+  }
+
+  /// Returns a handle to the task running the event loop for the current (or
+  /// most recent) connection, or None if the client has never connected.
+  ///
+  /// Embedders can use this to supervise the event loop, i.e. to detect it
+  /// exiting unexpectedly, or to stop it. Aborting the event loop effectively
+  /// disconnects the client, see [ButtplugClientEventLoopHandle] for details.
+  pub fn event_loop_handle(&self) -> Option<ButtplugClientEventLoopHandle> {
+    // Only written during connect, so treat this as lockless, same as
+    // server_name.
+    if let Ok(handle) = self.event_loop_handle.try_lock() {
+      handle.clone()
+    } else {
+      None
+    }
+  }
+
+  /// Returns the names of the device communication managers that were
+  /// successfully initialized by [ButtplugClient::connect_in_process].
+  ///
+  /// Will be empty if the client was connected via
+  /// [connect][ButtplugClient::connect] instead.
+  pub fn in_process_comm_managers(&self) -> Vec<String> {
+    // Only written during connect_in_process, so treat this as lockless, same
+    // as server_name.
+    if let Ok(managers) = self.in_process_comm_managers.try_lock() {
+      managers.clone()
+    } else {
+      vec![]
+    }
+  }
+}
+
+#[cfg(feature = "server")]
+fn add_in_process_comm_manager<T>(device_manager: &DeviceManager, builder: T)
+where
+  T: DeviceCommunicationManagerBuilder,
+{
+  if let Err(e) = device_manager.add_comm_manager(builder) {
+    warn!("Device communication manager failed to initialize: {}", e);
+  }
 }
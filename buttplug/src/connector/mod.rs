@@ -95,6 +95,7 @@ use crate::{
 };
 use displaydoc::Display;
 use futures::future::{self, BoxFuture};
+use std::sync::{atomic::AtomicBool, Arc};
 use thiserror::Error;
 use tokio::sync::mpsc::Sender;
 
@@ -116,10 +117,12 @@ pub enum ButtplugConnectorError {
   ConnectorChannelClosed,
   /// Connector already connected, cannot be connected twice.
   ConnectorAlreadyConnected,
+  /// Connection attempt was cancelled.
+  ConnectorCancelled,
   /// Connector error: {0}
   ConnectorGenericError(String),
   /// Specific error for connector type: {0}.
-  TransportSpecificError(transport::ButtplugConnectorTransportSpecificError),
+  TransportSpecificError(#[source] transport::ButtplugConnectorTransportSpecificError),
 }
 
 impl<T> From<ButtplugConnectorError> for BoxFuture<'static, Result<T, ButtplugConnectorError>>
@@ -187,4 +190,10 @@ where
   /// If the connector is not currently connected, or an error happens during
   /// the send operation, this will return a [ButtplugConnectorError]
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture;
+  /// Returns a flag tracking whether the connector's underlying transport is
+  /// alive, or None if the connector has no transport (i.e. in-process
+  /// connectors) or the transport doesn't track its liveness.
+  fn transport_connected_status(&self) -> Option<Arc<AtomicBool>> {
+    None
+  }
 }
@@ -20,7 +20,10 @@ use crate::{
   util::async_manager,
 };
 use futures::{future::BoxFuture, FutureExt};
-use std::marker::PhantomData;
+use std::{
+  marker::PhantomData,
+  sync::{atomic::AtomicBool, Arc},
+};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 enum ButtplugRemoteConnectorMessage<T>
@@ -196,6 +199,9 @@ pub struct ButtplugRemoteConnector<
   transport: Option<TransportType>,
   /// Sender for forwarding outgoing messages to the connector event loop.
   event_loop_sender: Option<Sender<ButtplugRemoteConnectorMessage<OutboundMessageType>>>,
+  /// Liveness flag of the transport, if it reports one. Held separately since
+  /// the transport moves into the event loop on connect.
+  transport_connected_status: Option<Arc<AtomicBool>>,
   dummy_serializer: PhantomData<SerializerType>,
 }
 
@@ -210,6 +216,7 @@ where
 {
   pub fn new(transport: TransportType) -> Self {
     Self {
+      transport_connected_status: transport.connected_status(),
       transport: Some(transport),
       event_loop_sender: None,
       dummy_serializer: PhantomData::default(),
@@ -291,6 +298,10 @@ where
     }
   }
 
+  fn transport_connected_status(&self) -> Option<Arc<AtomicBool>> {
+    self.transport_connected_status.clone()
+  }
+
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture {
     if let Some(ref sender) = self.event_loop_sender {
       let sender_clone = sender.clone();
@@ -1,9 +1,17 @@
+mod recorder;
 #[cfg(feature = "websockets")]
 mod websocket;
 use crate::connector::{
   ButtplugConnectorError, ButtplugConnectorResultFuture, ButtplugSerializedMessage,
 };
 use futures::future::BoxFuture;
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+pub use recorder::{
+  ButtplugTransportDirection, ButtplugTransportRecordEntry, ButtplugTransportRecorder,
+};
 use tokio::sync::mpsc::{Receiver, Sender};
 #[cfg(feature = "websockets")]
 pub use websocket::{ButtplugWebsocketClientTransport, TungsteniteError, ButtplugWebsocketServerTransport, ButtplugWebsocketServerTransportBuilder};
@@ -31,6 +39,32 @@ pub trait ButtplugConnectorTransport: Send + Sync {
     incoming_sender: Sender<ButtplugTransportIncomingMessage>,
   ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>>;
   fn disconnect(self) -> ButtplugConnectorResultFuture;
+  /// Returns a flag the transport keeps up to date with whether its
+  /// connection is alive, or None if the transport doesn't track this.
+  ///
+  /// This is the transport's own view of the link (i.e. whether the socket is
+  /// still open and the remote side is responding), separate from whether the
+  /// Buttplug protocol considers itself connected.
+  fn connected_status(&self) -> Option<Arc<AtomicBool>> {
+    None
+  }
+}
+
+/// Marks a transport connected while held, and disconnected once dropped, so
+/// transport I/O loops can't exit without updating their status.
+pub(super) struct ButtplugTransportConnectedGuard(Arc<AtomicBool>);
+
+impl ButtplugTransportConnectedGuard {
+  pub(super) fn new(connected: Arc<AtomicBool>) -> Self {
+    connected.store(true, Ordering::SeqCst);
+    Self(connected)
+  }
+}
+
+impl Drop for ButtplugTransportConnectedGuard {
+  fn drop(&mut self) {
+    self.0.store(false, Ordering::SeqCst);
+  }
 }
 
 #[derive(Error, Debug)]
@@ -40,4 +74,6 @@ pub enum ButtplugConnectorTransportSpecificError {
   TungsteniteError(#[from] TungsteniteError),
   #[error("Network error: {0}")]
   GenericNetworkError(String),
+  #[error("IO error: {0}")]
+  IoError(#[from] std::io::Error),
 }
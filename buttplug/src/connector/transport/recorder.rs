@@ -0,0 +1,292 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Recording of raw serialized messages going through a transport.
+//!
+//! Recordings are plain text, one message per line, in the format
+//!
+//! ```text
+//! <microseconds since unix epoch> <out|in> <text|binary> <payload>
+//! ```
+//!
+//! Text payloads are written as JSON string literals (so they always fit on
+//! one line), binary payloads as lowercase hex. Use
+//! [ButtplugTransportRecorder::read_recording] to load a recording back for
+//! replay.
+
+use crate::core::messages::serializer::ButtplugSerializedMessage;
+use std::{
+  fmt,
+  fs::File,
+  io::{self, BufRead, BufWriter, Write},
+  path::Path,
+  sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+  thread,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Number of recorded lines that can be waiting on the writer thread before
+/// new ones are dropped.
+const RECORDER_BUFFER_SIZE: usize = 1024;
+
+enum RecorderCommand {
+  Line(String),
+  Flush(SyncSender<()>),
+}
+
+/// Direction a recorded message was travelling in, relative to the side of
+/// the connection doing the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtplugTransportDirection {
+  /// Sent from this side to the remote side.
+  Outgoing,
+  /// Received from the remote side.
+  Incoming,
+}
+
+/// A single message read back from a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButtplugTransportRecordEntry {
+  /// Time the message was recorded, since the unix epoch.
+  pub timestamp: Duration,
+  pub direction: ButtplugTransportDirection,
+  pub message: ButtplugSerializedMessage,
+}
+
+/// Writes every raw serialized message a transport sends or receives to a
+/// file or other [Write] implementation, for protocol debugging and bug
+/// reports.
+///
+/// Recorders are cheap to clone, and clones write to the same output, so one
+/// recorder can be shared between transports. Writing happens on a separate
+/// thread, so recording never blocks the transport. Failing to write a
+/// message, or falling too far behind, is logged, but doesn't affect the
+/// connection.
+#[derive(Clone)]
+pub struct ButtplugTransportRecorder {
+  sender: SyncSender<RecorderCommand>,
+}
+
+impl fmt::Debug for ButtplugTransportRecorder {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ButtplugTransportRecorder").finish()
+  }
+}
+
+impl ButtplugTransportRecorder {
+  /// Creates a recorder that writes to `writer`. The writer is flushed
+  /// whenever the recorder has caught up with the transport, so buffered
+  /// writers are fine to use.
+  pub fn new<T>(writer: T) -> Self
+  where
+    T: Write + Send + 'static,
+  {
+    let (sender, receiver) = mpsc::sync_channel(RECORDER_BUFFER_SIZE);
+    thread::Builder::new()
+      .name("Transport Recorder Thread".to_string())
+      .spawn(move || {
+        recorder_write_thread(writer, receiver);
+      })
+      .unwrap();
+    Self { sender }
+  }
+
+  /// Creates a recorder that writes to the file at `path`, replacing it if it
+  /// already exists.
+  pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    Ok(Self::new(BufWriter::new(File::create(path)?)))
+  }
+
+  /// Records a message travelling in `direction`.
+  pub fn record(&self, direction: ButtplugTransportDirection, msg: &ButtplugSerializedMessage) {
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_micros();
+    let direction = match direction {
+      ButtplugTransportDirection::Outgoing => "out",
+      ButtplugTransportDirection::Incoming => "in",
+    };
+    let line = match msg {
+      ButtplugSerializedMessage::Text(text) => format!(
+        "{} {} text {}\n",
+        timestamp,
+        direction,
+        // Serializing a string can't fail.
+        serde_json::to_string(text).unwrap()
+      ),
+      ButtplugSerializedMessage::Binary(data) => format!(
+        "{} {} binary {}\n",
+        timestamp,
+        direction,
+        data.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+      ),
+    };
+    match self.sender.try_send(RecorderCommand::Line(line)) {
+      Ok(()) => {}
+      Err(TrySendError::Full(_)) => {
+        warn!("Transport recording can't keep up, dropping message.");
+      }
+      Err(TrySendError::Disconnected(_)) => {
+        warn!("Transport recording writer has stopped, dropping message.");
+      }
+    }
+  }
+
+  /// Blocks until every message recorded so far has been written and the
+  /// writer flushed. Not meant to be called from async code, i.e. use it
+  /// before reading back a recording once the transport is done.
+  pub fn flush(&self) {
+    let (done_sender, done_receiver) = mpsc::sync_channel(1);
+    let flush = RecorderCommand::Flush(done_sender);
+    if self.sender.send(flush).is_ok() {
+      let _ = done_receiver.recv();
+    }
+  }
+
+  /// Reads a recording made by a [ButtplugTransportRecorder] back into
+  /// entries, in the order they were recorded. Blank lines are skipped.
+  pub fn read_recording<R: BufRead>(reader: R) -> io::Result<Vec<ButtplugTransportRecordEntry>> {
+    let mut entries = vec![];
+    for (line_number, line) in reader.lines().enumerate() {
+      let line = line?;
+      if line.trim().is_empty() {
+        continue;
+      }
+      let entry = parse_record_line(&line).ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!("Invalid transport recording line {}: {}", line_number + 1, line),
+        )
+      })?;
+      entries.push(entry);
+    }
+    Ok(entries)
+  }
+}
+
+/// Writes recorded lines until every recorder clone is dropped, flushing
+/// whenever there's nothing left to write, instead of after every line.
+fn recorder_write_thread(mut writer: impl Write, receiver: Receiver<RecorderCommand>) {
+  let mut next = receiver.recv().ok();
+  while let Some(command) = next {
+    match command {
+      RecorderCommand::Line(line) => {
+        if let Err(e) = writer.write_all(line.as_bytes()) {
+          warn!("Cannot write to transport recording: {:?}", e);
+        }
+      }
+      RecorderCommand::Flush(done_sender) => {
+        if let Err(e) = writer.flush() {
+          warn!("Cannot flush transport recording: {:?}", e);
+        }
+        let _ = done_sender.send(());
+      }
+    }
+    next = match receiver.try_recv() {
+      Ok(command) => Some(command),
+      Err(_) => {
+        if let Err(e) = writer.flush() {
+          warn!("Cannot flush transport recording: {:?}", e);
+        }
+        receiver.recv().ok()
+      }
+    };
+  }
+}
+
+fn parse_record_line(line: &str) -> Option<ButtplugTransportRecordEntry> {
+  let mut parts = line.splitn(4, ' ');
+  let timestamp = Duration::from_micros(parts.next()?.parse().ok()?);
+  let direction = match parts.next()? {
+    "out" => ButtplugTransportDirection::Outgoing,
+    "in" => ButtplugTransportDirection::Incoming,
+    _ => return None,
+  };
+  let kind = parts.next()?;
+  let payload = parts.next()?;
+  let message = match kind {
+    "text" => ButtplugSerializedMessage::Text(serde_json::from_str(payload).ok()?),
+    "binary" => {
+      if payload.len() % 2 != 0 {
+        return None;
+      }
+      let data = (0..payload.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(payload.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+      ButtplugSerializedMessage::Binary(data)
+    }
+    _ => return None,
+  };
+  Some(ButtplugTransportRecordEntry {
+    timestamp,
+    direction,
+    message,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::sync::{Arc, Mutex};
+
+  #[derive(Clone, Default)]
+  struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn test_transport_recording_round_trip() {
+    let buffer = SharedBuffer::default();
+    let recorder = ButtplugTransportRecorder::new(buffer.clone());
+    let messages = vec![
+      (
+        ButtplugTransportDirection::Outgoing,
+        ButtplugSerializedMessage::Text("[{\"Ping\":{\"Id\":1}}]".to_owned()),
+      ),
+      (
+        ButtplugTransportDirection::Incoming,
+        ButtplugSerializedMessage::Text("line one\nline two".to_owned()),
+      ),
+      (
+        ButtplugTransportDirection::Incoming,
+        ButtplugSerializedMessage::Binary(vec![0x00, 0x7f, 0xff]),
+      ),
+    ];
+    for (direction, msg) in &messages {
+      recorder.record(*direction, msg);
+    }
+    recorder.flush();
+    let recording = buffer.0.lock().unwrap().clone();
+    // One line per message, no matter what's in the payload.
+    assert_eq!(recording.iter().filter(|b| **b == b'\n').count(), 3);
+    let entries = ButtplugTransportRecorder::read_recording(&recording[..]).unwrap();
+    assert_eq!(
+      entries
+        .iter()
+        .map(|e| (e.direction, e.message.clone()))
+        .collect::<Vec<_>>(),
+      messages
+    );
+    assert!(entries.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+  }
+
+  #[test]
+  fn test_transport_recording_invalid_line() {
+    let recording = "12 sideways text \"hi\"\n";
+    assert!(ButtplugTransportRecorder::read_recording(recording.as_bytes()).is_err());
+  }
+}
@@ -11,7 +11,8 @@ use crate::{
   connector::{
     transport::{
       ButtplugConnectorTransport, ButtplugConnectorTransportSpecificError,
-      ButtplugTransportIncomingMessage,
+      ButtplugTransportConnectedGuard, ButtplugTransportDirection,
+      ButtplugTransportIncomingMessage, ButtplugTransportRecorder,
     },
     ButtplugConnectorError, ButtplugConnectorResultFuture,
   },
@@ -20,7 +21,7 @@ use crate::{
 };
 use async_tungstenite::{tokio::connect_async_with_tls_connector, tungstenite::protocol::Message};
 use futures::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
-use std::sync::Arc;
+use std::sync::{atomic::AtomicBool, Arc};
 use tokio::sync::{
   mpsc::{Receiver, Sender},
   Notify,
@@ -38,6 +39,10 @@ pub struct ButtplugWebsocketClientTransport {
   bypass_cert_verify: bool,
   /// Internally held sender, used for when disconnect is called.
   disconnect_notifier: Arc<Notify>,
+  /// If set, every message sent or received is written to this recorder.
+  recorder: Option<ButtplugTransportRecorder>,
+  /// True while the websocket I/O task is running.
+  connected: Arc<AtomicBool>,
 }
 
 impl ButtplugWebsocketClientTransport {
@@ -47,6 +52,8 @@ impl ButtplugWebsocketClientTransport {
       address: address.to_owned(),
       bypass_cert_verify,
       disconnect_notifier: Arc::new(Notify::new()),
+      recorder: None,
+      connected: Arc::new(AtomicBool::new(false)),
     }
   }
 
@@ -69,6 +76,14 @@ impl ButtplugWebsocketClientTransport {
   pub fn new_secure_connector(address: &str, bypass_cert_verify: bool) -> Self {
     ButtplugWebsocketClientTransport::create(address, true, bypass_cert_verify)
   }
+
+  /// Records every serialized message sent or received over the connection,
+  /// for debugging, or stops recording if None. Off by default. Must be set
+  /// before connecting.
+  pub fn recorder(&mut self, recorder: Option<ButtplugTransportRecorder>) -> &mut Self {
+    self.recorder = recorder;
+    self
+  }
 }
 
 impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
@@ -101,6 +116,8 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
       None
     };
     let address = self.address.clone();
+    let recorder = self.recorder.clone();
+    let connected = self.connected.clone();
 
     Box::pin(async move {
       match connect_async_with_tls_connector(&address, tls_connector).await {
@@ -109,10 +126,14 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
 
           async_manager::spawn(
             async move {
+              let _connected_guard = ButtplugTransportConnectedGuard::new(connected);
               loop {
                 select! {
                   msg = outgoing_receiver.recv().fuse() => {
                     if let Some(msg) = msg {
+                      if let Some(recorder) = &recorder {
+                        recorder.record(ButtplugTransportDirection::Outgoing, &msg);
+                      }
                       let out_msg = match msg {
                         ButtplugSerializedMessage::Text(text) => Message::Text(text),
                         ButtplugSerializedMessage::Binary(bin) => Message::Binary(bin),
@@ -135,10 +156,12 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
                     match response.unwrap() {
                       Ok(msg) => match msg {
                         Message::Text(t) => {
+                          let msg = ButtplugSerializedMessage::Text(t.to_string());
+                          if let Some(recorder) = &recorder {
+                            recorder.record(ButtplugTransportDirection::Incoming, &msg);
+                          }
                           if incoming_sender
-                            .send(ButtplugTransportIncomingMessage::Message(
-                              ButtplugSerializedMessage::Text(t.to_string()),
-                            ))
+                            .send(ButtplugTransportIncomingMessage::Message(msg))
                             .await
                             .is_err()
                           {
@@ -147,10 +170,12 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
                           }
                         }
                         Message::Binary(v) => {
+                          let msg = ButtplugSerializedMessage::Binary(v);
+                          if let Some(recorder) = &recorder {
+                            recorder.record(ButtplugTransportDirection::Incoming, &msg);
+                          }
                           if incoming_sender
-                            .send(ButtplugTransportIncomingMessage::Message(
-                              ButtplugSerializedMessage::Binary(v),
-                            ))
+                            .send(ButtplugTransportIncomingMessage::Message(msg))
                             .await
                             .is_err()
                           {
@@ -208,4 +233,8 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
       Ok(())
     })
   }
+
+  fn connected_status(&self) -> Option<Arc<AtomicBool>> {
+    Some(self.connected.clone())
+  }
 }
@@ -2,38 +2,65 @@ use crate::{
   connector::{
     transport::{
       ButtplugConnectorTransport, ButtplugConnectorTransportSpecificError,
-      ButtplugTransportIncomingMessage,
+      ButtplugTransportConnectedGuard, ButtplugTransportDirection,
+      ButtplugTransportIncomingMessage, ButtplugTransportRecorder,
     },
     ButtplugConnectorError, ButtplugConnectorResultFuture,
   },
   core::messages::serializer::ButtplugSerializedMessage,
-  util::async_manager,
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
 };
 use futures_timer::Delay;
-use futures::{future::BoxFuture, AsyncRead, AsyncWrite, FutureExt, SinkExt, StreamExt};
+use futures::{
+  future::BoxFuture, AsyncRead, AsyncWrite, Future, FutureExt, SinkExt, Stream, StreamExt,
+};
 use std::{
-  sync::Arc,
+  net::SocketAddr,
+  sync::{atomic::AtomicBool, Arc},
   time::Duration
 };
-use tokio::net::TcpListener;
+use tokio::net::TcpSocket;
 use tokio::sync::{
+  broadcast,
   mpsc::{Receiver, Sender},
-  Mutex, Notify,
+  watch, Mutex, Notify,
 };
 
+/// Maximum size of a websocket ping payload, per RFC 6455 (control frame
+/// payloads can be at most 125 bytes).
+pub const MAX_WEBSOCKET_PING_PAYLOAD_LENGTH: usize = 125;
+
 #[derive(Clone, Debug)]
 pub struct ButtplugWebsocketServerTransportBuilder {
   /// If true, listens all on available interfaces. Otherwise, only listens on 127.0.0.1.
   listen_on_all_interfaces: bool,
-  /// Insecure port for listening for websocket connections.
+  /// Insecure port for listening for websocket connections. If 0, the OS
+  /// picks a free port, which can be retrieved via
+  /// [ButtplugWebsocketServerTransport::bound_port].
   port: u16,
+  /// If true, sets TCP_NODELAY on accepted connections, turning off Nagle
+  /// buffering so commands go out as soon as they're sent.
+  tcp_nodelay: bool,
+  /// If true, sets SO_REUSEADDR on the listening socket, so the server can be
+  /// restarted on the same port while old connections are in TIME_WAIT.
+  reuse_address: bool,
+  /// Payload sent with each websocket ping frame.
+  ping_payload: Vec<u8>,
+  /// If set, every message sent or received is written to this recorder.
+  recorder: Option<ButtplugTransportRecorder>,
 }
 
 impl Default for ButtplugWebsocketServerTransportBuilder {
   fn default() -> Self {
     Self {
       listen_on_all_interfaces: false,
-      port: 12345
+      port: 12345,
+      tcp_nodelay: true,
+      // Matches what tokio does for TcpListener::bind(). On Windows,
+      // SO_REUSEADDR allows other processes to steal the port, so it's off.
+      reuse_address: !cfg!(windows),
+      ping_payload: vec![0],
+      recorder: None,
     }
   }
 }
@@ -49,24 +76,102 @@ impl ButtplugWebsocketServerTransportBuilder {
     self
   }
 
+  /// Sets whether TCP_NODELAY is set on accepted connections. When on, Nagle
+  /// buffering is turned off, so small messages like device commands go out
+  /// as soon as they're sent instead of being held to batch with later
+  /// writes. Defaults to true.
+  pub fn tcp_nodelay(&mut self, tcp_nodelay: bool) -> &mut Self {
+    self.tcp_nodelay = tcp_nodelay;
+    self
+  }
+
+  /// Sets whether SO_REUSEADDR is set on the listening socket. When on, the
+  /// server can be restarted on the same port while connections from the
+  /// last run are still in TIME_WAIT. Defaults to true everywhere but
+  /// Windows, where SO_REUSEADDR lets other processes steal the port.
+  pub fn reuse_address(&mut self, reuse_address: bool) -> &mut Self {
+    self.reuse_address = reuse_address;
+    self
+  }
+
+  /// Sets the payload sent with the websocket ping frames the server uses to
+  /// check that the client is still alive. Clients echo this back in their
+  /// pong frames, which can be watched via
+  /// [ButtplugWebsocketServerTransport::pong_stream], so this can be used for
+  /// lightweight app level keepalive data. Defaults to a single 0 byte.
+  ///
+  /// Payloads longer than [MAX_WEBSOCKET_PING_PAYLOAD_LENGTH] are not allowed
+  /// by the websocket spec, and will be truncated.
+  pub fn ping_payload(&mut self, ping_payload: &[u8]) -> &mut Self {
+    if ping_payload.len() > MAX_WEBSOCKET_PING_PAYLOAD_LENGTH {
+      warn!(
+        "Websocket ping payload is {} bytes, truncating to {} bytes.",
+        ping_payload.len(),
+        MAX_WEBSOCKET_PING_PAYLOAD_LENGTH
+      );
+    }
+    let length = ping_payload.len().min(MAX_WEBSOCKET_PING_PAYLOAD_LENGTH);
+    self.ping_payload = ping_payload[..length].to_vec();
+    self
+  }
+
+  /// Records every serialized message sent or received over the connection,
+  /// for debugging, or stops recording if None. Off by default.
+  pub fn recorder(&mut self, recorder: Option<ButtplugTransportRecorder>) -> &mut Self {
+    self.recorder = recorder;
+    self
+  }
+
   pub fn finish(&self) -> ButtplugWebsocketServerTransport {
+    let (pong_sender, _) = broadcast::channel(256);
+    let (bound_port_sender, bound_port_receiver) = watch::channel(None);
     ButtplugWebsocketServerTransport {
       port: self.port,
       listen_on_all_interfaces: self.listen_on_all_interfaces,
+      tcp_nodelay: self.tcp_nodelay,
+      reuse_address: self.reuse_address,
+      ping_payload: self.ping_payload.clone(),
+      pong_sender,
       disconnect_notifier: Arc::new(Notify::new()),
+      recorder: self.recorder.clone(),
+      connected: Arc::new(AtomicBool::new(false)),
+      bound_port_sender: std::sync::Mutex::new(Some(bound_port_sender)),
+      bound_port_receiver,
     }
   }
 }
 
+/// Per-connection settings handed from the transport to its connection loop.
+struct ConnectionLoopOptions {
+  /// Payload sent with each websocket ping frame.
+  ping_payload: Vec<u8>,
+  /// Relays the payloads of pong frames received from the client.
+  pong_sender: broadcast::Sender<Vec<u8>>,
+  /// If set, every message sent or received is written to this recorder.
+  recorder: Option<ButtplugTransportRecorder>,
+  /// Set for as long as the connection loop is running.
+  connected: Arc<AtomicBool>,
+}
+
 async fn run_connection_loop<S>(
   ws_stream: async_tungstenite::WebSocketStream<S>,
   mut request_receiver: Receiver<ButtplugSerializedMessage>,
   response_sender: Sender<ButtplugTransportIncomingMessage>,
   disconnect_notifier: Arc<Notify>,
+  options: ConnectionLoopOptions,
 ) where
   S: AsyncRead + AsyncWrite + Unpin,
 {
   info!("Starting websocket server connection event loop.");
+  let ConnectionLoopOptions {
+    ping_payload,
+    pong_sender,
+    recorder,
+    connected,
+  } = options;
+  // The connection is considered alive for as long as we're in this loop,
+  // which we leave as soon as the client misses a pong.
+  let _connected_guard = ButtplugTransportConnectedGuard::new(connected);
 
   let (mut websocket_server_sender, mut websocket_server_receiver) = ws_stream.split();
 
@@ -90,7 +195,7 @@ async fn run_connection_loop<S>(
         }
         pong_count = 0;
         if websocket_server_sender
-          .send(async_tungstenite::tungstenite::Message::Ping(vec!(0)))
+          .send(async_tungstenite::tungstenite::Message::Ping(ping_payload.clone()))
           .await
           .is_err() {
           error!("Cannot send ping to client, considering connection closed.");
@@ -100,6 +205,9 @@ async fn run_connection_loop<S>(
       },
       serialized_msg = request_receiver.recv().fuse() => {
         if let Some(serialized_msg) = serialized_msg {
+          if let Some(recorder) = &recorder {
+            recorder.record(ButtplugTransportDirection::Outgoing, &serialized_msg);
+          }
           match serialized_msg {
             ButtplugSerializedMessage::Text(text_msg) => {
               if websocket_server_sender
@@ -136,7 +244,11 @@ async fn run_connection_loop<S>(
               match msg {
                 async_tungstenite::tungstenite::Message::Text(text_msg) => {
                   trace!("Got text: {}", text_msg);
-                  if response_sender.send(ButtplugTransportIncomingMessage::Message(ButtplugSerializedMessage::Text(text_msg))).await.is_err() {
+                  let serialized_msg = ButtplugSerializedMessage::Text(text_msg);
+                  if let Some(recorder) = &recorder {
+                    recorder.record(ButtplugTransportDirection::Incoming, &serialized_msg);
+                  }
+                  if response_sender.send(ButtplugTransportIncomingMessage::Message(serialized_msg)).await.is_err() {
                     error!("Connector that owns transport no longer available, exiting.");
                     break;
                   }
@@ -149,9 +261,10 @@ async fn run_connection_loop<S>(
                   // noop
                   continue;
                 }
-                async_tungstenite::tungstenite::Message::Pong(_) => {
-                  // noop
+                async_tungstenite::tungstenite::Message::Pong(payload) => {
                   pong_count += 1;
+                  // Nobody may be listening for pongs, which is fine.
+                  let _ = pong_sender.send(payload);
                   continue;
                 }
                 async_tungstenite::tungstenite::Message::Binary(_) => {
@@ -175,11 +288,69 @@ async fn run_connection_loop<S>(
   }
 }
 
+fn bind_listener(addr: &str, reuse_address: bool) -> std::io::Result<tokio::net::TcpListener> {
+  let socket_addr: SocketAddr = addr
+    .parse()
+    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+  let socket = if socket_addr.is_ipv4() {
+    TcpSocket::new_v4()?
+  } else {
+    TcpSocket::new_v6()?
+  };
+  socket.set_reuseaddr(reuse_address)?;
+  socket.bind(socket_addr)?;
+  debug!("Websocket Insecure: Socket bound.");
+  socket.listen(1024)
+}
+
 /// Websocket connector for ButtplugClients, using [async_tungstenite]
 pub struct ButtplugWebsocketServerTransport {
   port: u16,
   listen_on_all_interfaces: bool,
+  tcp_nodelay: bool,
+  reuse_address: bool,
+  ping_payload: Vec<u8>,
+  pong_sender: broadcast::Sender<Vec<u8>>,
   disconnect_notifier: Arc<Notify>,
+  recorder: Option<ButtplugTransportRecorder>,
+  connected: Arc<AtomicBool>,
+  // Taken by connect(), so that if binding fails the sender is dropped and
+  // anyone waiting on bound_port() gets None instead of waiting forever.
+  bound_port_sender: std::sync::Mutex<Option<watch::Sender<Option<u16>>>>,
+  bound_port_receiver: watch::Receiver<Option<u16>>,
+}
+
+impl ButtplugWebsocketServerTransport {
+  /// Returns a stream of the payloads of pong frames received from the
+  /// client. Since the transport is moved into a connector when used, this
+  /// should be called before handing the transport off.
+  pub fn pong_stream(&self) -> impl Stream<Item = Vec<u8>> {
+    convert_broadcast_receiver_to_stream(self.pong_sender.subscribe())
+  }
+
+  /// Returns a future that resolves to the port the transport is listening
+  /// on, once it has bound its socket. This is mostly useful when the
+  /// transport was built with port 0, in which case the OS picks a free port
+  /// and this is the only way to find out which one. Since the transport is
+  /// moved into a connector when used, this should be called before handing
+  /// the transport off.
+  ///
+  /// Resolves to None if binding fails, or if the transport is dropped before
+  /// it connects.
+  pub fn bound_port(&self) -> impl Future<Output = Option<u16>> {
+    let mut receiver = self.bound_port_receiver.clone();
+    async move {
+      loop {
+        let port = *receiver.borrow();
+        if port.is_some() {
+          return port;
+        }
+        if receiver.changed().await.is_err() {
+          return None;
+        }
+      }
+    }
+  }
 }
 
 impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
@@ -203,18 +374,36 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
     let request_receiver_clone = request_receiver;
     let response_sender_clone = incoming_sender;
     let disconnect_notifier_clone = disconnect_notifier;
+    let tcp_nodelay = self.tcp_nodelay;
+    let reuse_address = self.reuse_address;
+    let ping_payload = self.ping_payload.clone();
+    let pong_sender = self.pong_sender.clone();
+    let recorder = self.recorder.clone();
+    let connected = self.connected.clone();
+    let bound_port_sender = self.bound_port_sender.lock().unwrap().take();
     let fut = async move {
       // Create the event loop and TCP listener we'll accept connections on.
-      let try_socket = TcpListener::bind(&addr).await;
-      debug!("Websocket Insecure: Socket bound.");
-      let listener = try_socket.map_err(|e| {
+      let listener = bind_listener(&addr, reuse_address).map_err(|e| {
         ButtplugConnectorError::TransportSpecificError(
-          ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+          ButtplugConnectorTransportSpecificError::IoError(e),
         )
       })?;
-      debug!("Websocket Insecure: Listening on: {}", addr);
+      let local_addr = listener.local_addr().map_err(|e| {
+        ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::IoError(e),
+        )
+      })?;
+      debug!("Websocket Insecure: Listening on: {}", local_addr);
+      if let Some(bound_port_sender) = bound_port_sender {
+        // Nobody may be waiting on the port, which is fine.
+        let _ = bound_port_sender.send(Some(local_addr.port()));
+      }
       if let Ok((stream, _)) = listener.accept().await {
         info!("Websocket Insecure: Got connection");
+        if let Err(e) = stream.set_nodelay(tcp_nodelay) {
+          // Not fatal, we'll just have a little more latency.
+          warn!("Websocket Insecure: Cannot set TCP_NODELAY: {:?}", e);
+        }
         let ws_fut = async_tungstenite::tokio::accept_async(stream);
         let ws_stream = ws_fut.await.map_err(|err| {
           error!("Websocket server accept error: {:?}", err);
@@ -228,6 +417,12 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
             (*request_receiver_clone.lock().await).take().unwrap(),
             response_sender_clone,
             disconnect_notifier_clone,
+            ConnectionLoopOptions {
+              ping_payload,
+              pong_sender,
+              recorder,
+              connected,
+            },
           )
           .await;
         })
@@ -253,4 +448,8 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
       Ok(())
     })
   }
+
+  fn connected_status(&self) -> Option<Arc<AtomicBool>> {
+    Some(self.connected.clone())
+  }
 }
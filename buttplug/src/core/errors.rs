@@ -43,6 +43,8 @@ pub enum ButtplugHandshakeError {
   HandshakeAlreadyHappened,
   /// Server spec version ({0}) must be equal or greater than client version ({1})
   MessageSpecVersionMismatch(ButtplugMessageSpecVersion, ButtplugMessageSpecVersion),
+  /// Invalid client name: {0}
+  InvalidClientName(String),
   /// Untyped Deserialized Error: {0}
   UntypedDeserializedError(String),
 }
@@ -76,6 +78,8 @@ pub enum ButtplugMessageError {
   /// Message serialization error
   #[error(transparent)]
   MessageSerializationError(#[from] ButtplugSerializerError),
+  /// Unknown message received: {0}
+  UnknownMessage(String),
   /// Untyped Deserialized Error: {0}
   UntypedDeserializedError(String),
 }
@@ -127,12 +131,16 @@ pub enum ButtplugDeviceError {
   DeviceFeatureCountMismatch(u32, u32),
   /// Device only has {0} features, but was given an index of {1}
   DeviceFeatureIndexError(u32, u32),
+  /// Invalid command value {1} for feature {0}, values must be finite and between 0.0 and 1.0
+  DeviceCommandValueError(u32, f64),
   /// Device connection error: {0}
   DeviceConnectionError(String),
   /// Device communication error: {0}
   DeviceCommunicationError(String),
   /// Device does not have endpoint {0}
   InvalidEndpoint(Endpoint),
+  /// Cannot write empty data to endpoint {0}
+  EmptyWrite(Endpoint),
   /// Device does not handle command type: {0}
   UnhandledCommand(String),
   #[cfg(feature = "server")]
@@ -144,10 +152,14 @@ pub enum ButtplugDeviceError {
   DeviceSpecificError(String),
   /// No device available at index {0}
   DeviceNotAvailable(u32),
+  /// Device {0} could not be found for reconnection within {1}ms
+  DeviceReconnectTimeout(String, u64),
   /// Device scanning already started.
   DeviceScanningAlreadyStarted,
   /// Device scanning already stopped.
   DeviceScanningAlreadyStopped,
+  /// No device communication manager named {0}
+  DeviceCommunicationManagerNotFound(String),
   /// Device permission error: {0}
   DevicePermissionError(String),
   /// {0}
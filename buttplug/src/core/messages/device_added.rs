@@ -5,7 +5,7 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1};
+use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1, DeviceMessageInfoV2};
 use super::*;
 
 #[cfg(feature = "serialize-json")]
@@ -57,6 +57,40 @@ impl ButtplugMessageValidator for DeviceAdded {
   }
 }
 
+#[derive(Default, ButtplugMessage, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceAddedV2 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
+  device_name: String,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceMessages"))]
+  device_messages: DeviceMessageAttributesMap,
+}
+
+impl From<DeviceAdded> for DeviceAddedV2 {
+  fn from(msg: DeviceAdded) -> Self {
+    let id = msg.id();
+    let dmi = DeviceMessageInfo::from(msg);
+    let dmiv2 = DeviceMessageInfoV2::from(dmi);
+
+    Self {
+      id,
+      device_index: dmiv2.device_index,
+      device_name: dmiv2.device_name,
+      device_messages: dmiv2.device_messages,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for DeviceAddedV2 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
+
 #[derive(Default, ButtplugMessage, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceAddedV1 {
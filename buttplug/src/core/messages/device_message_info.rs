@@ -65,6 +65,43 @@ impl From<DeviceAdded> for DeviceMessageInfo {
   }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceMessageInfoV2 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  pub device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
+  pub device_name: String,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "DeviceMessages", serialize_with = "ordered_map")
+  )]
+  pub device_messages: DeviceMessageAttributesMap,
+}
+
+impl From<DeviceAdded> for DeviceMessageInfoV2 {
+  fn from(device_added: DeviceAdded) -> Self {
+    let dmi = DeviceMessageInfo::from(device_added);
+    DeviceMessageInfoV2::from(dmi)
+  }
+}
+
+impl From<DeviceMessageInfo> for DeviceMessageInfoV2 {
+  fn from(device_message_info: DeviceMessageInfo) -> Self {
+    // No structural difference, v3 only added message types.
+    let mut dmi_v2 = Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_messages: device_message_info.device_messages,
+    };
+    // Remove entries that weren't in V2.
+    dmi_v2
+      .device_messages
+      .remove(&ButtplugDeviceMessageType::OscillateCmd);
+    dmi_v2
+  }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceMessageInfoV1 {
@@ -102,6 +139,7 @@ impl From<DeviceMessageInfo> for DeviceMessageInfoV1 {
       ButtplugDeviceMessageType::RawUnsubscribeCmd,
       ButtplugDeviceMessageType::BatteryLevelCmd,
       ButtplugDeviceMessageType::RSSILevelCmd,
+      ButtplugDeviceMessageType::OscillateCmd,
     ];
     for t in &v2_message_types {
       dmi_v1.device_messages.remove(t);
@@ -20,6 +20,7 @@ mod device_added;
 mod device_list;
 mod device_message_info;
 mod device_removed;
+mod disconnect_device;
 mod error;
 mod fleshlight_launch_fw12_cmd;
 mod kiiroo_cmd;
@@ -29,12 +30,14 @@ mod log_level;
 mod lovense_cmd;
 mod message_attributes;
 mod ok;
+mod oscillate_cmd;
 mod ping;
 mod raw_read_cmd;
 mod raw_reading;
 mod raw_subscribe_cmd;
 mod raw_unsubscribe_cmd;
 mod raw_write_cmd;
+mod reconnect_device;
 mod request_device_list;
 mod request_log;
 mod request_server_info;
@@ -46,6 +49,7 @@ pub mod serializer;
 mod server_info;
 mod single_motor_vibrate_cmd;
 mod start_scanning;
+mod start_scanning_managers;
 mod stop_all_devices;
 mod stop_device_cmd;
 mod stop_scanning;
@@ -56,10 +60,11 @@ mod vorze_a10_cyclone_cmd;
 pub use self::log::Log;
 pub use battery_level_cmd::BatteryLevelCmd;
 pub use battery_level_reading::BatteryLevelReading;
-pub use device_added::{DeviceAdded, DeviceAddedV0, DeviceAddedV1};
-pub use device_list::{DeviceList, DeviceListV0, DeviceListV1};
+pub use device_added::{DeviceAdded, DeviceAddedV0, DeviceAddedV1, DeviceAddedV2};
+pub use device_list::{DeviceList, DeviceListV0, DeviceListV1, DeviceListV2};
 pub use device_message_info::{DeviceMessageAttributesMap, DeviceMessageInfo};
 pub use device_removed::DeviceRemoved;
+pub use disconnect_device::DisconnectDevice;
 pub use error::{Error, ErrorCode, ErrorV0};
 pub use fleshlight_launch_fw12_cmd::FleshlightLaunchFW12Cmd;
 pub use kiiroo_cmd::KiirooCmd;
@@ -68,12 +73,14 @@ pub use log_level::LogLevel;
 pub use lovense_cmd::LovenseCmd;
 pub use message_attributes::DeviceMessageAttributes;
 pub use ok::Ok;
+pub use oscillate_cmd::{OscillateCmd, OscillateSubcommand};
 pub use ping::Ping;
 pub use raw_read_cmd::RawReadCmd;
 pub use raw_reading::RawReading;
 pub use raw_subscribe_cmd::RawSubscribeCmd;
 pub use raw_unsubscribe_cmd::RawUnsubscribeCmd;
 pub use raw_write_cmd::RawWriteCmd;
+pub use reconnect_device::ReconnectDevice;
 pub use request_device_list::RequestDeviceList;
 pub use request_log::RequestLog;
 pub use request_server_info::RequestServerInfo;
@@ -81,9 +88,10 @@ pub use rotate_cmd::{RotateCmd, RotationSubcommand};
 pub use rssi_level_cmd::RSSILevelCmd;
 pub use rssi_level_reading::RSSILevelReading;
 pub use scanning_finished::ScanningFinished;
-pub use server_info::{ServerInfo, ServerInfoV0};
+pub use server_info::{ServerInfo, ServerInfoV0, ServerInfoV2};
 pub use single_motor_vibrate_cmd::SingleMotorVibrateCmd;
 pub use start_scanning::StartScanning;
+pub use start_scanning_managers::StartScanningManagers;
 pub use stop_all_devices::StopAllDevices;
 pub use stop_device_cmd::StopDeviceCmd;
 pub use stop_scanning::StopScanning;
@@ -107,6 +115,7 @@ pub enum ButtplugMessageSpecVersion {
   Version0 = 0,
   Version1 = 1,
   Version2 = 2,
+  Version3 = 3,
 }
 
 /// Message Id for events sent from the server, which are not in response to a
@@ -115,7 +124,7 @@ pub const BUTTPLUG_SERVER_EVENT_ID: u32 = 0;
 
 /// The current latest version of the spec implemented by the library.
 pub const BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION: ButtplugMessageSpecVersion =
-  ButtplugMessageSpecVersion::Version2;
+  ButtplugMessageSpecVersion::Version3;
 
 /// Base trait for all Buttplug Protocol Message Structs. Handles management of
 /// message ids, as well as implementing conveinence functions for converting
@@ -187,6 +196,7 @@ pub enum ButtplugDeviceMessageType {
   VibrateCmd,
   LinearCmd,
   RotateCmd,
+  OscillateCmd,
   StopDeviceCmd,
   RawWriteCmd,
   RawReadCmd,
@@ -229,6 +239,7 @@ pub enum ButtplugCurrentSpecDeviceMessageType {
   VibrateCmd,
   LinearCmd,
   RotateCmd,
+  OscillateCmd,
   StopDeviceCmd,
   RawWriteCmd,
   RawReadCmd,
@@ -259,6 +270,9 @@ impl TryFrom<ButtplugDeviceMessageType> for ButtplugCurrentSpecDeviceMessageType
       ButtplugDeviceMessageType::VibrateCmd => Ok(ButtplugCurrentSpecDeviceMessageType::VibrateCmd),
       ButtplugDeviceMessageType::LinearCmd => Ok(ButtplugCurrentSpecDeviceMessageType::LinearCmd),
       ButtplugDeviceMessageType::RotateCmd => Ok(ButtplugCurrentSpecDeviceMessageType::RotateCmd),
+      ButtplugDeviceMessageType::OscillateCmd => {
+        Ok(ButtplugCurrentSpecDeviceMessageType::OscillateCmd)
+      }
       ButtplugDeviceMessageType::StopDeviceCmd => {
         Ok(ButtplugCurrentSpecDeviceMessageType::StopDeviceCmd)
       }
@@ -291,6 +305,9 @@ impl From<ButtplugCurrentSpecDeviceMessageType> for ButtplugDeviceMessageType {
       ButtplugCurrentSpecDeviceMessageType::VibrateCmd => ButtplugDeviceMessageType::VibrateCmd,
       ButtplugCurrentSpecDeviceMessageType::LinearCmd => ButtplugDeviceMessageType::LinearCmd,
       ButtplugCurrentSpecDeviceMessageType::RotateCmd => ButtplugDeviceMessageType::RotateCmd,
+      ButtplugCurrentSpecDeviceMessageType::OscillateCmd => {
+        ButtplugDeviceMessageType::OscillateCmd
+      }
       ButtplugCurrentSpecDeviceMessageType::StopDeviceCmd => {
         ButtplugDeviceMessageType::StopDeviceCmd
       }
@@ -329,13 +346,17 @@ pub enum ButtplugClientMessage {
   RequestServerInfo(RequestServerInfo),
   // Device enumeration messages
   StartScanning(StartScanning),
+  StartScanningManagers(StartScanningManagers),
   StopScanning(StopScanning),
   RequestDeviceList(RequestDeviceList),
+  ReconnectDevice(ReconnectDevice),
+  DisconnectDevice(DisconnectDevice),
   // Generic commands
   StopAllDevices(StopAllDevices),
   VibrateCmd(VibrateCmd),
   LinearCmd(LinearCmd),
   RotateCmd(RotateCmd),
+  OscillateCmd(OscillateCmd),
   RawWriteCmd(RawWriteCmd),
   RawReadCmd(RawReadCmd),
   StopDeviceCmd(StopDeviceCmd),
@@ -390,11 +411,11 @@ pub enum ButtplugServerMessage {
 }
 
 /// Type alias for the latest version of client-to-server messages.
-pub type ButtplugCurrentSpecClientMessage = ButtplugSpecV2ClientMessage;
+pub type ButtplugCurrentSpecClientMessage = ButtplugSpecV3ClientMessage;
 /// Type alias for the latest version of server-to-client messages.
-pub type ButtplugCurrentSpecServerMessage = ButtplugSpecV2ServerMessage;
+pub type ButtplugCurrentSpecServerMessage = ButtplugSpecV3ServerMessage;
 
-/// Represents all client-to-server messages in v2 of the Buttplug Spec
+/// Represents all client-to-server messages in v3 of the Buttplug Spec
 #[derive(
   Debug,
   Clone,
@@ -406,19 +427,23 @@ pub type ButtplugCurrentSpecServerMessage = ButtplugSpecV2ServerMessage;
   TryFromButtplugClientMessage,
 )]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
-pub enum ButtplugSpecV2ClientMessage {
+pub enum ButtplugSpecV3ClientMessage {
   // Handshake messages
   RequestServerInfo(RequestServerInfo),
   Ping(Ping),
   // Device enumeration messages
   StartScanning(StartScanning),
+  StartScanningManagers(StartScanningManagers),
   StopScanning(StopScanning),
   RequestDeviceList(RequestDeviceList),
+  ReconnectDevice(ReconnectDevice),
+  DisconnectDevice(DisconnectDevice),
   // Generic commands
   StopAllDevices(StopAllDevices),
   VibrateCmd(VibrateCmd),
   LinearCmd(LinearCmd),
   RotateCmd(RotateCmd),
+  OscillateCmd(OscillateCmd),
   RawWriteCmd(RawWriteCmd),
   RawReadCmd(RawReadCmd),
   StopDeviceCmd(StopDeviceCmd),
@@ -429,7 +454,7 @@ pub enum ButtplugSpecV2ClientMessage {
   RSSILevelCmd(RSSILevelCmd),
 }
 
-/// Represents all server-to-client messages in v2 of the Buttplug Spec
+/// Represents all server-to-client messages in v3 of the Buttplug Spec
 #[derive(
   Debug,
   Clone,
@@ -441,7 +466,7 @@ pub enum ButtplugSpecV2ClientMessage {
   TryFromButtplugServerMessage,
 )]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
-pub enum ButtplugSpecV2ServerMessage {
+pub enum ButtplugSpecV3ServerMessage {
   // Status messages
   Ok(Ok),
   Error(Error),
@@ -459,6 +484,104 @@ pub enum ButtplugSpecV2ServerMessage {
   RSSILevelReading(RSSILevelReading),
 }
 
+/// Represents all client-to-server messages in v2 of the Buttplug Spec
+#[derive(
+  Debug,
+  Clone,
+  PartialEq,
+  ButtplugMessage,
+  ButtplugMessageValidator,
+  ButtplugClientMessageType,
+  FromSpecificButtplugMessage,
+  TryFromButtplugClientMessage,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum ButtplugSpecV2ClientMessage {
+  // Handshake messages
+  RequestServerInfo(RequestServerInfo),
+  Ping(Ping),
+  // Device enumeration messages
+  StartScanning(StartScanning),
+  StopScanning(StopScanning),
+  RequestDeviceList(RequestDeviceList),
+  // Generic commands
+  StopAllDevices(StopAllDevices),
+  VibrateCmd(VibrateCmd),
+  LinearCmd(LinearCmd),
+  RotateCmd(RotateCmd),
+  RawWriteCmd(RawWriteCmd),
+  RawReadCmd(RawReadCmd),
+  StopDeviceCmd(StopDeviceCmd),
+  RawSubscribeCmd(RawSubscribeCmd),
+  RawUnsubscribeCmd(RawUnsubscribeCmd),
+  // Sensor commands
+  BatteryLevelCmd(BatteryLevelCmd),
+  RSSILevelCmd(RSSILevelCmd),
+}
+
+/// Represents all server-to-client messages in v2 of the Buttplug Spec
+#[derive(
+  Debug, Clone, PartialEq, ButtplugMessage, ButtplugMessageValidator, ButtplugServerMessageType,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum ButtplugSpecV2ServerMessage {
+  // Status messages
+  Ok(Ok),
+  Error(Error),
+  // Handshake messages
+  ServerInfo(ServerInfoV2),
+  // Device enumeration messages
+  DeviceList(DeviceListV2),
+  DeviceAdded(DeviceAddedV2),
+  DeviceRemoved(DeviceRemoved),
+  ScanningFinished(ScanningFinished),
+  // Generic commands
+  RawReading(RawReading),
+  // Sensor commands
+  BatteryLevelReading(BatteryLevelReading),
+  RSSILevelReading(RSSILevelReading),
+}
+
+// This was implementated as a derive, but for some reason the .into() calls
+// wouldn't work correctly when used as a device. If the actual implementation
+// is here, things work fine. Luckily it won't ever be changed much.
+impl TryFrom<ButtplugServerMessage> for ButtplugSpecV2ServerMessage {
+  type Error = ButtplugMessageError;
+  fn try_from(msg: ButtplugServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugServerMessage::Ok(msg) => Ok(ButtplugSpecV2ServerMessage::Ok(msg)),
+      ButtplugServerMessage::Error(msg) => Ok(ButtplugSpecV2ServerMessage::Error(msg)),
+      ButtplugServerMessage::ServerInfo(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::ServerInfo(msg.into()))
+      }
+      ButtplugServerMessage::DeviceList(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceList(msg.into()))
+      }
+      ButtplugServerMessage::DeviceAdded(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceAdded(msg.into()))
+      }
+      ButtplugServerMessage::DeviceRemoved(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceRemoved(msg))
+      }
+      ButtplugServerMessage::ScanningFinished(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::ScanningFinished(msg))
+      }
+      ButtplugServerMessage::RawReading(msg) => Ok(ButtplugSpecV2ServerMessage::RawReading(msg)),
+      ButtplugServerMessage::BatteryLevelReading(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::BatteryLevelReading(msg))
+      }
+      ButtplugServerMessage::RSSILevelReading(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::RSSILevelReading(msg))
+      }
+      _ => Err(ButtplugMessageError::VersionError(
+        "ButtplugServerMessage".to_owned(),
+        format!("{:?}", msg),
+        "ButtplugSpecV2ServerMessage".to_owned(),
+      )),
+    }
+  }
+}
+
 /// Represents all client-to-server messages in v1 of the Buttplug Spec
 #[derive(
   Debug,
@@ -647,7 +770,10 @@ pub enum ButtplugDeviceManagerMessageUnion {
   RequestDeviceList(RequestDeviceList),
   StopAllDevices(StopAllDevices),
   StartScanning(StartScanning),
+  StartScanningManagers(StartScanningManagers),
   StopScanning(StopScanning),
+  ReconnectDevice(ReconnectDevice),
+  DisconnectDevice(DisconnectDevice),
 }
 
 /// Represents all possible device command message types.
@@ -670,6 +796,7 @@ pub enum ButtplugDeviceCommandMessageUnion {
   VibrateCmd(VibrateCmd),
   LinearCmd(LinearCmd),
   RotateCmd(RotateCmd),
+  OscillateCmd(OscillateCmd),
   RawWriteCmd(RawWriteCmd),
   RawReadCmd(RawReadCmd),
   StopDeviceCmd(StopDeviceCmd),
@@ -1,19 +1,20 @@
 use super::{ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugSerializerError};
 use crate::{
   core::{
-    errors::{ButtplugError, ButtplugHandshakeError},
+    errors::{ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
     messages::{
       self, ButtplugClientMessage, ButtplugCurrentSpecClientMessage,
       ButtplugCurrentSpecServerMessage, ButtplugMessage, ButtplugMessageSpecVersion,
       ButtplugServerMessage, ButtplugSpecV0ClientMessage, ButtplugSpecV0ServerMessage,
       ButtplugSpecV1ClientMessage, ButtplugSpecV1ServerMessage, ButtplugSpecV2ClientMessage,
-      ButtplugSpecV2ServerMessage,
+      ButtplugSpecV2ServerMessage, ButtplugSpecV3ClientMessage, ButtplugSpecV3ServerMessage,
     },
   },
   util::json::JSONValidator,
 };
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 
 static MESSAGE_JSON_SCHEMA: &str =
@@ -23,6 +24,16 @@ static MESSAGE_JSON_SCHEMA: &str =
 pub fn create_message_validator() -> JSONValidator {
   JSONValidator::new(MESSAGE_JSON_SCHEMA)
 }
+
+/// Returns the names of all message types in the built in buttplug message
+/// schema.
+fn known_message_names() -> HashSet<String> {
+  let schema: serde_json::Value = serde_json::from_str(MESSAGE_JSON_SCHEMA).unwrap();
+  schema["items"]["properties"]
+    .as_object()
+    .map(|properties| properties.keys().cloned().collect())
+    .unwrap_or_default()
+}
 pub struct ButtplugServerJSONSerializer {
   pub(super) message_version: RefCell<Option<messages::ButtplugMessageSpecVersion>>,
   validator: JSONValidator,
@@ -109,6 +120,17 @@ fn serialize_to_version(
         .collect();
       vec_to_protocol_json(msg_vec)
     }
+    ButtplugMessageSpecVersion::Version3 => {
+      let msg_vec: Vec<ButtplugSpecV3ServerMessage> = msgs
+        .iter()
+        .cloned()
+        .map(|msg| match ButtplugSpecV3ServerMessage::try_from(msg) {
+          Ok(msgv0) => msgv0,
+          Err(err) => ButtplugSpecV3ServerMessage::Error(ButtplugError::from(err).into()),
+        })
+        .collect();
+      vec_to_protocol_json(msg_vec)
+    }
   })
 }
 
@@ -155,16 +177,23 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
             .map(|m| m.into())
             .collect()
         }
+        ButtplugMessageSpecVersion::Version3 => {
+          deserialize_to_message::<ButtplugSpecV3ClientMessage>(&self.validator, msg)?
+            .iter()
+            .cloned()
+            .map(|m| m.into())
+            .collect()
+        }
       });
     }
     // instead of using if/else here, return in the if, which drops the borrow.
     // so we can possibly mutate it now.
-    let msg_union = deserialize_to_message::<ButtplugSpecV2ClientMessage>(&self.validator, msg)?;
+    let msg_union = deserialize_to_message::<ButtplugSpecV3ClientMessage>(&self.validator, msg)?;
     // If the message is malformed, just return an spec version not received error.
     if msg_union.is_empty() {
       return Err(ButtplugSerializerError::MessageSpecVersionNotReceived);
     }
-    if let ButtplugSpecV2ClientMessage::RequestServerInfo(rsi) = &msg_union[0] {
+    if let ButtplugSpecV3ClientMessage::RequestServerInfo(rsi) = &msg_union[0] {
       info!(
         "Setting JSON Wrapper message version to {}",
         rsi.message_version()
@@ -184,7 +213,7 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
       // RequestServerInfo message (so we can't set up our known spec
       // version), just encode to the latest and return.
       if let ButtplugServerMessage::Error(_) = &msgs[0] {
-        serialize_to_version(ButtplugMessageSpecVersion::Version2, msgs)
+        serialize_to_version(ButtplugMessageSpecVersion::Version3, msgs)
       } else {
         // If we don't even have enough info to know which message
         // version to convert to, consider this a handshake error.
@@ -200,12 +229,58 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
 
 pub struct ButtplugClientJSONSerializer {
   validator: JSONValidator,
+  known_messages: HashSet<String>,
 }
 
 impl Default for ButtplugClientJSONSerializer {
   fn default() -> Self {
     Self {
       validator: create_message_validator(),
+      known_messages: known_message_names(),
+    }
+  }
+}
+
+impl ButtplugClientJSONSerializer {
+  /// Fallback for message arrays that fail to deserialize.
+  ///
+  /// If the only problem with the array is message types we don't know about
+  /// (i.e. the server is newer than we are), deserializes the messages we do
+  /// know, and stands in an [Error][messages::Error] carrying
+  /// [ButtplugMessageError::UnknownMessage] for each of the rest, so the client
+  /// can decide what to do with them. Returns None if anything else is wrong
+  /// with the array.
+  fn deserialize_with_unknown_messages(
+    &self,
+    msg: &str,
+  ) -> Option<Vec<ButtplugCurrentSpecServerMessage>> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(msg).ok()?;
+    let mut found_unknown = false;
+    let mut msgs = vec![];
+    for value in values {
+      let name = value
+        .as_object()
+        .filter(|obj| obj.len() == 1)
+        .and_then(|obj| obj.keys().next().cloned())?;
+      if self.known_messages.contains(&name) {
+        msgs.append(
+          &mut deserialize_to_message::<ButtplugCurrentSpecServerMessage>(
+            &self.validator,
+            format!("[{}]", value),
+          )
+          .ok()?,
+        );
+      } else {
+        found_unknown = true;
+        msgs.push(ButtplugCurrentSpecServerMessage::Error(
+          ButtplugError::from(ButtplugMessageError::UnknownMessage(value.to_string())).into(),
+        ));
+      }
+    }
+    if found_unknown {
+      Some(msgs)
+    } else {
+      None
     }
   }
 }
@@ -222,7 +297,8 @@ impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
     msg: ButtplugSerializedMessage,
   ) -> Result<Vec<ButtplugCurrentSpecServerMessage>, ButtplugSerializerError> {
     if let ButtplugSerializedMessage::Text(text_msg) = msg {
-      deserialize_to_message::<Self::Inbound>(&self.validator, text_msg)
+      deserialize_to_message::<Self::Inbound>(&self.validator, text_msg.clone())
+        .or_else(|err| self.deserialize_with_unknown_messages(&text_msg).ok_or(err))
     } else {
       Err(ButtplugSerializerError::BinaryDeserializationError)
     }
@@ -257,6 +333,56 @@ mod test {
     );
   }
 
+  #[test]
+  fn test_v2_client_cannot_send_v3_messages() {
+    let serializer = ButtplugServerJSONSerializer::default();
+    serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        r#"[{"RequestServerInfo":{"Id":1,"ClientName":"Test Client","MessageVersion":2}}]"#
+          .to_owned(),
+      ))
+      .unwrap();
+    let json = r#"[{"OscillateCmd":{"Id":2,"DeviceIndex":0,"Speeds":[{"Index":0,"Speed":0.5}]}}]"#;
+    assert!(serializer
+      .deserialize(ButtplugSerializedMessage::Text(json.to_owned()))
+      .is_err());
+  }
+
+  #[test]
+  fn test_v3_additions_removed_from_v2_server_messages() {
+    let serializer = ButtplugServerJSONSerializer::default();
+    serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        r#"[{"RequestServerInfo":{"Id":1,"ClientName":"Test Client","MessageVersion":2}}]"#
+          .to_owned(),
+      ))
+      .unwrap();
+    let mut server_info =
+      messages::ServerInfo::new("Test Server", ButtplugMessageSpecVersion::Version2, 0);
+    server_info.set_capabilities(vec!["DeviceReconnect".to_owned()]);
+    let mut device_messages = messages::DeviceMessageAttributesMap::new();
+    for message_type in &[
+      messages::ButtplugDeviceMessageType::VibrateCmd,
+      messages::ButtplugDeviceMessageType::OscillateCmd,
+    ] {
+      device_messages.insert(
+        *message_type,
+        messages::DeviceMessageAttributes {
+          feature_count: Some(1),
+          ..Default::default()
+        },
+      );
+    }
+    let device_added = messages::DeviceAdded::new(0, "Test Device", &device_messages);
+    assert_eq!(
+      serializer.serialize(vec![server_info.into(), device_added.into()]),
+      ButtplugSerializedMessage::Text(
+        r#"[{"ServerInfo":{"Id":1,"MessageVersion":2,"MaxPingTime":0,"ServerName":"Test Server"}},{"DeviceAdded":{"Id":0,"DeviceIndex":0,"DeviceName":"Test Device","DeviceMessages":{"VibrateCmd":{"FeatureCount":1}}}}]"#
+          .to_owned()
+      )
+    );
+  }
+
   #[test]
   fn test_wrong_message_version() {
     let json = r#"[{
@@ -280,8 +406,6 @@ mod test {
       "{}",
       // Valid json but not an object
       "[]",
-      // Not a message type
-      "[{\"NotAMessage\":{}}]",
       // Valid json and message type but not in correct format
       "[{\"Ok\":[]}]",
       // Valid json and message type but not in correct format
@@ -307,5 +431,42 @@ mod test {
         assert!(false, "Wrong error!");
       }
     }
+    // Not a message type. Rather than failing the whole array, unknown message
+    // types come back as errors, so the client can apply its unknown message
+    // policy to them.
+    let res = serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        "[{\"NotAMessage\":{}}]".to_owned(),
+      ))
+      .unwrap();
+    assert!(matches!(res[..], [ButtplugCurrentSpecServerMessage::Error(_)]));
+  }
+
+  #[test]
+  fn test_client_unknown_messages() {
+    let serializer = ButtplugClientJSONSerializer::default();
+    let msgs = serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        "[{\"NotAMessage\":{}},{\"Ok\":{\"Id\":1}}]".to_owned(),
+      ))
+      .unwrap();
+    assert_eq!(msgs.len(), 2);
+    if let ButtplugCurrentSpecServerMessage::Error(err) = &msgs[0] {
+      assert_eq!(err.id(), 0);
+      assert!(matches!(
+        err.original_error(),
+        ButtplugError::ButtplugMessageError(ButtplugMessageError::UnknownMessage(msg))
+          if msg == "{\"NotAMessage\":{}}"
+      ));
+    } else {
+      panic!("Unknown message should be turned into an error.");
+    }
+    assert!(matches!(msgs[1], ButtplugCurrentSpecServerMessage::Ok(_)));
+    // Unknown messages don't excuse known messages that are malformed.
+    assert!(serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        "[{\"NotAMessage\":{}},{\"Ok\":[]}]".to_owned(),
+      ))
+      .is_err());
   }
 }
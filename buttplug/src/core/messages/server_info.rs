@@ -20,6 +20,13 @@ pub struct ServerInfo {
   max_ping_time: u32,
   #[cfg_attr(feature = "serialize-json", serde(rename = "ServerName"))]
   server_name: String,
+  /// Names of optional features the server supports. Left out of the message
+  /// if empty, so servers that don't report capabilities are unaffected.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "Capabilities", default, skip_serializing_if = "Vec::is_empty")
+  )]
+  capabilities: Vec<String>,
 }
 
 impl ServerInfo {
@@ -33,6 +40,7 @@ impl ServerInfo {
       message_version,
       max_ping_time,
       server_name: server_name.to_string(),
+      capabilities: vec![],
     }
   }
 
@@ -47,6 +55,14 @@ impl ServerInfo {
   pub fn server_name(&self) -> &String {
     &self.server_name
   }
+
+  pub fn capabilities(&self) -> &Vec<String> {
+    &self.capabilities
+  }
+
+  pub fn set_capabilities(&mut self, capabilities: Vec<String>) {
+    self.capabilities = capabilities;
+  }
 }
 
 impl ButtplugMessageValidator for ServerInfo {
@@ -55,6 +71,37 @@ impl ButtplugMessageValidator for ServerInfo {
   }
 }
 
+#[derive(Debug, ButtplugMessage, PartialEq, Clone)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct ServerInfoV2 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "MessageVersion"))]
+  message_version: ButtplugMessageSpecVersion,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "MaxPingTime"))]
+  max_ping_time: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "ServerName"))]
+  server_name: String,
+}
+
+// Capabilities were added in v3, so they're dropped on the way down.
+impl From<ServerInfo> for ServerInfoV2 {
+  fn from(msg: ServerInfo) -> Self {
+    Self {
+      id: msg.id,
+      message_version: msg.message_version,
+      max_ping_time: msg.max_ping_time,
+      server_name: msg.server_name,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for ServerInfoV2 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
+
 #[derive(Debug, ButtplugMessage, PartialEq, Clone)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct ServerInfoV0 {
@@ -0,0 +1,43 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Request for the server to start scanning for devices, using only the named
+/// device communication managers.
+#[derive(Debug, ButtplugMessage, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct StartScanningManagers {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Managers"))]
+  managers: Vec<String>,
+}
+
+impl StartScanningManagers {
+  pub fn new(managers: Vec<String>) -> Self {
+    Self { id: 1, managers }
+  }
+
+  pub fn managers(&self) -> &Vec<String> {
+    &self.managers
+  }
+}
+
+impl ButtplugMessageValidator for StartScanningManagers {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)?;
+    if self.managers.is_empty() {
+      return Err(ButtplugMessageError::InvalidMessageContents(
+        "StartScanningManagers requires at least one manager name.".to_owned(),
+      ));
+    }
+    Ok(())
+  }
+}
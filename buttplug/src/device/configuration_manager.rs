@@ -16,9 +16,10 @@ use crate::{
     messages::{ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap},
   },
   device::Endpoint,
+  util::device_configuration::load_protocol_config_from_json,
 };
 use dashmap::DashMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
   collections::{HashMap, HashSet},
   sync::Arc,
@@ -424,6 +425,58 @@ impl DeviceProtocolConfiguration {
   }
 }
 
+/// Summary of a protocol the configuration manager can create devices for,
+/// as returned by [DeviceConfigurationManager::supported_protocols]. Meant for
+/// listing supported hardware in UIs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupportedProtocolInfo {
+  /// Protocol name, as used in the device configuration file.
+  pub protocol: String,
+  /// Names the protocol matches devices on, i.e. Bluetooth advertisement
+  /// names or websocket device names. Names ending in `*` are prefix
+  /// wildcards.
+  pub identifiers: Vec<String>,
+  /// Human readable names of the devices the protocol supports, from the
+  /// default and device specific configurations.
+  pub device_names: Vec<String>,
+}
+
+impl SupportedProtocolInfo {
+  fn new(protocol: &str, definition: &ProtocolDefinition) -> Self {
+    let mut identifiers: Vec<String> = definition
+      .btle
+      .iter()
+      .flat_map(|btle| btle.names.iter())
+      .chain(
+        definition
+          .websocket
+          .iter()
+          .flat_map(|websocket| websocket.names.iter()),
+      )
+      .cloned()
+      .collect();
+    identifiers.sort();
+    identifiers.dedup();
+    // Name maps are keyed by locale. Prefer english, since that's what the
+    // built in config always has.
+    let mut device_names: Vec<String> = definition
+      .defaults
+      .iter()
+      .chain(definition.configurations.iter())
+      .filter_map(|attrs| attrs.name.as_ref())
+      .filter_map(|names| names.get("en-us").or_else(|| names.values().next()))
+      .cloned()
+      .collect();
+    device_names.sort();
+    device_names.dedup();
+    Self {
+      protocol: protocol.to_owned(),
+      identifiers,
+      device_names,
+    }
+  }
+}
+
 pub struct DeviceConfigurationManager {
   allow_raw_messages: bool,
   protocol_definitions: Arc<DashMap<String, ProtocolDefinition>>,
@@ -455,6 +508,39 @@ impl DeviceConfigurationManager {
     self.protocol_definitions.remove(protocol_name);
   }
 
+  /// Merges a device configuration JSON string into the currently loaded
+  /// protocol definitions.
+  ///
+  /// The JSON is validated against the device configuration schema before
+  /// anything is changed, so a malformed file leaves the current
+  /// configuration untouched. Protocols we don't know about yet are added.
+  /// Protocols we already have are merged via
+  /// [ProtocolDefinition::merge_user_definition], so new specifiers are
+  /// appended and configurations with matching identifiers take precedence
+  /// over the ones already loaded.
+  ///
+  /// # Errors
+  ///
+  /// Returns [ButtplugDeviceError::DeviceConfigurationFileError] if the JSON
+  /// fails to parse, fails schema validation, or is older than the internal
+  /// device configuration version.
+  pub fn add_device_configuration_json(&self, config_json: &str) -> Result<(), ButtplugError> {
+    let config = load_protocol_config_from_json(config_json)?;
+    for (protocol_name, definition) in config.protocols {
+      if let Some(mut existing) = self.protocol_definitions.get_mut(&protocol_name) {
+        info!(
+          "Merging additional configuration into protocol {}",
+          protocol_name
+        );
+        existing.merge_user_definition(definition);
+      } else {
+        info!("Adding new protocol definition {}", protocol_name);
+        self.protocol_definitions.insert(protocol_name, definition);
+      }
+    }
+    Ok(())
+  }
+
   pub fn add_protocol<T>(&self, protocol_name: &str)
   where
     T: ButtplugProtocol,
@@ -485,6 +571,20 @@ impl DeviceConfigurationManager {
     self.protocol_definitions.clone()
   }
 
+  /// Lists every protocol that has both a loaded definition and a protocol
+  /// implementation, i.e. everything devices can currently be created for,
+  /// sorted by protocol name.
+  pub fn supported_protocols(&self) -> Vec<SupportedProtocolInfo> {
+    let mut protocols: Vec<SupportedProtocolInfo> = self
+      .protocol_definitions
+      .iter()
+      .filter(|definition| self.has_protocol(definition.key()))
+      .map(|definition| SupportedProtocolInfo::new(definition.key(), definition.value()))
+      .collect();
+    protocols.sort_by(|a, b| a.protocol.cmp(&b.protocol));
+    protocols
+  }
+
   pub fn find_protocol_definitions(
     &self,
     specifier: &DeviceSpecifier,
@@ -524,9 +624,15 @@ impl DeviceConfigurationManager {
 #[cfg(test)]
 mod test {
   use super::{
-    BluetoothLESpecifier, DeviceProtocolConfiguration, DeviceSpecifier, SerialSpecifier
+    BluetoothLESpecifier, DeviceConfigurationManager, DeviceProtocolConfiguration, DeviceSpecifier,
+    SerialSpecifier,
+  };
+  use crate::{
+    core::messages::ButtplugDeviceMessageType,
+    device::{configuration_manager::ProtocolDefinition, Endpoint},
+    util::device_configuration::{create_test_dcm, get_internal_config_version},
   };
-  use crate::{core::messages::ButtplugDeviceMessageType, device::configuration_manager::ProtocolDefinition, util::device_configuration::create_test_dcm};
+  use uuid::Uuid;
 /*
   #[test]
   fn test_load_config() {
@@ -541,6 +647,51 @@ mod test {
     assert!(config.find_protocol_definitions(&launch).is_some());
   }
 
+  #[test]
+  fn test_add_device_configuration_json_overrides_endpoint() {
+    let config = create_test_dcm(false);
+    let service = Uuid::parse_str("0000ff00-0000-1000-8000-00805f9b34fb").unwrap();
+    let tx_endpoint = |config: &DeviceConfigurationManager| {
+      let aneros =
+        DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("Massage Demo"));
+      let proto = config.find_protocol_definitions(&aneros).unwrap();
+      proto.2.btle.unwrap().services[&service][&Endpoint::Tx]
+    };
+    assert_eq!(
+      tx_endpoint(&config),
+      Uuid::parse_str("0000ff01-0000-1000-8000-00805f9b34fb").unwrap()
+    );
+    let additional_config = format!(
+      r#"
+      {{
+        "version": {},
+        "protocols": {{
+          "aneros": {{
+            "btle": {{
+              "names": ["Massage Demo"],
+              "services": {{
+                "0000ff00-0000-1000-8000-00805f9b34fb": {{
+                  "tx": "0000ff02-0000-1000-8000-00805f9b34fb"
+                }}
+              }}
+            }}
+          }}
+        }}
+      }}
+      "#,
+      get_internal_config_version()
+    );
+    config
+      .add_device_configuration_json(&additional_config)
+      .unwrap();
+    // The service was already defined, so its endpoints should be replaced
+    // rather than added alongside the defaults.
+    assert_eq!(
+      tx_endpoint(&config),
+      Uuid::parse_str("0000ff02-0000-1000-8000-00805f9b34fb").unwrap()
+    );
+  }
+
   #[test]
   fn test_config_wildcard_equals() {
     let config = create_test_dcm(false);
@@ -9,11 +9,12 @@ use std::{
   str::FromStr,
   string::ToString,
   sync::Arc,
+  time::Duration,
 };
 
 use crate::{
   core::{
-    errors::ButtplugError,
+    errors::{ButtplugDeviceError, ButtplugError},
     messages::{
       self, ButtplugDeviceCommandMessageUnion, ButtplugServerMessage, DeviceMessageAttributesMap,
       RawReadCmd, RawReading, RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd,
@@ -28,7 +29,7 @@ use crate::{
 use async_trait::async_trait;
 use configuration_manager::DeviceProtocolConfiguration;
 use core::hash::{Hash, Hasher};
-use futures::future::BoxFuture;
+use futures::future::{self, BoxFuture};
 use tokio::sync::broadcast;
 
 // We need this array to be exposed in our WASM FFI, but the only way to do that
@@ -44,6 +45,10 @@ pub enum Endpoint {
   RxAccel,
   RxBLEBattery,
   RxBLEModel,
+  RxBLEManufacturer,
+  RxBLESerialNumber,
+  RxBLEFirmwareRevision,
+  RxBLEHardwareRevision,
   RxPressure,
   RxTouch,
   Tx,
@@ -298,6 +303,21 @@ pub enum ButtplugDeviceEvent {
   Notification(String, Endpoint, Vec<u8>),
   Removed(String),
 }
+
+/// Connection parameters negotiated between the host and a Bluetooth LE
+/// device. The connection interval usually sets the floor for command latency,
+/// since writes only go out on connection events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BleConnectionParameters {
+  /// Time between connection events.
+  pub connection_interval: Duration,
+  /// Number of connection events the device is allowed to skip.
+  pub peripheral_latency: u16,
+  /// Time without a successful connection event before the link is
+  /// considered lost.
+  pub supervision_timeout: Duration,
+}
+
 pub struct DeviceImpl {
   name: String,
   address: String,
@@ -362,6 +382,14 @@ impl DeviceImpl {
   pub fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture {
     self.internal_impl.unsubscribe(msg)
   }
+
+  pub fn connection_parameters(&self) -> Option<BleConnectionParameters> {
+    self.internal_impl.connection_parameters()
+  }
+
+  pub fn request_connection_interval(&self, interval: Duration) -> ButtplugResultFuture {
+    self.internal_impl.request_connection_interval(interval)
+  }
 }
 
 pub trait DeviceImplInternal: Sync + Send {
@@ -375,6 +403,22 @@ pub trait DeviceImplInternal: Sync + Send {
   fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture;
   fn subscribe(&self, msg: DeviceSubscribeCmd) -> ButtplugResultFuture;
   fn unsubscribe(&self, msg: DeviceUnsubscribeCmd) -> ButtplugResultFuture;
+  /// Returns the negotiated connection parameters, if this is a BLE device and
+  /// the platform exposes them.
+  fn connection_parameters(&self) -> Option<BleConnectionParameters> {
+    None
+  }
+  /// Asks the platform to use a (usually shorter) connection interval. Fails
+  /// with [ButtplugDeviceError::UnhandledCommand] if the device or platform
+  /// doesn't support it.
+  fn request_connection_interval(&self, _interval: Duration) -> ButtplugResultFuture {
+    Box::pin(future::ready(Err(
+      ButtplugDeviceError::UnhandledCommand(
+        "Device does not support connection interval requests".to_owned(),
+      )
+      .into(),
+    )))
+  }
 }
 
 #[async_trait]
@@ -510,6 +554,21 @@ impl ButtplugDevice {
     self.device.disconnect()
   }
 
+  /// Returns the negotiated BLE connection parameters for the device, if the
+  /// device is BLE and the platform exposes them. Useful for diagnosing
+  /// command latency.
+  pub fn connection_parameters(&self) -> Option<BleConnectionParameters> {
+    self.device.connection_parameters()
+  }
+
+  /// Asks the platform to use the given BLE connection interval for the
+  /// device. Whether the device accepts it is up to the device, so check
+  /// [connection_parameters][ButtplugDevice::connection_parameters]
+  /// afterward.
+  pub fn request_connection_interval(&self, interval: Duration) -> ButtplugResultFuture {
+    self.device.request_connection_interval(interval)
+  }
+
   pub fn message_attributes(&self) -> DeviceMessageAttributesMap {
     self.protocol.message_attributes()
   }
@@ -2,18 +2,22 @@ use crate::core::{
   errors::{ButtplugDeviceError, ButtplugError},
   messages::{
     ButtplugDeviceCommandMessageUnion, ButtplugDeviceMessageType, DeviceMessageAttributesMap,
-    LinearCmd, RotateCmd, RotationSubcommand, VibrateCmd, VibrateSubcommand,
+    LinearCmd, OscillateCmd, OscillateSubcommand, RotateCmd, RotationSubcommand, VibrateCmd,
+    VibrateSubcommand,
   },
 };
 
 pub struct GenericCommandManager {
   sent_vibration: bool,
   sent_rotation: bool,
+  sent_oscillation: bool,
   _sent_linear: bool,
   vibrations: Vec<u32>,
   vibration_step_counts: Vec<u32>,
   rotations: Vec<(u32, bool)>,
   rotation_step_counts: Vec<u32>,
+  oscillations: Vec<u32>,
+  oscillation_step_counts: Vec<u32>,
   _linears: Vec<(u32, u32)>,
   _linear_step_counts: Vec<u32>,
   stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
@@ -25,6 +29,8 @@ impl GenericCommandManager {
     let mut vibration_step_counts: Vec<u32> = vec![];
     let mut rotations: Vec<(u32, bool)> = vec![];
     let mut rotation_step_counts: Vec<u32> = vec![];
+    let mut oscillations: Vec<u32> = vec![];
+    let mut oscillation_step_counts: Vec<u32> = vec![];
     let mut linears: Vec<(u32, u32)> = vec![];
     let mut linear_step_counts: Vec<u32> = vec![];
 
@@ -63,6 +69,20 @@ impl GenericCommandManager {
       }
       stop_commands.push(RotateCmd::new(0, subcommands).into());
     }
+    if let Some(attr) = attributes.get(&ButtplugDeviceMessageType::OscillateCmd) {
+      if let Some(count) = attr.feature_count {
+        oscillations = vec![0; count as usize];
+      }
+      if let Some(step_counts) = &attr.step_count {
+        oscillation_step_counts = step_counts.clone();
+      }
+
+      let mut subcommands = vec![];
+      for i in 0..oscillations.len() {
+        subcommands.push(OscillateSubcommand::new(i as u32, 0.0));
+      }
+      stop_commands.push(OscillateCmd::new(0, subcommands).into());
+    }
     if let Some(attr) = attributes.get(&ButtplugDeviceMessageType::LinearCmd) {
       if let Some(count) = attr.feature_count {
         linears = vec![(0, 0); count as usize];
@@ -75,12 +95,15 @@ impl GenericCommandManager {
     Self {
       sent_vibration: false,
       sent_rotation: false,
+      sent_oscillation: false,
       _sent_linear: false,
       vibrations,
       rotations,
+      oscillations,
       _linears: linears,
       vibration_step_counts,
       rotation_step_counts,
+      oscillation_step_counts,
       _linear_step_counts: linear_step_counts,
       stop_commands,
     }
@@ -226,6 +249,50 @@ impl GenericCommandManager {
     Ok(result)
   }
 
+  pub fn update_oscillation(
+    &mut self,
+    msg: &OscillateCmd,
+  ) -> Result<Vec<Option<u32>>, ButtplugError> {
+    // First, make sure this is a valid command, that contains at least one
+    // command.
+    if msg.speeds().is_empty() {
+      return Err(
+        ButtplugDeviceError::ProtocolRequirementError(
+          "OscillateCmd has 0 commands, will not do anything.".to_owned(),
+        )
+        .into(),
+      );
+    }
+
+    let mut result: Vec<Option<u32>> = vec![None; self.oscillations.len()];
+    for speed_command in msg.speeds() {
+      let index = speed_command.index() as usize;
+      if index >= self.oscillations.len() {
+        return Err(
+          ButtplugDeviceError::ProtocolRequirementError(format!(
+            "OscillateCmd has {} commands, device has {} oscillators.",
+            msg.speeds().len(),
+            self.oscillations.len()
+          ))
+          .into(),
+        );
+      }
+
+      // Same rounding and resend rules as vibration and rotation.
+      let speed =
+        (speed_command.speed() * self.oscillation_step_counts[index] as f64).ceil() as u32;
+      if !self.sent_oscillation || speed != self.oscillations[index] {
+        self.oscillations[index] = speed;
+        result[index] = Some(speed);
+      }
+    }
+
+    self.sent_oscillation = true;
+
+    // Return the command vector for the protocol to turn into proprietary commands
+    Ok(result)
+  }
+
   pub fn _update_linear(
     &mut self,
     _msg: &LinearCmd,
@@ -258,8 +325,8 @@ mod test {
 
   use super::GenericCommandManager;
   use crate::core::messages::{
-    ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap, RotateCmd,
-    RotationSubcommand, VibrateCmd, VibrateSubcommand,
+    ButtplugDeviceMessageType, DeviceMessageAttributes, DeviceMessageAttributesMap, OscillateCmd,
+    OscillateSubcommand, RotateCmd, RotationSubcommand, VibrateCmd, VibrateSubcommand,
   };
   #[test]
   pub fn test_command_generator_vibration() {
@@ -337,5 +404,50 @@ mod test {
     assert!(mgr.update_rotation(&rotate_msg_invalid).is_err());
   }
 
+  #[test]
+  pub fn test_command_generator_oscillation() {
+    let mut attributes_map = DeviceMessageAttributesMap::new();
+
+    let oscillate_attributes = DeviceMessageAttributes {
+      feature_count: Some(2),
+      step_count: Some(vec![20, 20]),
+      ..Default::default()
+    };
+    attributes_map.insert(
+      ButtplugDeviceMessageType::OscillateCmd,
+      oscillate_attributes,
+    );
+    let mut mgr = GenericCommandManager::new(&attributes_map);
+    assert_eq!(mgr.get_stop_commands().len(), 1);
+    let oscillate_msg = OscillateCmd::new(
+      0,
+      vec![
+        OscillateSubcommand::new(0, 0.5),
+        OscillateSubcommand::new(1, 0.5),
+      ],
+    );
+    assert_eq!(
+      mgr.update_oscillation(&oscillate_msg).unwrap(),
+      vec![Some(10), Some(10)]
+    );
+    assert_eq!(
+      mgr.update_oscillation(&oscillate_msg).unwrap(),
+      vec![None, None]
+    );
+    let oscillate_msg_2 = OscillateCmd::new(
+      0,
+      vec![
+        OscillateSubcommand::new(0, 0.5),
+        OscillateSubcommand::new(1, 0.75),
+      ],
+    );
+    assert_eq!(
+      mgr.update_oscillation(&oscillate_msg_2).unwrap(),
+      vec![None, Some(15)]
+    );
+    let oscillate_msg_invalid = OscillateCmd::new(0, vec![OscillateSubcommand::new(2, 0.5)]);
+    assert!(mgr.update_oscillation(&oscillate_msg_invalid).is_err());
+  }
+
   // TODO Write test for vibration stop generator
 }
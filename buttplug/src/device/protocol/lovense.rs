@@ -152,6 +152,25 @@ impl ButtplugProtocolCommandHandler for Lovense {
     })
   }
 
+  fn handle_oscillate_cmd(
+    &self,
+    device: Arc<DeviceImpl>,
+    msg: messages::OscillateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager.lock().await.update_oscillation(&msg)?;
+      // Oscillating motors (i.e. the Osci's head) take the same command as
+      // vibrators do, Lovense doesn't have a separate one for them.
+      if let Some(speed) = result[0] {
+        let lovense_cmd = format!("Vibrate:{};", speed).as_bytes().to_vec();
+        let fut = device.write_value(DeviceWriteCmd::new(Endpoint::Tx, lovense_cmd, false));
+        fut.await?;
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+
   fn handle_rotate_cmd(
     &self,
     device: Arc<DeviceImpl>,
@@ -221,6 +221,10 @@ pub trait ButtplugProtocolProperties {
         &ButtplugDeviceMessageType::RotateCmd,
         &self.message_attributes(),
       ),
+      ButtplugDeviceCommandMessageUnion::OscillateCmd(_) => check_message_support(
+        &ButtplugDeviceMessageType::OscillateCmd,
+        &self.message_attributes(),
+      ),
       ButtplugDeviceCommandMessageUnion::RSSILevelCmd(_) => check_message_support(
         &ButtplugDeviceMessageType::RSSILevelCmd,
         &self.message_attributes(),
@@ -271,6 +275,9 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
       ButtplugDeviceCommandMessageUnion::RawReadCmd(msg) => self.handle_raw_read_cmd(device, msg),
       ButtplugDeviceCommandMessageUnion::RawWriteCmd(msg) => self.handle_raw_write_cmd(device, msg),
       ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => self.handle_rotate_cmd(device, msg),
+      ButtplugDeviceCommandMessageUnion::OscillateCmd(msg) => {
+        self.handle_oscillate_cmd(device, msg)
+      }
       ButtplugDeviceCommandMessageUnion::SingleMotorVibrateCmd(msg) => {
         self.handle_single_motor_vibrate_cmd(device, msg)
       }
@@ -468,6 +475,14 @@ pub trait ButtplugProtocolCommandHandler: Send + ButtplugProtocolProperties {
     self.command_unimplemented(print_type_of(&message))
   }
 
+  fn handle_oscillate_cmd(
+    &self,
+    _device: Arc<DeviceImpl>,
+    message: messages::OscillateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    self.command_unimplemented(print_type_of(&message))
+  }
+
   fn handle_battery_level_cmd(
     &self,
     device: Arc<DeviceImpl>,
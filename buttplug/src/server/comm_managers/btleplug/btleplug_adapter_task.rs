@@ -1,34 +1,135 @@
-use super::btleplug_device_impl::BtlePlugDeviceImplCreator;
-use crate::server::comm_managers::DeviceCommunicationEvent;
+use super::btleplug_device_impl::{
+  BtlePlugDeviceImplCreator, BtleplugEmptyWritePolicy, BtleplugEndpointOverrides,
+};
+use crate::{
+  core::{errors::ButtplugDeviceError, ButtplugResult},
+  server::comm_managers::DeviceCommunicationEvent,
+  util::future::ButtplugFutureStateShared,
+};
 use btleplug::{
   api::{BDAddr, Central, CentralEvent, Manager as _, Peripheral},
   platform::{Adapter, Manager},
 };
-use futures::{future::FutureExt, StreamExt};
+use futures::{
+  future::{self, BoxFuture, FutureExt},
+  StreamExt,
+};
 use futures_timer::Delay;
-use std::time::Duration;
-use tokio::sync::mpsc::{Receiver, Sender};
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+
+/// How long stopping a scan waits for connections to devices found during it
+/// to be torn down. Those connections are cancelled either way, this only
+/// keeps a wedged connection from holding up the adapter task.
+const SCAN_STOP_TIMEOUT: Duration = Duration::from_secs(5);
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub enum BtleplugAdapterCommand {
   StartScanning,
   StopScanning,
+  /// Look for a single device by address, giving up after the duration.
+  ReconnectDevice(BDAddr, Duration, ButtplugFutureStateShared<ButtplugResult>),
+}
+
+/// A device we've been asked to reconnect, but haven't found yet.
+struct PendingReconnect {
+  address: BDAddr,
+  timeout: Duration,
+  deadline: Instant,
+  waker: ButtplugFutureStateShared<ButtplugResult>,
+}
+
+/// Held by device creators for devices found while scanning, until they're
+/// done connecting. Lets a scan cancel connections it started when it stops.
+pub(super) struct ScanConnectGuard {
+  token: CancellationToken,
+  // Never sent on, only dropped. See ScanSession::stop.
+  _in_flight: mpsc::Sender<()>,
+}
+
+impl ScanConnectGuard {
+  /// Token that is cancelled once the scan this device was found in stops.
+  pub(super) fn token(&self) -> CancellationToken {
+    self.token.clone()
+  }
+}
+
+/// A running scan, along with connections to devices found during it.
+struct ScanSession {
+  token: CancellationToken,
+  in_flight_sender: mpsc::Sender<()>,
+  in_flight_receiver: mpsc::Receiver<()>,
+}
+
+impl ScanSession {
+  fn new() -> Self {
+    let (in_flight_sender, in_flight_receiver) = mpsc::channel(1);
+    Self {
+      token: CancellationToken::new(),
+      in_flight_sender,
+      in_flight_receiver,
+    }
+  }
+
+  fn connect_guard(&self) -> ScanConnectGuard {
+    ScanConnectGuard {
+      token: self.token.child_token(),
+      _in_flight: self.in_flight_sender.clone(),
+    }
+  }
+
+  /// Cancels connections to devices found during the scan, and waits up to
+  /// `timeout` for all of them to either finish or be torn down.
+  async fn stop(self, timeout: Duration) {
+    let Self {
+      token,
+      in_flight_sender,
+      mut in_flight_receiver,
+    } = self;
+    token.cancel();
+    drop(in_flight_sender);
+    // Nothing is ever sent, so this only returns once every guard is dropped.
+    select! {
+      _ = in_flight_receiver.recv().fuse() => {},
+      _ = Delay::new(timeout).fuse() => {
+        warn!(
+          "Connections from stopped scan still tearing down after {:?}, not waiting any longer.",
+          timeout
+        );
+      }
+    };
+  }
 }
 
 pub struct BtleplugAdapterTask {
   event_sender: Sender<DeviceCommunicationEvent>,
   command_receiver: Receiver<BtleplugAdapterCommand>,
-
+  scanning_status: Arc<AtomicBool>,
+  empty_write_policy: BtleplugEmptyWritePolicy,
+  endpoint_overrides: Arc<BtleplugEndpointOverrides>,
 }
 
 impl BtleplugAdapterTask {
   pub fn new(
     event_sender: Sender<DeviceCommunicationEvent>,
     command_receiver: Receiver<BtleplugAdapterCommand>,
+    scanning_status: Arc<AtomicBool>,
+    empty_write_policy: BtleplugEmptyWritePolicy,
+    endpoint_overrides: Arc<BtleplugEndpointOverrides>,
   ) -> Self {
     Self {
       event_sender,
       command_receiver,
+      scanning_status,
+      empty_write_policy,
+      endpoint_overrides,
     }
   }
 
@@ -37,11 +138,21 @@ impl BtleplugAdapterTask {
     bd_addr: &BDAddr,
     adapter: &Adapter,
     tried_addresses: &mut Vec<BDAddr>,
-  ) {
-    let peripheral = adapter.peripheral(*bd_addr).await.unwrap();
+    scan_session: Option<&ScanSession>,
+  ) -> bool {
+    let peripheral = match adapter.peripheral(*bd_addr).await {
+      Ok(peripheral) => peripheral,
+      Err(e) => {
+        trace!("Peripheral {} no longer available: {:?}", bd_addr, e);
+        return false;
+      }
+    };
     // If a device has no discernable name, we can't do anything
     // with it, just ignore it.
-    let properties = peripheral.properties().await.unwrap().unwrap();
+    let properties = match peripheral.properties().await {
+      Ok(Some(properties)) => properties,
+      _ => return false,
+    };
     if let Some(name) = properties.local_name {
       let span = info_span!(
         "btleplug enumeration",
@@ -58,12 +169,18 @@ impl BtleplugAdapterTask {
         let address = properties.address;
         debug!("Found new bluetooth device: {} {}", name, address);
         tried_addresses.push(address);
-        let device_creator = Box::new(BtlePlugDeviceImplCreator::new(
+        let mut device_creator = BtlePlugDeviceImplCreator::new(
           &name,
           &properties.address,
           peripheral.clone(),
           adapter.clone(),
-        ));
+          self.empty_write_policy,
+          self.endpoint_overrides.clone(),
+        );
+        if let Some(session) = scan_session {
+          device_creator.set_scan_connect_guard(session.connect_guard());
+        }
+        let device_creator = Box::new(device_creator);
 
         if self
           .event_sender
@@ -76,7 +193,9 @@ impl BtleplugAdapterTask {
           .is_err()
         {
           error!("Device manager receiver dropped, cannot send device found message.");
+          return false;
         }
+        return true;
       }
     } else {
       trace!(
@@ -84,6 +203,83 @@ impl BtleplugAdapterTask {
         properties.address
       );
     }
+    false
+  }
+
+  /// Handles a peripheral showing up. While scanning, any peripheral may be
+  /// added. Otherwise, we only care about devices we've been asked to
+  /// reconnect, so unrelated devices don't surface.
+  async fn handle_peripheral(
+    &self,
+    bd_addr: &BDAddr,
+    adapter: &Adapter,
+    tried_addresses: &mut Vec<BDAddr>,
+    scan_session: Option<&ScanSession>,
+    pending_reconnects: &mut Vec<PendingReconnect>,
+  ) {
+    let pending_index = pending_reconnects
+      .iter()
+      .position(|pending| pending.address == *bd_addr);
+    let scanning = scan_session.is_some();
+    if !scanning && pending_index.is_none() {
+      return;
+    }
+    // Reconnects aren't part of the scan, so stopping the scan shouldn't
+    // cancel them.
+    let scan_session = scan_session.filter(|_| pending_index.is_none());
+    if self
+      .maybe_add_peripheral(bd_addr, adapter, tried_addresses, scan_session)
+      .await
+    {
+      if let Some(index) = pending_index {
+        info!("Found device {} for reconnection.", bd_addr);
+        pending_reconnects.remove(index).waker.set_reply(Ok(()));
+        // If the scan was only running to find reconnecting devices, it's
+        // done now.
+        if !scanning && pending_reconnects.is_empty() {
+          if let Err(e) = adapter.stop_scan().await {
+            error!("Error stopping reconnection scan: {:?}", e);
+          }
+        }
+      }
+    }
+  }
+
+  /// Fails any reconnects that have run out of time. Returns true if any
+  /// reconnects were removed.
+  fn expire_reconnects(pending_reconnects: &mut Vec<PendingReconnect>) -> bool {
+    let now = Instant::now();
+    let count = pending_reconnects.len();
+    pending_reconnects.retain(|pending| {
+      if pending.deadline > now {
+        return true;
+      }
+      info!("Device {} not found for reconnection, giving up.", pending.address);
+      pending.waker.set_reply(Err(
+        ButtplugDeviceError::DeviceReconnectTimeout(
+          pending.address.to_string(),
+          pending.timeout.as_millis() as u64,
+        )
+        .into(),
+      ));
+      false
+    });
+    pending_reconnects.len() != count
+  }
+
+  /// Resolves when the earliest pending reconnect runs out of time, so we wake
+  /// up to fail it even if nothing else is happening. Never resolves if there
+  /// are no pending reconnects, so it doesn't starve the other branches of the
+  /// event loop.
+  fn next_reconnect_deadline(pending_reconnects: &[PendingReconnect]) -> BoxFuture<'static, ()> {
+    let deadline = pending_reconnects
+      .iter()
+      .map(|pending| pending.deadline)
+      .min();
+    match deadline {
+      Some(deadline) => Delay::new(deadline.saturating_duration_since(Instant::now())).boxed(),
+      None => future::pending().boxed(),
+    }
   }
 
   pub async fn run(&mut self) {
@@ -132,20 +328,33 @@ impl BtleplugAdapterTask {
     let mut events = adapter.events().await.unwrap();
 
     let mut tried_addresses = vec![];
+    let mut scan_session: Option<ScanSession> = None;
+    let mut pending_reconnects: Vec<PendingReconnect> = vec![];
 
     loop {
+      if Self::expire_reconnects(&mut pending_reconnects)
+        && pending_reconnects.is_empty()
+        && scan_session.is_none()
+      {
+        if let Err(e) = adapter.stop_scan().await {
+          error!("Error stopping reconnection scan: {:?}", e);
+        }
+      }
+
       #[cfg(target_os = "linux")]
       let event_fut = Delay::new(Duration::from_secs(2));
       #[cfg(not(target_os = "linux"))]
       let event_fut = events.next();
 
+      let reconnect_timeout_fut = Self::next_reconnect_deadline(&pending_reconnects);
+
       select! {
         event = event_fut.fuse() => {
           #[cfg(not(target_os = "linux"))]
           {
             match event.unwrap() {
               CentralEvent::DeviceDiscovered(bd_addr) | CentralEvent::DeviceUpdated(bd_addr) => {
-                self.maybe_add_peripheral(&bd_addr, &adapter, &mut tried_addresses).await;
+                self.handle_peripheral(&bd_addr, &adapter, &mut tried_addresses, scan_session.as_ref(), &mut pending_reconnects).await;
               }
               CentralEvent::DeviceDisconnected(addr) => {
                 debug!("BTLEPlug Device disconnected: {:?}", addr);
@@ -169,18 +378,62 @@ impl BtleplugAdapterTask {
               // We'll incur 2 peripheral lookups here but this isn't really a slow call so it's
               // fine.
               let properties = peripheral.properties().await.unwrap().unwrap();
-              self.maybe_add_peripheral(&properties.address, &adapter, &mut tried_addresses).await;
+              self.handle_peripheral(&properties.address, &adapter, &mut tried_addresses, scan_session.as_ref(), &mut pending_reconnects).await;
             }
           }
         },
+        _ = reconnect_timeout_fut.fuse() => {},
         command = self.command_receiver.recv().fuse() => {
           if let Some(cmd) = command {
             match cmd {
               BtleplugAdapterCommand::StartScanning => {
                 tried_addresses.clear();
+                if scan_session.is_none() {
+                  scan_session = Some(ScanSession::new());
+                }
                 adapter.start_scan().await.unwrap();
               }
-              BtleplugAdapterCommand::StopScanning => adapter.stop_scan().await.unwrap(),
+              BtleplugAdapterCommand::StopScanning => {
+                // Keep scanning under the hood if we're still looking for
+                // devices to reconnect.
+                if pending_reconnects.is_empty() {
+                  adapter.stop_scan().await.unwrap();
+                }
+                // Make sure devices found just before the stop don't show up
+                // after it, then let the device manager know we're done.
+                if let Some(session) = scan_session.take() {
+                  debug!("Cancelling in-flight connections for stopped scan.");
+                  session.stop(SCAN_STOP_TIMEOUT).await;
+                }
+                self.scanning_status.store(false, Ordering::SeqCst);
+                if self
+                  .event_sender
+                  .send(DeviceCommunicationEvent::ScanningFinished)
+                  .await
+                  .is_err()
+                {
+                  error!("Device manager receiver dropped, cannot send scanning finished message.");
+                }
+              }
+              BtleplugAdapterCommand::ReconnectDevice(address, timeout, waker) => {
+                info!("Looking for device {} to reconnect.", address);
+                // Let the device through even if we tried it earlier in this
+                // scanning session. We wait for the device to advertise
+                // instead of using whatever the adapter has cached, as cached
+                // peripherals may be long out of range.
+                tried_addresses.retain(|bd_addr| *bd_addr != address);
+                if scan_session.is_none() && pending_reconnects.is_empty() {
+                  if let Err(e) = adapter.start_scan().await {
+                    error!("Error starting reconnection scan: {:?}", e);
+                  }
+                }
+                pending_reconnects.push(PendingReconnect {
+                  address,
+                  timeout,
+                  deadline: Instant::now() + timeout,
+                  waker,
+                });
+              }
             }
           }
         }
@@ -188,3 +441,124 @@ impl BtleplugAdapterTask {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    core::errors::ButtplugError,
+    util::{async_manager, future::ButtplugFuture},
+  };
+  use std::str::FromStr;
+
+  fn pending_reconnect(
+    address: &str,
+    timeout: Duration,
+  ) -> (PendingReconnect, ButtplugFuture<ButtplugResult>) {
+    let future = ButtplugFuture::default();
+    let pending = PendingReconnect {
+      address: BDAddr::from_str(address).unwrap(),
+      timeout,
+      deadline: Instant::now() + timeout,
+      waker: future.get_state_clone(),
+    };
+    (pending, future)
+  }
+
+  #[test]
+  fn test_reconnect_deadline_does_not_starve_event_loop() {
+    async_manager::block_on(async {
+      // With nothing to reconnect, the deadline should never win over the
+      // linux peripheral poll (or any other event).
+      let no_reconnects = select! {
+        _ = BtleplugAdapterTask::next_reconnect_deadline(&[]).fuse() => false,
+        _ = Delay::new(Duration::from_millis(100)).fuse() => true,
+      };
+      assert!(no_reconnects);
+      // A reconnect that's about to run out of time should wake us up before
+      // the next poll.
+      let (pending, _future) = pending_reconnect("00:00:00:00:00:01", Duration::from_millis(10));
+      let pending_reconnects = vec![pending];
+      let woke_for_reconnect = select! {
+        _ = BtleplugAdapterTask::next_reconnect_deadline(&pending_reconnects).fuse() => true,
+        _ = Delay::new(Duration::from_secs(2)).fuse() => false,
+      };
+      assert!(woke_for_reconnect);
+    });
+  }
+
+  #[test]
+  fn test_expire_reconnects() {
+    async_manager::block_on(async {
+      let (expired, expired_future) =
+        pending_reconnect("00:00:00:00:00:01", Duration::from_millis(0));
+      let (waiting, _waiting_future) =
+        pending_reconnect("00:00:00:00:00:02", Duration::from_secs(60));
+      let mut pending_reconnects = vec![expired, waiting];
+      assert!(BtleplugAdapterTask::expire_reconnects(
+        &mut pending_reconnects
+      ));
+      assert_eq!(pending_reconnects.len(), 1);
+      assert!(matches!(
+        expired_future.await,
+        Err(ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::DeviceReconnectTimeout(..)
+        ))
+      ));
+      assert!(!BtleplugAdapterTask::expire_reconnects(
+        &mut pending_reconnects
+      ));
+    });
+  }
+
+  #[test]
+  fn test_scan_session_stop_cancels_in_flight_connects() {
+    async_manager::block_on(async {
+      let session = ScanSession::new();
+      // Stands in for the creator of a device found right before the stop.
+      let guard = session.connect_guard();
+      let (added_sender, mut added_receiver) = mpsc::unbounded_channel();
+      async_manager::spawn(async move {
+        let token = guard.token();
+        select! {
+          _ = Delay::new(Duration::from_secs(5)).fuse() => {
+            let _ = added_sender.send(());
+          }
+          _ = token.cancelled().fuse() => {}
+        }
+        // Tearing down the connection takes a moment, stop() should wait.
+        Delay::new(Duration::from_millis(50)).await;
+        drop(guard);
+        drop(added_sender);
+      })
+      .unwrap();
+      session.stop(SCAN_STOP_TIMEOUT).await;
+      assert!(added_receiver.recv().await.is_none());
+    });
+  }
+
+  #[test]
+  fn test_scan_session_stop_times_out() {
+    async_manager::block_on(async {
+      let session = ScanSession::new();
+      // Stands in for a device creator that's wedged tearing down its
+      // connection, but still honors the cancellation.
+      let guard = session.connect_guard();
+      let (added_sender, mut added_receiver) = mpsc::unbounded_channel();
+      async_manager::spawn(async move {
+        Delay::new(Duration::from_secs(5)).await;
+        if !guard.token().is_cancelled() {
+          let _ = added_sender.send(());
+        }
+        drop(guard);
+      })
+      .unwrap();
+      let start = Instant::now();
+      session.stop(Duration::from_millis(100)).await;
+      assert!(start.elapsed() < Duration::from_secs(2));
+      // Nothing found during the scan should show up after the stop.
+      Delay::new(Duration::from_millis(200)).await;
+      assert!(added_receiver.try_recv().is_err());
+    });
+  }
+}
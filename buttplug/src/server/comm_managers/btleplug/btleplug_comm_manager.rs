@@ -1,18 +1,87 @@
-use super::btleplug_adapter_task::{BtleplugAdapterCommand, BtleplugAdapterTask};
+use super::{
+  btleplug_adapter_task::{BtleplugAdapterCommand, BtleplugAdapterTask},
+  btleplug_device_impl::{BtleplugEmptyWritePolicy, BtleplugEndpointOverrides},
+};
 use crate::{
-  core::ButtplugResultFuture,
+  core::{errors::ButtplugDeviceError, ButtplugResultFuture},
+  device::Endpoint,
   server::comm_managers::{
     DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
   },
-  util::async_manager,
+  util::{async_manager, future::ButtplugFuture},
+};
+use btleplug::api::BDAddr;
+use std::{
+  str::FromStr,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::Duration,
 };
-use std::sync::{atomic::AtomicBool, Arc};
+use uuid::Uuid;
 
 use tokio::sync::mpsc::{channel, Sender};
 
-#[derive(Default)]
+/// Default amount of time to look for a device when reconnecting.
+const DEFAULT_RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct BtlePlugCommunicationManagerBuilder {
   sender: Option<Sender<DeviceCommunicationEvent>>,
+  reconnect_timeout: Duration,
+  empty_write_policy: BtleplugEmptyWritePolicy,
+  endpoint_overrides: BtleplugEndpointOverrides,
+}
+
+impl Default for BtlePlugCommunicationManagerBuilder {
+  fn default() -> Self {
+    Self {
+      sender: None,
+      reconnect_timeout: DEFAULT_RECONNECT_TIMEOUT,
+      empty_write_policy: BtleplugEmptyWritePolicy::default(),
+      endpoint_overrides: BtleplugEndpointOverrides::new(),
+    }
+  }
+}
+
+impl BtlePlugCommunicationManagerBuilder {
+  /// Sets how long to look for a device when reconnecting it, before giving
+  /// up. Defaults to 10 seconds.
+  pub fn reconnect_timeout(mut self, timeout: Duration) -> Self {
+    self.reconnect_timeout = timeout;
+    self
+  }
+
+  /// Sets how devices handle writes with no data. Defaults to
+  /// [BtleplugEmptyWritePolicy::Skip].
+  pub fn empty_write_policy(mut self, policy: BtleplugEmptyWritePolicy) -> Self {
+    self.empty_write_policy = policy;
+    self
+  }
+
+  /// Maps `endpoint` to the characteristic with UUID `characteristic` on the
+  /// device at `address`, instead of what the device configuration says. For
+  /// firmware versions that have moved things around before the
+  /// configuration catches up.
+  ///
+  /// Overrides only match by address, not advertised name, so they only ever
+  /// apply to the one device they were set up for.
+  ///
+  /// Overrides referencing characteristics a device doesn't have are ignored
+  /// with a warning when the device connects.
+  pub fn endpoint_override(
+    mut self,
+    address: BDAddr,
+    endpoint: Endpoint,
+    characteristic: Uuid,
+  ) -> Self {
+    self
+      .endpoint_overrides
+      .entry(address)
+      .or_default()
+      .insert(endpoint, characteristic);
+    self
+  }
 }
 
 impl DeviceCommunicationManagerBuilder for BtlePlugCommunicationManagerBuilder {
@@ -24,24 +93,45 @@ impl DeviceCommunicationManagerBuilder for BtlePlugCommunicationManagerBuilder {
   fn finish(mut self) -> Box<dyn DeviceCommunicationManager> {
     Box::new(BtlePlugCommunicationManager::new(
       self.sender.take().unwrap(),
+      self.reconnect_timeout,
+      self.empty_write_policy,
+      self.endpoint_overrides,
     ))
   }
 }
 
 pub struct BtlePlugCommunicationManager {
   adapter_event_sender: Sender<BtleplugAdapterCommand>,
+  reconnect_timeout: Duration,
+  scanning_status: Arc<AtomicBool>,
 }
 
 impl BtlePlugCommunicationManager {
-  pub fn new(event_sender: Sender<DeviceCommunicationEvent>) -> Self {
+  pub fn new(
+    event_sender: Sender<DeviceCommunicationEvent>,
+    reconnect_timeout: Duration,
+    empty_write_policy: BtleplugEmptyWritePolicy,
+    endpoint_overrides: BtleplugEndpointOverrides,
+  ) -> Self {
     let (sender, receiver) = channel(256);
+    let scanning_status = Arc::new(AtomicBool::new(false));
+    let task_scanning_status = scanning_status.clone();
+    let endpoint_overrides = Arc::new(endpoint_overrides);
     async_manager::spawn(async move {
-      let mut task = BtleplugAdapterTask::new(event_sender, receiver);
+      let mut task = BtleplugAdapterTask::new(
+        event_sender,
+        receiver,
+        task_scanning_status,
+        empty_write_policy,
+        endpoint_overrides,
+      );
       task.run().await;
     })
     .unwrap();
     Self {
       adapter_event_sender: sender,
+      reconnect_timeout,
+      scanning_status,
     }
   }
 }
@@ -53,6 +143,10 @@ impl DeviceCommunicationManager for BtlePlugCommunicationManager {
 
   fn start_scanning(&self) -> ButtplugResultFuture {
     let adapter_event_sender = self.adapter_event_sender.clone();
+    // Set before the adapter task gets the command, so the device manager
+    // doesn't think we've already finished. The task clears it once a stop
+    // has been fully handled.
+    self.scanning_status.store(true, Ordering::SeqCst);
     Box::pin(async move {
       adapter_event_sender
         .send(BtleplugAdapterCommand::StartScanning)
@@ -74,7 +168,30 @@ impl DeviceCommunicationManager for BtlePlugCommunicationManager {
   }
 
   fn scanning_status(&self) -> Arc<AtomicBool> {
-    Arc::new(AtomicBool::new(false))
+    self.scanning_status.clone()
+  }
+
+  fn reconnect_device(&self, address: &str) -> Option<ButtplugResultFuture> {
+    // If it doesn't parse as a bluetooth address, it's not our device.
+    let bd_addr = BDAddr::from_str(address).ok()?;
+    let adapter_event_sender = self.adapter_event_sender.clone();
+    let timeout = self.reconnect_timeout;
+    Some(Box::pin(async move {
+      let fut = ButtplugFuture::default();
+      adapter_event_sender
+        .send(BtleplugAdapterCommand::ReconnectDevice(
+          bd_addr,
+          timeout,
+          fut.get_state_clone(),
+        ))
+        .await
+        .map_err(|_| {
+          ButtplugDeviceError::DeviceConnectionError(
+            "Bluetooth adapter task is no longer running.".to_owned(),
+          )
+        })?;
+      fut.await
+    }))
   }
 }
 /*
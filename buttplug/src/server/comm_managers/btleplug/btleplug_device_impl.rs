@@ -2,7 +2,7 @@ use crate::{
   core::{
     errors::{ButtplugDeviceError, ButtplugError},
     messages::RawReading,
-    ButtplugResultFuture,
+    ButtplugResult, ButtplugResultFuture,
   },
   device::{
     configuration_manager::{BluetoothLESpecifier, DeviceSpecifier, ProtocolDefinition},
@@ -12,6 +12,7 @@ use crate::{
   server::comm_managers::ButtplugDeviceSpecificError,
   util::async_manager,
 };
+use super::btleplug_adapter_task::ScanConnectGuard;
 use async_trait::async_trait;
 use btleplug::{
   api::{BDAddr, Central, CentralEvent, Characteristic, Peripheral, ValueNotification, WriteType},
@@ -31,22 +32,169 @@ use std::{
   },
 };
 use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// How to handle writes with no data.
+///
+/// BLE stacks disagree on zero-length writes: some fail them, others quietly
+/// succeed. To behave the same on every platform, we deal with them before
+/// they get to btleplug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtleplugEmptyWritePolicy {
+  /// Skip the write, reporting success. As nothing would have been sent
+  /// anyways, this is the default.
+  Skip,
+  /// Fail the write with [ButtplugDeviceError::EmptyWrite].
+  Reject,
+}
+
+impl Default for BtleplugEmptyWritePolicy {
+  fn default() -> Self {
+    BtleplugEmptyWritePolicy::Skip
+  }
+}
+
+impl BtleplugEmptyWritePolicy {
+  /// Result of an empty write to `endpoint` under this policy.
+  fn empty_write_result(&self, endpoint: Endpoint) -> ButtplugResult {
+    match self {
+      BtleplugEmptyWritePolicy::Skip => {
+        debug!("Skipping empty write to endpoint {:?}.", endpoint);
+        Ok(())
+      }
+      BtleplugEmptyWritePolicy::Reject => Err(ButtplugDeviceError::EmptyWrite(endpoint).into()),
+    }
+  }
+}
+
+/// User supplied endpoint to characteristic mappings, keyed by device
+/// address. These take precedence over the device configuration.
+///
+/// Keyed by address rather than advertised name, since devices of the same
+/// model advertise the same name even when their firmware lays out
+/// characteristics differently.
+pub type BtleplugEndpointOverrides = HashMap<BDAddr, HashMap<Endpoint, Uuid>>;
+
+/// Expands a 16-bit assigned number to a full Bluetooth base UUID.
+fn bluetooth_base_uuid(short: u128) -> Uuid {
+  Uuid::from_u128((short << 96) | 0x0000_1000_8000_00805f9b34fb)
+}
+
+/// Standard BLE Device Information Service UUID.
+fn device_information_service() -> Uuid {
+  bluetooth_base_uuid(0x180a)
+}
+
+/// Standard BLE Device Information Service characteristics. Devices that
+/// expose the service get the matching endpoints, whether or not their
+/// configuration lists them.
+fn device_information_characteristics() -> [(Endpoint, Uuid); 5] {
+  [
+    (Endpoint::RxBLEManufacturer, bluetooth_base_uuid(0x2a29)),
+    (Endpoint::RxBLEModel, bluetooth_base_uuid(0x2a24)),
+    (Endpoint::RxBLESerialNumber, bluetooth_base_uuid(0x2a25)),
+    (Endpoint::RxBLEFirmwareRevision, bluetooth_base_uuid(0x2a26)),
+    (Endpoint::RxBLEHardwareRevision, bluetooth_base_uuid(0x2a27)),
+  ]
+}
+
+/// Works out which characteristic each endpoint uses, based on the protocol
+/// configuration's services, standard Device Information Service
+/// characteristics if the device has that service, then any overrides for the
+/// device. Only characteristics the device actually exposes are used.
+fn resolve_endpoint_uuids(
+  name: &str,
+  services: &HashMap<Uuid, HashMap<Endpoint, Uuid>>,
+  discovered: &[Uuid],
+  has_device_information_service: bool,
+  overrides: Option<&HashMap<Endpoint, Uuid>>,
+) -> HashMap<Endpoint, Uuid> {
+  let mut endpoint_uuids = HashMap::new();
+  for proto_service in services.values() {
+    for (chr_name, chr_uuid) in proto_service.iter() {
+      if discovered.contains(chr_uuid) {
+        endpoint_uuids.insert(*chr_name, *chr_uuid);
+      }
+    }
+  }
+  for (endpoint, chr_uuid) in device_information_characteristics().iter() {
+    if has_device_information_service && discovered.contains(chr_uuid) {
+      endpoint_uuids.entry(*endpoint).or_insert(*chr_uuid);
+    }
+  }
+  for (endpoint, chr_uuid) in overrides.into_iter().flatten() {
+    if discovered.contains(chr_uuid) {
+      info!(
+        "Overriding endpoint {} on {} to use characteristic {}",
+        endpoint, name, chr_uuid
+      );
+      endpoint_uuids.insert(*endpoint, *chr_uuid);
+    } else {
+      warn!(
+        "Endpoint override for {} on {} uses characteristic {}, which the device does not expose. Ignoring.",
+        endpoint, name, chr_uuid
+      );
+    }
+  }
+  endpoint_uuids
+}
+
 pub struct BtlePlugDeviceImplCreator<T: Peripheral + 'static> {
   name: String,
   address: BDAddr,
   device: T,
   adapter: Adapter,
+  empty_write_policy: BtleplugEmptyWritePolicy,
+  endpoint_overrides: Arc<BtleplugEndpointOverrides>,
+  /// Set if the device was found while scanning, so that stopping the scan
+  /// cancels the connection attempt.
+  scan_connect_guard: Option<ScanConnectGuard>,
 }
 
 impl<T: Peripheral> BtlePlugDeviceImplCreator<T> {
-  pub fn new(name: &str, address: &BDAddr, device: T, adapter: Adapter) -> Self {
+  pub fn new(
+    name: &str,
+    address: &BDAddr,
+    device: T,
+    adapter: Adapter,
+    empty_write_policy: BtleplugEmptyWritePolicy,
+    endpoint_overrides: Arc<BtleplugEndpointOverrides>,
+  ) -> Self {
     Self {
       name: name.to_owned(),
       address: address.to_owned(),
       device,
       adapter,
+      empty_write_policy,
+      endpoint_overrides,
+      scan_connect_guard: None,
+    }
+  }
+
+  pub(super) fn set_scan_connect_guard(&mut self, guard: ScanConnectGuard) {
+    self.scan_connect_guard = Some(guard);
+  }
+
+  async fn connect_and_discover(&self) -> Result<Vec<Characteristic>, ButtplugError> {
+    if let Err(err) = self.device.connect().await {
+      let return_err = ButtplugDeviceError::DeviceSpecificError(
+        ButtplugDeviceSpecificError::BtleplugError(format!("{:?}", err)),
+      );
+      return Err(return_err.into());
+    }
+    match self.device.discover_characteristics().await {
+      Ok(chars) => Ok(chars),
+      Err(err) => {
+        error!("BTLEPlug error discovering characteristics: {:?}", err);
+        Err(
+          ButtplugDeviceError::DeviceConnectionError(format!(
+            "BTLEPlug error discovering characteristics: {:?}",
+            err
+          ))
+          .into(),
+        )
+      }
     }
   }
 }
@@ -67,35 +215,48 @@ impl<T: Peripheral> ButtplugDeviceImplCreator for BtlePlugDeviceImplCreator<T> {
     &mut self,
     protocol: ProtocolDefinition,
   ) -> Result<DeviceImpl, ButtplugError> {
-    if let Err(err) = self.device.connect().await {
-      let return_err = ButtplugDeviceError::DeviceSpecificError(
-        ButtplugDeviceSpecificError::BtleplugError(format!("{:?}", err)),
-      );
-      return Err(return_err.into());
-    }
-    // Map UUIDs to endpoints
-    let mut uuid_map = HashMap::<Uuid, Endpoint>::new();
-    let mut endpoints = HashMap::<Endpoint, Characteristic>::new();
-    let chars = match self.device.discover_characteristics().await {
-      Ok(chars) => chars,
-      Err(err) => {
-        error!("BTLEPlug error discovering characteristics: {:?}", err);
+    let scan_token = self
+      .scan_connect_guard
+      .as_ref()
+      .map_or_else(CancellationToken::new, |guard| guard.token());
+    let chars = select! {
+      result = self.connect_and_discover().fuse() => Some(result?),
+      _ = scan_token.cancelled().fuse() => None,
+    };
+    let chars = match chars {
+      Some(chars) => chars,
+      None => {
+        info!("Scanning stopped while connecting to {}, disconnecting.", self.address);
+        if let Err(err) = self.device.disconnect().await {
+          debug!("Error disconnecting cancelled device: {:?}", err);
+        }
         return Err(
-          ButtplugDeviceError::DeviceConnectionError(format!(
-            "BTLEPlug error discovering characteristics: {:?}",
-            err
-          ))
+          ButtplugDeviceError::DeviceConnectionError(
+            "Scanning stopped before device finished connecting.".to_owned(),
+          )
           .into(),
         );
       }
     };
-    for proto_service in protocol.btle.unwrap().services.values() {
-      for (chr_name, chr_uuid) in proto_service.iter() {
-        let maybe_chr = chars.iter().find(|c| c.uuid == *chr_uuid);
-        if let Some(chr) = maybe_chr {
-          endpoints.insert(*chr_name, chr.clone());
-          uuid_map.insert(*chr_uuid, *chr_name);
-        }
+    // Map UUIDs to endpoints
+    let mut uuid_map = HashMap::<Uuid, Endpoint>::new();
+    let mut endpoints = HashMap::<Endpoint, Characteristic>::new();
+    let discovered: Vec<Uuid> = chars.iter().map(|c| c.uuid).collect();
+    let has_device_information_service = match self.device.properties().await {
+      Ok(Some(properties)) => properties.services.contains(&device_information_service()),
+      _ => false,
+    };
+    let endpoint_uuids = resolve_endpoint_uuids(
+      &self.name,
+      &protocol.btle.unwrap().services,
+      &discovered,
+      has_device_information_service,
+      self.endpoint_overrides.get(&self.address),
+    );
+    for (chr_name, chr_uuid) in endpoint_uuids {
+      if let Some(chr) = chars.iter().find(|c| c.uuid == chr_uuid) {
+        endpoints.insert(chr_name, chr.clone());
+        uuid_map.insert(chr_uuid, chr_name);
       }
     }
     let notification_stream = self.device.notifications().await.unwrap();
@@ -107,6 +268,7 @@ impl<T: Peripheral> ButtplugDeviceImplCreator for BtlePlugDeviceImplCreator<T> {
       notification_stream,
       endpoints.clone(),
       uuid_map,
+      self.empty_write_policy,
     );
     let device_impl = DeviceImpl::new(
       &self.name,
@@ -123,6 +285,8 @@ pub struct BtlePlugDeviceImpl<T: Peripheral + 'static> {
   event_stream: broadcast::Sender<ButtplugDeviceEvent>,
   connected: Arc<AtomicBool>,
   endpoints: HashMap<Endpoint, Characteristic>,
+  task_token: CancellationToken,
+  empty_write_policy: BtleplugEmptyWritePolicy,
 }
 
 unsafe impl<T: Peripheral + 'static> Send for BtlePlugDeviceImpl<T> {}
@@ -133,71 +297,103 @@ impl<T: Peripheral + 'static> BtlePlugDeviceImpl<T> {
     device: T,
     name: &str,
     address: BDAddr,
-    mut adapter_event_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
-    mut notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+    adapter_event_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
+    notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
     endpoints: HashMap<Endpoint, Characteristic>,
     uuid_map: HashMap<Uuid, Endpoint>,
+    empty_write_policy: BtleplugEmptyWritePolicy,
   ) -> Self {
     let (event_stream, _) = broadcast::channel(256);
-    let event_stream_clone = event_stream.clone();
-    let address_clone = address;
-    let name_clone = name.to_owned();
-    async_manager::spawn(async move {
-      let mut error_notification = false;
-      loop {
-        select! {
-          notification = notification_stream.next().fuse() => {
-            if let Some(notification) = notification {
-              let endpoint = if let Some(endpoint) = uuid_map.get(&notification.uuid) {
-                *endpoint
-              } else {
-                // Only print the error message once.
-                if !error_notification {
-                  error!(
-                    "Endpoint for UUID {} not found in map, assuming device has disconnected.",
-                    notification.uuid
-                  );
-                  error_notification = true;
-                }
-                continue;
-              };
-              if let Err(err) = event_stream_clone.send(ButtplugDeviceEvent::Notification(
-                address.to_string(),
-                endpoint,
-                notification.value,
-              )) {
-                error!(
-                  "Cannot send notification, device object disappeared: {:?}",
-                  err
-                );
-                return;
-              }
-            }
-          }
-          adapter_event = adapter_event_stream.next().fuse() => {
-            if let Some(CentralEvent::DeviceDisconnected(addr)) = adapter_event {
-              if address_clone == addr {
-                info!(
-                  "Device {:?} disconnected",
-                  name_clone
-                );
-                event_stream_clone
-                  .send(ButtplugDeviceEvent::Removed(
-                    address_clone.to_string()
-                  ))
-                  .unwrap();
-              }
-            }
-          }
-        }
-      }
-    })
+    let task_token = CancellationToken::new();
+    async_manager::spawn(run_device_event_loop(
+      name.to_owned(),
+      address,
+      adapter_event_stream,
+      notification_stream,
+      uuid_map,
+      event_stream.clone(),
+      task_token.child_token(),
+    ))
     .unwrap();
     Self {
       device,
       endpoints,
       connected: Arc::new(AtomicBool::new(true)),
       event_stream,
+      task_token,
+      empty_write_policy,
+    }
+  }
+}
+
+// Forwards notifications and disconnection events from btleplug to the
+// device event stream. The OS may keep delivering events for a device after
+// we're done with it, so the loop runs until the token is cancelled (on
+// disconnect or drop of the owning device impl) instead of relying on the
+// btleplug streams closing.
+async fn run_device_event_loop(
+  name: String,
+  address: BDAddr,
+  adapter_event_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
+  notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+  uuid_map: HashMap<Uuid, Endpoint>,
+  event_stream: broadcast::Sender<ButtplugDeviceEvent>,
+  token: CancellationToken,
+) {
+  // Fuse the streams so that we stop polling them once they close, instead of
+  // spinning on None.
+  let mut adapter_event_stream = adapter_event_stream.fuse();
+  let mut notification_stream = notification_stream.fuse();
+  let mut error_notification = false;
+  loop {
+    select! {
+      _ = token.cancelled().fuse() => {
+        debug!("Device {:?} event loop cancelled, exiting.", name);
+        return;
+      }
+      notification = notification_stream.next() => {
+        if let Some(notification) = notification {
+          let endpoint = if let Some(endpoint) = uuid_map.get(&notification.uuid) {
+            *endpoint
+          } else {
+            // Only print the error message once.
+            if !error_notification {
+              error!(
+                "Endpoint for UUID {} not found in map, assuming device has disconnected.",
+                notification.uuid
+              );
+              error_notification = true;
+            }
+            continue;
+          };
+          if let Err(err) = event_stream.send(ButtplugDeviceEvent::Notification(
+            address.to_string(),
+            endpoint,
+            notification.value,
+          )) {
+            error!(
+              "Cannot send notification, device object disappeared: {:?}",
+              err
+            );
+            return;
+          }
+        }
+      }
+      adapter_event = adapter_event_stream.next() => {
+        if let Some(CentralEvent::DeviceDisconnected(addr)) = adapter_event {
+          if address == addr {
+            info!(
+              "Device {:?} disconnected",
+              name
+            );
+            // If nothing is listening anymore, we're shutting down anyways.
+            let _ = event_stream
+              .send(ButtplugDeviceEvent::Removed(
+                address.to_string()
+              ));
+          }
+        }
+      }
     }
   }
 }
@@ -213,6 +409,7 @@ impl<T: Peripheral + 'static> DeviceImplInternal for BtlePlugDeviceImpl<T> {
 
   fn disconnect(&self) -> ButtplugResultFuture {
     let device = self.device.clone();
+    self.task_token.cancel();
     Box::pin(async move {
       let _ = device.disconnect().await;
       Ok(())
@@ -228,6 +425,11 @@ impl<T: Peripheral + 'static> DeviceImplInternal for BtlePlugDeviceImpl<T> {
         )));
       }
     };
+    if msg.data.is_empty() {
+      return Box::pin(future::ready(
+        self.empty_write_policy.empty_write_result(msg.endpoint),
+      ));
+    }
     let device = self.device.clone();
     let write_type = if msg.write_with_response {
       WriteType::WithResponse
@@ -317,3 +519,255 @@ impl<T: Peripheral + 'static> DeviceImplInternal for BtlePlugDeviceImpl<T> {
     })
   }
 }
+
+impl<T: Peripheral + 'static> Drop for BtlePlugDeviceImpl<T> {
+  fn drop(&mut self) {
+    self.task_token.cancel();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    device_information_characteristics, resolve_endpoint_uuids, run_device_event_loop,
+    BtlePlugDeviceImpl, BtleplugEmptyWritePolicy,
+  };
+  use crate::{
+    core::errors::{ButtplugDeviceError, ButtplugError},
+    device::{ButtplugDeviceEvent, DeviceImplInternal, DeviceWriteCmd, Endpoint},
+    util::async_manager,
+  };
+  use async_trait::async_trait;
+  use btleplug::api::{
+    BDAddr, CentralEvent, CharPropFlags, Characteristic, Peripheral, PeripheralProperties,
+    ValueNotification, WriteType,
+  };
+  use futures::{stream, Stream};
+  use std::{
+    collections::{BTreeSet, HashMap},
+    pin::Pin,
+    sync::{Arc, Mutex},
+  };
+  use tokio::sync::broadcast;
+  use tokio_util::sync::CancellationToken;
+  use uuid::Uuid;
+
+  /// Peripheral that only records writes, so we can check what would've made
+  /// it to btleplug.
+  #[derive(Clone, Debug, Default)]
+  struct WriteRecordingPeripheral {
+    writes: Arc<Mutex<Vec<Vec<u8>>>>,
+  }
+
+  #[async_trait]
+  impl Peripheral for WriteRecordingPeripheral {
+    fn address(&self) -> BDAddr {
+      BDAddr::default()
+    }
+
+    async fn properties(&self) -> btleplug::Result<Option<PeripheralProperties>> {
+      Ok(None)
+    }
+
+    fn characteristics(&self) -> BTreeSet<Characteristic> {
+      BTreeSet::new()
+    }
+
+    async fn is_connected(&self) -> btleplug::Result<bool> {
+      Ok(true)
+    }
+
+    async fn connect(&self) -> btleplug::Result<()> {
+      Ok(())
+    }
+
+    async fn disconnect(&self) -> btleplug::Result<()> {
+      Ok(())
+    }
+
+    async fn discover_characteristics(&self) -> btleplug::Result<Vec<Characteristic>> {
+      Ok(vec![])
+    }
+
+    async fn write(
+      &self,
+      _characteristic: &Characteristic,
+      data: &[u8],
+      _write_type: WriteType,
+    ) -> btleplug::Result<()> {
+      self.writes.lock().unwrap().push(data.to_vec());
+      Ok(())
+    }
+
+    async fn read(&self, _characteristic: &Characteristic) -> btleplug::Result<Vec<u8>> {
+      Ok(vec![])
+    }
+
+    async fn subscribe(&self, _characteristic: &Characteristic) -> btleplug::Result<()> {
+      Ok(())
+    }
+
+    async fn unsubscribe(&self, _characteristic: &Characteristic) -> btleplug::Result<()> {
+      Ok(())
+    }
+
+    async fn notifications(
+      &self,
+    ) -> btleplug::Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+      Ok(Box::pin(stream::pending()))
+    }
+  }
+
+  fn new_write_test_device(
+    peripheral: WriteRecordingPeripheral,
+    empty_write_policy: BtleplugEmptyWritePolicy,
+  ) -> BtlePlugDeviceImpl<WriteRecordingPeripheral> {
+    let mut endpoints = HashMap::new();
+    endpoints.insert(
+      Endpoint::Tx,
+      Characteristic {
+        uuid: Uuid::from_u128(0x11),
+        properties: CharPropFlags::WRITE,
+      },
+    );
+    BtlePlugDeviceImpl::new(
+      peripheral,
+      "Test Device",
+      BDAddr::default(),
+      Box::pin(stream::pending()),
+      Box::pin(stream::pending()),
+      endpoints,
+      HashMap::new(),
+      empty_write_policy,
+    )
+  }
+
+  #[test]
+  fn test_device_event_loop_exits_on_cancel() {
+    async_manager::block_on(async {
+      // Streams that never yield or close, as with a device that is still
+      // connected at the OS level but has gone quiet.
+      let adapter_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>> =
+        Box::pin(stream::pending());
+      let notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>> =
+        Box::pin(stream::pending());
+      let (sender, _) = broadcast::channel::<ButtplugDeviceEvent>(256);
+      let token = CancellationToken::new();
+      let handle = async_manager::spawn_with_handle(run_device_event_loop(
+        "Test Device".to_owned(),
+        BDAddr::default(),
+        adapter_stream,
+        notification_stream,
+        HashMap::new(),
+        sender,
+        token.child_token(),
+      ))
+      .unwrap();
+      token.cancel();
+      handle.await;
+    });
+  }
+
+  #[test]
+  fn test_empty_write_policy() {
+    assert_eq!(BtleplugEmptyWritePolicy::default(), BtleplugEmptyWritePolicy::Skip);
+    async_manager::block_on(async {
+      let peripheral = WriteRecordingPeripheral::default();
+      let device = new_write_test_device(peripheral.clone(), BtleplugEmptyWritePolicy::Skip);
+      assert!(device
+        .write_value(DeviceWriteCmd::new(Endpoint::Tx, vec![], false))
+        .await
+        .is_ok());
+      assert!(peripheral.writes.lock().unwrap().is_empty());
+
+      let peripheral = WriteRecordingPeripheral::default();
+      let device = new_write_test_device(peripheral.clone(), BtleplugEmptyWritePolicy::Reject);
+      assert!(matches!(
+        device
+          .write_value(DeviceWriteCmd::new(Endpoint::Tx, vec![], true))
+          .await,
+        Err(ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::EmptyWrite(Endpoint::Tx)
+        ))
+      ));
+      assert!(peripheral.writes.lock().unwrap().is_empty());
+
+      // Writes with data go through no matter the policy.
+      device
+        .write_value(DeviceWriteCmd::new(Endpoint::Tx, vec![0x01], true))
+        .await
+        .unwrap();
+      assert_eq!(*peripheral.writes.lock().unwrap(), vec![vec![0x01u8]]);
+    });
+  }
+
+  #[test]
+  fn test_endpoint_overrides() {
+    let service = Uuid::from_u128(0x10);
+    let tx = Uuid::from_u128(0x11);
+    let rx = Uuid::from_u128(0x12);
+    let moved_tx = Uuid::from_u128(0x13);
+    let missing = Uuid::from_u128(0x14);
+    let mut characteristics = HashMap::new();
+    characteristics.insert(Endpoint::Tx, tx);
+    characteristics.insert(Endpoint::Rx, rx);
+    let mut services = HashMap::new();
+    services.insert(service, characteristics);
+    let discovered = vec![tx, rx, moved_tx];
+
+    let endpoint_uuids = resolve_endpoint_uuids("Test Device", &services, &discovered, false, None);
+    assert_eq!(endpoint_uuids.get(&Endpoint::Tx), Some(&tx));
+    assert_eq!(endpoint_uuids.get(&Endpoint::Rx), Some(&rx));
+
+    // Overrides win over the configuration, but only if the device has the
+    // characteristic.
+    let mut overrides = HashMap::new();
+    overrides.insert(Endpoint::Tx, moved_tx);
+    overrides.insert(Endpoint::Command, missing);
+    let endpoint_uuids = resolve_endpoint_uuids(
+      "Test Device",
+      &services,
+      &discovered,
+      false,
+      Some(&overrides),
+    );
+    assert_eq!(endpoint_uuids.len(), 2);
+    assert_eq!(endpoint_uuids.get(&Endpoint::Tx), Some(&moved_tx));
+    assert_eq!(endpoint_uuids.get(&Endpoint::Rx), Some(&rx));
+    assert!(endpoint_uuids.get(&Endpoint::Command).is_none());
+  }
+
+  #[test]
+  fn test_device_information_characteristics() {
+    let manufacturer = Uuid::parse_str("00002a29-0000-1000-8000-00805f9b34fb").unwrap();
+    let firmware = Uuid::parse_str("00002a26-0000-1000-8000-00805f9b34fb").unwrap();
+    assert!(device_information_characteristics()
+      .iter()
+      .any(|(endpoint, uuid)| *endpoint == Endpoint::RxBLEManufacturer && *uuid == manufacturer));
+    let endpoint_uuids = resolve_endpoint_uuids(
+      "Test Device",
+      &HashMap::new(),
+      &[manufacturer, firmware],
+      true,
+      None,
+    );
+    assert_eq!(endpoint_uuids.len(), 2);
+    assert_eq!(
+      endpoint_uuids.get(&Endpoint::RxBLEManufacturer),
+      Some(&manufacturer)
+    );
+    assert_eq!(
+      endpoint_uuids.get(&Endpoint::RxBLEFirmwareRevision),
+      Some(&firmware)
+    );
+    // Devices without the service don't get the endpoints.
+    let endpoint_uuids = resolve_endpoint_uuids(
+      "Test Device",
+      &HashMap::new(),
+      &[manufacturer, firmware],
+      false,
+      None,
+    );
+    assert!(endpoint_uuids.is_empty());
+  }
+}
@@ -1,4 +1,5 @@
 pub mod btleplug_comm_manager;
 pub use btleplug_comm_manager::BtlePlugCommunicationManagerBuilder;
+pub use btleplug_device_impl::{BtleplugEmptyWritePolicy, BtleplugEndpointOverrides};
 mod btleplug_adapter_task;
 pub mod btleplug_device_impl;
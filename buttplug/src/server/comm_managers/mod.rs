@@ -30,6 +30,9 @@ pub enum DeviceCommunicationEvent {
     creator: Box<dyn ButtplugDeviceImplCreator>,
   },
   DeviceManagerAdded(Arc<AtomicBool>),
+  /// Sent by the device manager after disconnecting a device on request, so
+  /// the device is removed even if its implementation doesn't report it.
+  DeviceDisconnected(String),
   ScanningStarted,
   ScanningFinished,
 }
@@ -46,6 +49,14 @@ pub trait DeviceCommunicationManager: Send + Sync {
   fn scanning_status(&self) -> Arc<AtomicBool> {
     Arc::new(AtomicBool::new(false))
   }
+  /// Tries to find and reconnect a single, previously connected device by
+  /// address, without scanning for new devices. Returns None if this manager
+  /// does not handle devices with this address, otherwise the future resolves
+  /// once the device has been found and handed off to the device manager, or
+  /// with an error if it could not be found.
+  fn reconnect_device(&self, _address: &str) -> Option<ButtplugResultFuture> {
+    None
+  }
   // Events happen via channel senders passed to the comm manager.
 }
 
@@ -17,7 +17,7 @@ use futures::{future::BoxFuture, FutureExt};
 use serialport::{SerialPort, SerialPortInfo};
 use std::{
   fmt::{self, Debug},
-  io::ErrorKind,
+  io::{self, ErrorKind, Read, Write},
   sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -71,21 +71,65 @@ impl ButtplugDeviceImplCreator for SerialPortDeviceImplCreator {
   }
 }
 
-fn serial_write_thread(mut port: Box<dyn SerialPort>, receiver: mpsc::Receiver<Vec<u8>>) {
+/// Shared by the serial port threads, to mark the device as removed if the
+/// port goes away (i.e. the device was unplugged).
+#[derive(Clone)]
+struct SerialPortRemovalHandler {
+  address: String,
+  connected: Arc<AtomicBool>,
+  device_event_sender: broadcast::Sender<ButtplugDeviceEvent>,
+  token: CancellationToken,
+}
+
+impl SerialPortRemovalHandler {
+  fn port_lost(&self, err: &io::Error) {
+    // Both threads may see the port go away, only report it once. If we were
+    // already disconnected on purpose, there's nothing to report either.
+    if !self.connected.swap(false, Ordering::SeqCst) {
+      return;
+    }
+    info!("Serial port {} lost ({:?}), removing device.", self.address, err);
+    self.token.cancel();
+    // If nothing is listening anymore, we're shutting down anyways.
+    let _ = self
+      .device_event_sender
+      .send(ButtplugDeviceEvent::Removed(self.address.clone()));
+  }
+}
+
+/// Returns true if a serial port error means the port itself is gone, as
+/// opposed to a read just not having any data yet.
+fn is_port_lost_error(err: &io::Error) -> bool {
+  !matches!(
+    err.kind(),
+    ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::WouldBlock
+  )
+}
+
+fn serial_write_thread(
+  mut port: impl Write,
+  receiver: mpsc::Receiver<Vec<u8>>,
+  removal_handler: SerialPortRemovalHandler,
+) {
   let mut recv = receiver;
   // Instead of waiting on a token here, we'll expect that we'll break on our
   // channel going away.
   //
   // This is a blocking recv so we don't have to worry about the port.
   while let Some(v) = recv.blocking_recv() {
-    port.write_all(&v).unwrap();
+    if let Err(e) = port.write_all(&v) {
+      error!("Cannot write to serial port: {:?}", e);
+      removal_handler.port_lost(&e);
+      break;
+    }
   }
 }
 
 fn serial_read_thread(
-  mut port: Box<dyn SerialPort>,
+  mut port: impl Read,
   sender: mpsc::Sender<Vec<u8>>,
   token: CancellationToken,
+  removal_handler: SerialPortRemovalHandler,
 ) {
   while !token.is_cancelled() {
     // TODO This is probably too small
@@ -99,10 +143,12 @@ fn serial_read_thread(
         }
       }
       Err(e) => {
-        if e.kind() == ErrorKind::TimedOut {
+        if !is_port_lost_error(&e) {
           continue;
         }
-        error!("{:?}", e);
+        error!("Cannot read from serial port: {:?}", e);
+        removal_handler.port_lost(&e);
+        break;
       }
     }
   }
@@ -170,13 +216,22 @@ impl SerialPortDeviceImpl {
     let (writer_sender, writer_receiver) = mpsc::channel(256);
     let (reader_sender, reader_receiver) = mpsc::channel(256);
 
+    let address = port.name().unwrap();
+    let connected = Arc::new(AtomicBool::new(true));
     let token = CancellationToken::new();
+    let removal_handler = SerialPortRemovalHandler {
+      address: address.clone(),
+      connected: connected.clone(),
+      device_event_sender: device_event_sender.clone(),
+      token: token.clone(),
+    };
     let read_token = token.child_token();
     let read_port = (*port).try_clone().unwrap();
+    let read_removal_handler = removal_handler.clone();
     let read_thread = thread::Builder::new()
       .name("Serial Reader Thread".to_string())
       .spawn(move || {
-        serial_read_thread(read_port, reader_sender, read_token);
+        serial_read_thread(read_port, reader_sender, read_token, read_removal_handler);
       })
       .unwrap();
 
@@ -184,18 +239,18 @@ impl SerialPortDeviceImpl {
     let write_thread = thread::Builder::new()
       .name("Serial Writer Thread".to_string())
       .spawn(move || {
-        serial_write_thread(write_port, writer_receiver);
+        serial_write_thread(write_port, writer_receiver, removal_handler);
       })
       .unwrap();
 
     Ok(Self {
-      address: port.name().unwrap(),
+      address,
       _read_thread: read_thread,
       _write_thread: write_thread,
       port_receiver: Arc::new(Mutex::new(reader_receiver)),
       port_sender: writer_sender,
       _port: Arc::new(Mutex::new(port)),
-      connected: Arc::new(AtomicBool::new(true)),
+      connected,
       device_event_sender,
       thread_cancellation_token: token,
     })
@@ -241,9 +296,15 @@ impl DeviceImplInternal for SerialPortDeviceImpl {
 
   fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
     let sender = self.port_sender.clone();
+    let connected = self.connected.clone();
+    let address = self.address.clone();
     // TODO Should check endpoint validity
     Box::pin(async move {
-      sender.send(msg.data).await.unwrap();
+      // The writer thread exits if the port goes away, so fail instead of
+      // queueing writes nobody will make.
+      if !connected.load(Ordering::SeqCst) || sender.send(msg.data).await.is_err() {
+        return Err(ButtplugDeviceError::DeviceNotConnected(address).into());
+      }
       Ok(())
     })
   }
@@ -293,3 +354,98 @@ impl Drop for SerialPortDeviceImpl {
     self.thread_cancellation_token.cancel();
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// Stands in for a port that has been unplugged.
+  struct UnpluggedPort;
+
+  impl Read for UnpluggedPort {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+      Err(io::Error::new(ErrorKind::BrokenPipe, "Device unplugged"))
+    }
+  }
+
+  impl Write for UnpluggedPort {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+      Err(io::Error::new(ErrorKind::BrokenPipe, "Device unplugged"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  fn removal_handler() -> (SerialPortRemovalHandler, broadcast::Receiver<ButtplugDeviceEvent>) {
+    let (device_event_sender, device_event_receiver) = broadcast::channel(256);
+    (
+      SerialPortRemovalHandler {
+        address: "COM7".to_owned(),
+        connected: Arc::new(AtomicBool::new(true)),
+        device_event_sender,
+        token: CancellationToken::new(),
+      },
+      device_event_receiver,
+    )
+  }
+
+  #[test]
+  fn test_serial_read_error_removes_device() {
+    let (handler, mut events) = removal_handler();
+    let (sender, _receiver) = mpsc::channel(256);
+    serial_read_thread(UnpluggedPort, sender, handler.token.child_token(), handler.clone());
+    assert!(!handler.connected.load(Ordering::SeqCst));
+    assert!(handler.token.is_cancelled());
+    assert!(matches!(
+      events.try_recv(),
+      Ok(ButtplugDeviceEvent::Removed(address)) if address == "COM7"
+    ));
+    // Losing the port from the writer side as well only reports once.
+    let (sender, receiver) = mpsc::channel(256);
+    sender.try_send(vec![0x01]).unwrap();
+    drop(sender);
+    serial_write_thread(UnpluggedPort, receiver, handler);
+    assert!(events.try_recv().is_err());
+  }
+
+  #[test]
+  fn test_serial_write_error_removes_device() {
+    let (handler, mut events) = removal_handler();
+    let (sender, receiver) = mpsc::channel(256);
+    sender.try_send(vec![0x01]).unwrap();
+    drop(sender);
+    serial_write_thread(UnpluggedPort, receiver, handler.clone());
+    assert!(!handler.connected.load(Ordering::SeqCst));
+    assert!(matches!(
+      events.try_recv(),
+      Ok(ButtplugDeviceEvent::Removed(address)) if address == "COM7"
+    ));
+  }
+
+  #[test]
+  fn test_serial_read_timeout_keeps_device() {
+    struct QuietPort(CancellationToken);
+
+    impl Read for QuietPort {
+      fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        // Stop the loop after one timeout.
+        self.0.cancel();
+        Err(io::Error::new(ErrorKind::TimedOut, "Nothing to read"))
+      }
+    }
+
+    let (handler, mut events) = removal_handler();
+    let (sender, _receiver) = mpsc::channel(256);
+    let read_token = CancellationToken::new();
+    serial_read_thread(
+      QuietPort(read_token.clone()),
+      sender,
+      read_token,
+      handler.clone(),
+    );
+    assert!(handler.connected.load(Ordering::SeqCst));
+    assert!(events.try_recv().is_err());
+  }
+}
@@ -1,3 +1,5 @@
+#[cfg(all(feature = "server", any(test, feature = "test-harness")))]
+mod protocol_test_harness;
 mod test_device;
 #[cfg(feature = "server")]
 mod test_device_comm_manager;
@@ -16,6 +18,9 @@ pub use test_device_comm_manager::{
 };
 use tokio::sync::mpsc::Receiver;
 
+#[cfg(all(feature = "server", any(test, feature = "test-harness")))]
+pub use protocol_test_harness::ProtocolTestHarness;
+
 #[allow(dead_code)]
 pub fn check_test_recv_value(
   receiver: &Arc<Mutex<Receiver<DeviceImplCommand>>>,
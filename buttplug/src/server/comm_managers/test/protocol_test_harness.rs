@@ -0,0 +1,188 @@
+use super::{test_device_comm_manager::new_uninitialized_ble_test_device, TestDeviceInternal};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::ButtplugDeviceCommandMessageUnion,
+  },
+  device::{
+    configuration_manager::{DeviceConfigurationManager, ProtocolDefinition},
+    ButtplugDevice, DeviceWriteCmd,
+  },
+};
+use std::sync::Arc;
+
+static PROTOCOL_TEST_HARNESS_ADDRESS: &str = "protocol-test-harness";
+
+/// Builds a single device from a protocol definition on top of an in-memory
+/// [TestDeviceInternal], so protocol output can be checked byte for byte
+/// without standing up a server or client.
+///
+/// Device creation goes through the same [DeviceConfigurationManager] and
+/// [ButtplugDevice::try_create_device] path the server uses, so protocol
+/// initialization runs exactly as it would against hardware. Anything written
+/// during initialization is kept separately in
+/// [ProtocolTestHarness::initialization_writes], and
+/// [ProtocolTestHarness::send_command] only ever returns the writes caused by
+/// the command it was handed.
+pub struct ProtocolTestHarness {
+  device: ButtplugDevice,
+  test_device: Arc<TestDeviceInternal>,
+  initialization_writes: Vec<DeviceWriteCmd>,
+}
+
+impl ProtocolTestHarness {
+  /// Creates a device for `protocol_name` using `protocol_definition`, named
+  /// after the first (alphabetically) Bluetooth LE name in the definition.
+  ///
+  /// # Errors
+  ///
+  /// Returns [ButtplugDeviceError::ProtocolRequirementError] if the definition
+  /// has no Bluetooth LE names, and
+  /// [ButtplugDeviceError::ProtocolNotImplemented] if the library has no
+  /// protocol implementation called `protocol_name`.
+  pub async fn new(
+    protocol_name: &str,
+    protocol_definition: ProtocolDefinition,
+  ) -> Result<Self, ButtplugError> {
+    let device_name = protocol_definition
+      .btle
+      .as_ref()
+      .and_then(|btle| btle.names.iter().min().cloned())
+      .ok_or_else(|| {
+        ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Protocol definition for {} has no Bluetooth LE names to create a test device from.",
+          protocol_name
+        ))
+      })?;
+    Self::new_with_device_name(protocol_name, protocol_definition, &device_name).await
+  }
+
+  /// Same as [ProtocolTestHarness::new], but advertises the test device as
+  /// `device_name`. Useful for protocols that pick features or configurations
+  /// based on the exact name the device reports.
+  pub async fn new_with_device_name(
+    protocol_name: &str,
+    protocol_definition: ProtocolDefinition,
+    device_name: &str,
+  ) -> Result<Self, ButtplugError> {
+    let config_mgr = DeviceConfigurationManager::new(false);
+    config_mgr.add_protocol_definition(protocol_name, protocol_definition);
+    let (test_device, device_impl_creator) = new_uninitialized_ble_test_device(
+      device_name,
+      Some(PROTOCOL_TEST_HARNESS_ADDRESS.to_owned()),
+    );
+    let device =
+      ButtplugDevice::try_create_device(Arc::new(config_mgr), Box::new(device_impl_creator))
+        .await?
+        .ok_or_else(|| ButtplugDeviceError::ProtocolNotImplemented(protocol_name.to_owned()))?;
+    let initialization_writes = test_device.take_write_history();
+    Ok(Self {
+      device,
+      test_device,
+      initialization_writes,
+    })
+  }
+
+  /// The device built from the protocol definition.
+  pub fn device(&self) -> &ButtplugDevice {
+    &self.device
+  }
+
+  /// The in-memory device implementation backing the harness, for scripting
+  /// read responses or sending device events.
+  pub fn test_device(&self) -> Arc<TestDeviceInternal> {
+    self.test_device.clone()
+  }
+
+  /// Every write the protocol made while initializing the device, in order.
+  pub fn initialization_writes(&self) -> &[DeviceWriteCmd] {
+    &self.initialization_writes
+  }
+
+  /// Sends `message` to the device and returns every write it caused, in the
+  /// order the device saw them.
+  pub async fn send_command(
+    &self,
+    message: ButtplugDeviceCommandMessageUnion,
+  ) -> Result<Vec<DeviceWriteCmd>, ButtplugError> {
+    // Drop anything that showed up outside of a command (e.g. writes from
+    // protocol background tasks) so results only cover this message.
+    self.test_device.take_write_history();
+    self.device.parse_message(message).await?;
+    Ok(self.test_device.take_write_history())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ProtocolTestHarness;
+  use crate::{
+    core::messages::{LinearCmd, VectorSubcommand, VibrateCmd, VibrateSubcommand},
+    device::{configuration_manager::ProtocolDefinition, DeviceWriteCmd, Endpoint},
+    util::{
+      async_manager,
+      device_configuration::{load_protocol_config_from_json, DEVICE_CONFIGURATION_JSON},
+    },
+  };
+
+  fn builtin_protocol_definition(protocol_name: &str) -> ProtocolDefinition {
+    load_protocol_config_from_json(DEVICE_CONFIGURATION_JSON)
+      .unwrap()
+      .protocols
+      .remove(protocol_name)
+      .unwrap()
+  }
+
+  #[test]
+  fn test_protocol_test_harness_vibrate() {
+    async_manager::block_on(async move {
+      let harness = ProtocolTestHarness::new("aneros", builtin_protocol_definition("aneros"))
+        .await
+        .unwrap();
+      assert!(harness.initialization_writes().is_empty());
+      let writes = harness
+        .send_command(VibrateCmd::new(0, vec![VibrateSubcommand::new(1, 0.5)]).into())
+        .await
+        .unwrap();
+      assert_eq!(
+        writes,
+        vec![DeviceWriteCmd::new(Endpoint::Tx, vec![0xF2, 64], false)]
+      );
+    });
+  }
+
+  #[test]
+  fn test_protocol_test_harness_linear() {
+    async_manager::block_on(async move {
+      let harness = ProtocolTestHarness::new_with_device_name(
+        "kiiroo-v2",
+        builtin_protocol_definition("kiiroo-v2"),
+        "Launch",
+      )
+      .await
+      .unwrap();
+      assert_eq!(
+        harness.initialization_writes(),
+        &[DeviceWriteCmd::new(Endpoint::Firmware, vec![0x0], true)]
+      );
+      let writes = harness
+        .send_command(LinearCmd::new(0, vec![VectorSubcommand::new(0, 500, 0.5)]).into())
+        .await
+        .unwrap();
+      assert_eq!(writes.len(), 1);
+      assert_eq!(writes[0].endpoint, Endpoint::Tx);
+      assert_eq!(writes[0].data[0], 49);
+    });
+  }
+
+  #[test]
+  fn test_protocol_test_harness_unknown_protocol() {
+    async_manager::block_on(async move {
+      assert!(
+        ProtocolTestHarness::new("not-a-protocol", builtin_protocol_definition("aneros"))
+          .await
+          .is_err()
+      );
+    });
+  }
+}
@@ -15,8 +15,9 @@ use async_trait::async_trait;
 use dashmap::DashMap;
 use futures::future::{self, BoxFuture};
 use std::{
+  collections::VecDeque,
   fmt::{self, Debug},
-  sync::Arc,
+  sync::{Arc, Mutex},
 };
 use tokio::sync::{broadcast, mpsc};
 
@@ -100,11 +101,23 @@ impl TestDeviceEndpointChannel {
   }
 }
 
+type ReadResponseMap = Arc<DashMap<Endpoint, VecDeque<Vec<u8>>>>;
+type WriteHistory = Arc<Mutex<Vec<DeviceWriteCmd>>>;
+type WriteNotificationQueue = Arc<Mutex<VecDeque<(Endpoint, Vec<u8>)>>>;
+
 pub struct TestDeviceInternal {
   name: String,
   address: String,
   endpoint_channels: Arc<DashMap<Endpoint, TestDeviceEndpointChannel>>,
   event_sender: broadcast::Sender<ButtplugDeviceEvent>,
+  // Queue of scripted values to hand back on reads, per endpoint.
+  read_responses: ReadResponseMap,
+  // Every write the device has seen, in the order it saw them, across all
+  // endpoints. Lets tests assert exact byte output without having to poll each
+  // endpoint channel.
+  write_history: WriteHistory,
+  // Queue of scripted notifications to send back after writes.
+  write_notifications: WriteNotificationQueue,
 }
 
 impl TestDeviceInternal {
@@ -115,6 +128,9 @@ impl TestDeviceInternal {
       address: address.to_owned(),
       endpoint_channels: Arc::new(DashMap::new()),
       event_sender,
+      read_responses: Arc::new(DashMap::new()),
+      write_history: Arc::new(Mutex::new(vec![])),
+      write_notifications: Arc::new(Mutex::new(VecDeque::new())),
     }
   }
 
@@ -161,6 +177,35 @@ impl TestDeviceInternal {
       Ok(())
     })
   }
+
+  /// Queue up data to be returned by the next read on `endpoint`. Responses
+  /// are handed out in the order they were added. Reads on an endpoint with
+  /// nothing queued return an empty reading.
+  pub fn add_read_response(&self, endpoint: Endpoint, data: Vec<u8>) {
+    self
+      .read_responses
+      .entry(endpoint)
+      .or_insert_with(VecDeque::new)
+      .push_back(data);
+  }
+
+  /// Queue up a notification on `endpoint` to be sent right after the next
+  /// write the device receives, for protocols that wait on a reply to
+  /// something they wrote (i.e. the Lovense DeviceType query). Notifications
+  /// are sent one per write, in the order they were added.
+  pub fn add_write_notification(&self, endpoint: Endpoint, data: Vec<u8>) {
+    self
+      .write_notifications
+      .lock()
+      .unwrap()
+      .push_back((endpoint, data));
+  }
+
+  /// Returns all writes the device has received since the last call, in the
+  /// order they were received.
+  pub fn take_write_history(&self) -> Vec<DeviceWriteCmd> {
+    self.write_history.lock().unwrap().drain(..).collect()
+  }
 }
 
 pub struct TestDevice {
@@ -171,6 +216,9 @@ pub struct TestDevice {
   // matters here.
   pub endpoint_channels: Arc<DashMap<Endpoint, TestDeviceEndpointChannel>>,
   event_sender: broadcast::Sender<ButtplugDeviceEvent>,
+  read_responses: ReadResponseMap,
+  write_history: WriteHistory,
+  write_notifications: WriteNotificationQueue,
 }
 
 impl TestDevice {
@@ -180,6 +228,9 @@ impl TestDevice {
       address: internal_device.address(),
       endpoint_channels: internal_device.endpoint_channels.clone(),
       event_sender: internal_device.sender(),
+      read_responses: internal_device.read_responses.clone(),
+      write_history: internal_device.write_history.clone(),
+      write_notifications: internal_device.write_notifications.clone(),
     }
   }
 }
@@ -206,11 +257,29 @@ impl DeviceImplInternal for TestDevice {
     &self,
     msg: DeviceReadCmd,
   ) -> BoxFuture<'static, Result<RawReading, ButtplugError>> {
-    Box::pin(future::ready(Ok(RawReading::new(0, msg.endpoint, vec![]))))
+    let data = self
+      .read_responses
+      .get_mut(&msg.endpoint)
+      .and_then(|mut responses| responses.pop_front())
+      .unwrap_or_default();
+    Box::pin(future::ready(Ok(RawReading::new(0, msg.endpoint, data))))
   }
 
   fn write_value(&self, msg: DeviceWriteCmd) -> ButtplugResultFuture {
     let channels = self.endpoint_channels.clone();
+    self.write_history.lock().unwrap().push(DeviceWriteCmd::new(
+      msg.endpoint,
+      msg.data.clone(),
+      msg.write_with_response,
+    ));
+    if let Some((endpoint, data)) = self.write_notifications.lock().unwrap().pop_front() {
+      // Nobody listening just means nobody cares about the reply.
+      let _ = self.event_sender.send(ButtplugDeviceEvent::Notification(
+        self.address.clone(),
+        endpoint,
+        data,
+      ));
+    }
     Box::pin(async move {
       // Since we're only accessing a channel, we can use a read lock here.
       match channels.get(&msg.endpoint) {
@@ -1,6 +1,9 @@
 use super::test_device::{TestDeviceImplCreator, TestDeviceInternal};
 use crate::{
-  core::{errors::ButtplugError, ButtplugResultFuture},
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    ButtplugResultFuture,
+  },
   device::{
     configuration_manager::{BluetoothLESpecifier, DeviceConfigurationManager, DeviceSpecifier},
     ButtplugDevice,
@@ -19,8 +22,7 @@ use tokio::sync::{mpsc::Sender, Mutex};
 
 type WaitingDeviceList = Arc<Mutex<Vec<TestDeviceImplCreator>>>;
 
-#[allow(dead_code)]
-fn new_uninitialized_ble_test_device(
+pub(super) fn new_uninitialized_ble_test_device(
   name: &str,
   address: Option<String>,
 ) -> (Arc<TestDeviceInternal>, TestDeviceImplCreator) {
@@ -100,6 +102,25 @@ impl TestDeviceCommunicationManagerBuilder {
   pub fn helper(&self) -> TestDeviceCommunicationManagerHelper {
     TestDeviceCommunicationManagerHelper::new(self.devices.clone())
   }
+
+  /// Add a BLE device to the roster the comm manager will emit on its first
+  /// scan. The name decides which protocol the device will be matched to, via
+  /// the device configuration (i.e. "LVS-Test" will come up as a Lovense
+  /// device). Returns the device internals, which can be used to script read
+  /// responses and inspect written bytes.
+  ///
+  /// Since this only works before the builder is handed to the device
+  /// manager, it's synchronous. Use [TestDeviceCommunicationManagerHelper]
+  /// for adding devices afterward.
+  pub fn add_ble_device(&self, name: &str) -> Arc<TestDeviceInternal> {
+    let (device, creator) = new_uninitialized_ble_test_device(name, None);
+    self
+      .devices
+      .try_lock()
+      .expect("Builder device list should not be shared before finish is called.")
+      .push(creator);
+    device
+  }
 }
 
 impl DeviceCommunicationManagerBuilder for TestDeviceCommunicationManagerBuilder {
@@ -176,13 +197,120 @@ impl DeviceCommunicationManager for TestDeviceCommunicationManager {
   fn stop_scanning(&self) -> ButtplugResultFuture {
     Box::pin(future::ready(Ok(())))
   }
+
+  fn reconnect_device(&self, address: &str) -> Option<ButtplugResultFuture> {
+    let devices_vec = self.devices.clone();
+    let device_sender = self.device_sender.clone();
+    let address = address.to_owned();
+    Some(Box::pin(async move {
+      // Only emit the device we were asked for, leave everything else waiting
+      // for a scan.
+      let mut devices = devices_vec.lock().await;
+      let index = devices.iter().position(|d| {
+        d.device()
+          .as_ref()
+          .map_or(false, |x| x.address() == address)
+      });
+      let d = match index {
+        Some(index) => devices.remove(index),
+        // Act like the device was out of range and we timed out looking for it.
+        None => {
+          return Err(ButtplugDeviceError::DeviceReconnectTimeout(address, 0).into());
+        }
+      };
+      if device_sender
+        .send(DeviceCommunicationEvent::DeviceFound {
+          name: d
+            .device()
+            .as_ref()
+            .map_or("Test device".to_owned(), |x| x.name()),
+          address,
+          creator: Box::new(d),
+        })
+        .await
+        .is_err()
+      {
+        error!("Device channel no longer open.");
+      }
+      Ok(())
+    }))
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use crate::{core::messages::{self, ButtplugMessageSpecVersion, ButtplugServerMessage}, server::ButtplugServer, server::comm_managers::test::TestDeviceCommunicationManagerBuilder, util::async_manager};
+  use crate::{
+    core::messages::{self, ButtplugMessageSpecVersion, ButtplugServerMessage},
+    device::{DeviceWriteCmd, Endpoint},
+    server::comm_managers::test::TestDeviceCommunicationManagerBuilder,
+    server::{ButtplugServer, ButtplugServerBuilder},
+    util::async_manager,
+  };
   use futures::StreamExt;
 
+  #[test]
+  fn test_test_device_comm_manager_roster() {
+    async_manager::block_on(async {
+      let server = ButtplugServerBuilder::default()
+        .allow_raw_messages(true)
+        .finish()
+        .unwrap();
+      let recv = server.event_stream();
+      pin_mut!(recv);
+      let builder = TestDeviceCommunicationManagerBuilder::default();
+      let device = builder.add_ble_device("Massage Demo");
+      device.add_read_response(Endpoint::Tx, vec![1, 2, 3]);
+      server.device_manager().add_comm_manager(builder).unwrap();
+      let msg =
+        messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2);
+      server.parse_message(msg.into()).await.unwrap();
+      server
+        .parse_message(messages::StartScanning::default().into())
+        .await
+        .unwrap();
+      let mut device_index = None;
+      while let Some(msg) = recv.next().await {
+        if let ButtplugServerMessage::DeviceAdded(da) = msg {
+          assert_eq!(da.device_name(), "Aneros Vivi (Raw)");
+          device_index = Some(da.device_index());
+          break;
+        }
+      }
+      let device_index = device_index.unwrap();
+      server
+        .parse_message(
+          messages::VibrateCmd::new(device_index, vec![messages::VibrateSubcommand::new(0, 0.5)])
+            .into(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(
+        device.take_write_history(),
+        vec![DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)]
+      );
+      assert!(device.take_write_history().is_empty());
+      let reading = server
+        .parse_message(messages::RawReadCmd::new(device_index, Endpoint::Tx, 0, 0).into())
+        .await
+        .unwrap();
+      if let ButtplugServerMessage::RawReading(reading) = reading {
+        assert_eq!(reading.data(), &vec![1, 2, 3]);
+      } else {
+        panic!("Expected RawReading, got {:?}", reading);
+      }
+      // Scripted responses are used up in order, then reads come back empty.
+      let reading = server
+        .parse_message(messages::RawReadCmd::new(device_index, Endpoint::Tx, 0, 0).into())
+        .await
+        .unwrap();
+      if let ButtplugServerMessage::RawReading(reading) = reading {
+        assert!(reading.data().is_empty());
+      } else {
+        panic!("Expected RawReading, got {:?}", reading);
+      }
+    });
+  }
+
   #[test]
   fn test_test_device_comm_manager() {
     async_manager::block_on(async {
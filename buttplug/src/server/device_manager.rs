@@ -12,21 +12,24 @@ use super::{
   comm_managers::{
     DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
   },
-  device_manager_event_loop::DeviceManagerEventLoop,
+  device_manager_event_loop::{DeviceManagerEventLoop, DeviceManagerSharedState},
   ping_timer::PingTimer,
   ButtplugServerError,
 };
 use crate::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugMessageError, ButtplugUnknownError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError, ButtplugUnknownError},
     messages::{
       self, ButtplugClientMessage, ButtplugDeviceCommandMessageUnion,
       ButtplugDeviceManagerMessageUnion, ButtplugDeviceMessage, ButtplugMessage,
-      ButtplugServerMessage, DeviceList, DeviceMessageInfo,
+      ButtplugServerMessage, DeviceList, DeviceMessageInfo, DisconnectDevice,
+      ReconnectDevice,
     },
+    ButtplugResultFuture,
   },
   device::{
-    configuration_manager::{DeviceConfigurationManager, ProtocolDefinition}, protocol::ButtplugProtocol, ButtplugDevice,
+    configuration_manager::{DeviceConfigurationManager, ProtocolDefinition, SupportedProtocolInfo}, protocol::ButtplugProtocol, BleConnectionParameters,
+    ButtplugDevice, Endpoint,
   },
   server::ButtplugServerResultFuture,
   util::async_manager,
@@ -34,8 +37,11 @@ use crate::{
 use dashmap::{DashMap, DashSet};
 use futures::future;
 use std::{
+  any::type_name,
   convert::TryFrom,
+  panic::{self, AssertUnwindSafe},
   sync::{atomic::Ordering, Arc},
+  time::Duration,
 };
 use tokio::sync::{broadcast, mpsc};
 
@@ -48,6 +54,12 @@ pub struct DeviceManager {
   device_deny_list: Arc<DashSet<String>>,
   device_event_sender: mpsc::Sender<DeviceCommunicationEvent>,
   config: Arc<DeviceConfigurationManager>,
+  /// Maps device addresses to indexes, shared with the event loop. Lets us
+  /// find the address of a device that has disconnected, for reconnection.
+  device_index_map: Arc<DashMap<String, u32>>,
+  /// Device index/endpoint pairs that clients have raw subscribed to, shared
+  /// with the event loop so it knows which notifications to forward.
+  raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
 }
 
 unsafe impl Send for DeviceManager {}
@@ -65,12 +77,18 @@ impl DeviceManager {
     let (device_event_sender, device_event_receiver) = mpsc::channel(256);
     let device_allow_list = Arc::new(DashSet::new());
     let device_deny_list = Arc::new(DashSet::new());
+    let device_index_map = Arc::new(DashMap::new());
+    let raw_subscriptions = Arc::new(DashSet::new());
     let mut event_loop = DeviceManagerEventLoop::new(
       config.clone(),
       output_sender,
       devices.clone(),
-      device_allow_list.clone(),
-      device_deny_list.clone(),
+      DeviceManagerSharedState {
+        device_allow_list: device_allow_list.clone(),
+        device_deny_list: device_deny_list.clone(),
+        device_index_map: device_index_map.clone(),
+        raw_subscriptions: raw_subscriptions.clone(),
+      },
       ping_timer,
       device_event_receiver,
     );
@@ -85,54 +103,87 @@ impl DeviceManager {
       device_deny_list,
       comm_managers: Arc::new(DashMap::new()),
       config,
+      device_index_map,
+      raw_subscriptions,
     }
   }
 
   fn start_scanning(&self) -> ButtplugServerResultFuture {
+    self.start_scanning_managers(None)
+  }
+
+  /// Starts scanning on the named comm managers, or all comm managers if
+  /// `names` is None.
+  fn start_scanning_managers(&self, names: Option<&[String]>) -> ButtplugServerResultFuture {
     if self.comm_managers.is_empty() {
-      ButtplugUnknownError::NoDeviceCommManagers.into()
-    } else {
-      let mgrs = self.comm_managers.clone();
-      let sender = self.device_event_sender.clone();
-      Box::pin(async move {
-        for mgr in mgrs.iter() {
-          if mgr.value().scanning_status().load(Ordering::SeqCst) {
-            return Err(ButtplugDeviceError::DeviceScanningAlreadyStarted.into());
-          }
+      return ButtplugUnknownError::NoDeviceCommManagers.into();
+    }
+    if let Some(names) = names {
+      // Scanning with nothing would just report ScanningFinished right away,
+      // which looks like a scan that found nothing.
+      if names.is_empty() {
+        return ButtplugMessageError::InvalidMessageContents(
+          "StartScanningManagers requires at least one manager name.".to_owned(),
+        )
+        .into();
+      }
+      if let Some(unknown) = names.iter().find(|name| !self.comm_managers.contains_key(*name)) {
+        return ButtplugDeviceError::DeviceCommunicationManagerNotFound(unknown.clone()).into();
+      }
+    }
+    let names = names.map(|names| names.to_vec());
+    let mgrs = self.comm_managers.clone();
+    let sender = self.device_event_sender.clone();
+    Box::pin(async move {
+      // Scoped so we don't hold map guards across the await.
+      let fut_vec: Vec<_> = {
+        let selected: Vec<_> = mgrs
+          .iter()
+          .filter(|mgr| {
+            names
+              .as_ref()
+              .map_or(true, |names| names.contains(mgr.key()))
+          })
+          .collect();
+        if selected
+          .iter()
+          .any(|mgr| mgr.value().scanning_status().load(Ordering::SeqCst))
+        {
+          return Err(ButtplugDeviceError::DeviceScanningAlreadyStarted.into());
         }
-        let fut_vec: Vec<_> = mgrs
+        selected
           .iter()
           .map(|guard| guard.value().start_scanning())
-          .collect();
-        // TODO If start_scanning fails anywhere, this will ignore it. We should maybe at least log?
-        future::join_all(fut_vec).await;
-        debug!("All managers started, sending ScanningStarted (and invoking ScanningFinished hack) signal to event loop.");
-        // HACK: In case everything somehow exited between the time all of our
-        // futures resolved and when we updated the event loop, act like we're a
-        // device comm manager and send a ScanningFinished message. This will
-        // cause the finish check to run just in case, so we don't get stuck.
-        //
-        // Ideally, this should be some sort of state machine, but for now, we
-        // can deal with this.
-        //
-        // At this point, it doesn't really matter what we return, only way that
-        // event loop could shut down is if the whole system is shutting down.
-        // So complain if our sends error out, but don't worry about returning
-        // an error.
-        if sender
-          .send(DeviceCommunicationEvent::ScanningStarted)
+          .collect()
+      };
+      // TODO If start_scanning fails anywhere, this will ignore it. We should maybe at least log?
+      future::join_all(fut_vec).await;
+      debug!("All managers started, sending ScanningStarted (and invoking ScanningFinished hack) signal to event loop.");
+      // HACK: In case everything somehow exited between the time all of our
+      // futures resolved and when we updated the event loop, act like we're a
+      // device comm manager and send a ScanningFinished message. This will
+      // cause the finish check to run just in case, so we don't get stuck.
+      //
+      // Ideally, this should be some sort of state machine, but for now, we
+      // can deal with this.
+      //
+      // At this point, it doesn't really matter what we return, only way that
+      // event loop could shut down is if the whole system is shutting down.
+      // So complain if our sends error out, but don't worry about returning
+      // an error.
+      if sender
+        .send(DeviceCommunicationEvent::ScanningStarted)
+        .await
+        .is_err()
+        || sender
+          .send(DeviceCommunicationEvent::ScanningFinished)
           .await
           .is_err()
-          || sender
-            .send(DeviceCommunicationEvent::ScanningFinished)
-            .await
-            .is_err()
-        {
-          debug!("Device manager event loop shut down, cannot send ScanningStarted");
-        }
-        Ok(messages::Ok::default().into())
-      })
-    }
+      {
+        debug!("Device manager event loop shut down, cannot send ScanningStarted");
+      }
+      Ok(messages::Ok::default().into())
+    })
   }
 
   fn stop_scanning(&self) -> ButtplugServerResultFuture {
@@ -180,15 +231,102 @@ impl DeviceManager {
     })
   }
 
+  fn reconnect_device(&self, msg: &ReconnectDevice) -> ButtplugServerResultFuture {
+    let device_index = msg.device_index();
+    if self.devices.contains_key(&device_index) {
+      debug!("Device {} already connected, no reconnect needed.", device_index);
+      return Box::pin(future::ready(Ok(messages::Ok::default().into())));
+    }
+    let address = match self
+      .device_index_map
+      .iter()
+      .find(|entry| *entry.value() == device_index)
+    {
+      Some(entry) => entry.key().clone(),
+      None => return ButtplugDeviceError::DeviceNotAvailable(device_index).into(),
+    };
+    // Only one comm manager should own any given address, so use the first one
+    // that claims it.
+    let reconnect_fut = self
+      .comm_managers
+      .iter()
+      .find_map(|mgr| mgr.value().reconnect_device(&address));
+    match reconnect_fut {
+      Some(fut) => {
+        info!("Trying to reconnect device {} at address {}", device_index, address);
+        Box::pin(async move {
+          fut.await?;
+          Ok(messages::Ok::default().into())
+        })
+      }
+      None => ButtplugDeviceError::DeviceConnectionError(format!(
+        "No device communication manager can reconnect device {}.",
+        device_index
+      ))
+      .into(),
+    }
+  }
+
+  fn disconnect_device(&self, msg: &DisconnectDevice) -> ButtplugServerResultFuture {
+    let device_index = msg.device_index();
+    let device = match self.devices.get(&device_index) {
+      Some(device) => device.value().clone(),
+      None => return ButtplugDeviceError::DeviceNotAvailable(device_index).into(),
+    };
+    let event_sender = self.device_event_sender.clone();
+    Box::pin(async move {
+      info!("Force disconnecting device {} at address {}", device_index, device.address());
+      // We're dropping the device regardless, so don't let a device that's
+      // misbehaving enough to need this stop us.
+      if let Err(err) = device.disconnect().await {
+        error!("Error disconnecting device {}: {:?}", device_index, err);
+      }
+      // Not all device implementations report a removal when disconnected on
+      // purpose, so tell the event loop to remove the device ourselves.
+      if event_sender
+        .send(DeviceCommunicationEvent::DeviceDisconnected(
+          device.address().to_owned(),
+        ))
+        .await
+        .is_err()
+      {
+        error!("Device manager event loop shut down, cannot remove device.");
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+
   fn parse_device_message(
     &self,
     device_msg: ButtplugDeviceCommandMessageUnion,
   ) -> ButtplugServerResultFuture {
     match self.devices.get(&device_msg.device_index()) {
       Some(device) => {
+        // Note raw subscription changes, so the event loop knows whether to
+        // forward notifications for the endpoint.
+        let subscription_change = match &device_msg {
+          ButtplugDeviceCommandMessageUnion::RawSubscribeCmd(msg) => {
+            Some((msg.device_index(), msg.endpoint(), true))
+          }
+          ButtplugDeviceCommandMessageUnion::RawUnsubscribeCmd(msg) => {
+            Some((msg.device_index(), msg.endpoint(), false))
+          }
+          _ => None,
+        };
+        let raw_subscriptions = self.raw_subscriptions.clone();
         let fut = device.parse_message(device_msg);
         // Create a future to run the message through the device, then handle adding the id to the result.
-        Box::pin(async move { fut.await })
+        Box::pin(async move {
+          let result = fut.await;
+          if let (Ok(_), Some((index, endpoint, subscribed))) = (&result, subscription_change) {
+            if subscribed {
+              raw_subscriptions.insert((index, endpoint));
+            } else {
+              raw_subscriptions.remove(&(index, endpoint));
+            }
+          }
+          result
+        })
       }
       None => ButtplugDeviceError::DeviceNotAvailable(device_msg.device_index()).into(),
     }
@@ -214,7 +352,12 @@ impl DeviceManager {
       }
       ButtplugDeviceManagerMessageUnion::StopAllDevices(_) => self.stop_all_devices(),
       ButtplugDeviceManagerMessageUnion::StartScanning(_) => self.start_scanning(),
+      ButtplugDeviceManagerMessageUnion::StartScanningManagers(msg) => {
+        self.start_scanning_managers(Some(msg.managers()))
+      }
       ButtplugDeviceManagerMessageUnion::StopScanning(_) => self.stop_scanning(),
+      ButtplugDeviceManagerMessageUnion::ReconnectDevice(msg) => self.reconnect_device(&msg),
+      ButtplugDeviceManagerMessageUnion::DisconnectDevice(msg) => self.disconnect_device(&msg),
     }
   }
 
@@ -230,13 +373,22 @@ impl DeviceManager {
     }
   }
 
+  /// Builds and adds a device communication manager.
+  ///
+  /// Fails if a manager with the same name was already added, or if building
+  /// the manager panics.
   pub fn add_comm_manager<T>(&self, builder: T) -> Result<(), ButtplugServerError>
   where
     T: DeviceCommunicationManagerBuilder,
   {
-    let mgr = builder
-      .event_sender(self.device_event_sender.clone())
-      .finish();
+    // Comm managers bring up their platform backend (bluetooth adapter, serial
+    // ports, etc) in finish(), and panic if that's broken on this machine.
+    // Don't let one bad backend take down everything else.
+    let event_sender = self.device_event_sender.clone();
+    let mgr = panic::catch_unwind(AssertUnwindSafe(|| {
+      builder.event_sender(event_sender).finish()
+    }))
+    .map_err(|_| ButtplugServerError::DeviceManagerInitFailed(type_name::<T>().to_owned()))?;
     if self.comm_managers.contains_key(mgr.name()) {
       return Err(ButtplugServerError::DeviceManagerTypeAlreadyAdded(
         mgr.name().to_owned(),
@@ -256,6 +408,16 @@ impl DeviceManager {
     Ok(())
   }
 
+  /// Returns the names of all device communication managers that have been
+  /// added to the device manager.
+  pub fn comm_manager_names(&self) -> Vec<String> {
+    self
+      .comm_managers
+      .iter()
+      .map(|mgr| mgr.key().clone())
+      .collect()
+  }
+
   pub fn add_protocol<T>(&self, protocol_name: &str) -> Result<(), ButtplugServerError>
   where
     T: ButtplugProtocol,
@@ -293,6 +455,51 @@ impl DeviceManager {
     self.config.remove_protocol_definition(name);    
   }
 
+  /// Merges additional device configuration JSON into the running
+  /// configuration. Devices found after this call will be matched against the
+  /// updated definitions. See
+  /// [DeviceConfigurationManager::add_device_configuration_json] for merge
+  /// rules.
+  pub fn add_device_configuration_json(&self, config_json: &str) -> Result<(), ButtplugError> {
+    self.config.add_device_configuration_json(config_json)
+  }
+
+  /// Lists the protocols the server can create devices for, along with the
+  /// device names and identifiers each one matches. See
+  /// [DeviceConfigurationManager::supported_protocols].
+  pub fn supported_protocols(&self) -> Vec<SupportedProtocolInfo> {
+    self.config.supported_protocols()
+  }
+
+  /// Returns the negotiated BLE connection parameters for a connected device,
+  /// or None if the device isn't BLE or the platform doesn't expose them. See
+  /// [ButtplugDevice::connection_parameters].
+  pub fn device_connection_parameters(
+    &self,
+    device_index: u32,
+  ) -> Result<Option<BleConnectionParameters>, ButtplugDeviceError> {
+    self
+      .devices
+      .get(&device_index)
+      .map(|device| device.value().connection_parameters())
+      .ok_or(ButtplugDeviceError::DeviceNotAvailable(device_index))
+  }
+
+  /// Asks the platform to use the given BLE connection interval for a
+  /// connected device. See [ButtplugDevice::request_connection_interval].
+  pub fn request_device_connection_interval(
+    &self,
+    device_index: u32,
+    interval: Duration,
+  ) -> ButtplugResultFuture {
+    match self.devices.get(&device_index) {
+      Some(device) => device.value().request_connection_interval(interval),
+      None => Box::pin(future::ready(Err(
+        ButtplugDeviceError::DeviceNotAvailable(device_index).into(),
+      ))),
+    }
+  }
+
   pub fn add_allowed_device(&self, address: &str) {
     info!("Adding device address {} to allowed devices list.", address);
     self.device_allow_list.insert(address.to_owned());
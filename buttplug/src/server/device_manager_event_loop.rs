@@ -1,11 +1,12 @@
 use super::{comm_managers::DeviceCommunicationEvent, ping_timer::PingTimer};
 use crate::{
   core::messages::{
-    ButtplugServerMessage, DeviceAdded, DeviceRemoved, ScanningFinished, StopDeviceCmd,
+    ButtplugMessage, ButtplugServerMessage, DeviceAdded, DeviceRemoved, RawReading,
+    ScanningFinished, StopDeviceCmd,
   },
   device::{
     configuration_manager::DeviceConfigurationManager, ButtplugDevice, ButtplugDeviceEvent,
-    ButtplugDeviceImplCreator,
+    ButtplugDeviceImplCreator, Endpoint,
   },
   util::async_manager,
 };
@@ -21,6 +22,17 @@ use tokio::sync::{broadcast, mpsc};
 use tracing;
 use tracing_futures::Instrument;
 
+/// Device lookups shared between the [DeviceManagerEventLoop] and the device
+/// manager that owns it.
+pub struct DeviceManagerSharedState {
+  pub device_allow_list: Arc<DashSet<String>>,
+  pub device_deny_list: Arc<DashSet<String>>,
+  /// Maps device addresses to indexes, so they can be reused on reconnect.
+  pub device_index_map: Arc<DashMap<String, u32>>,
+  /// Device index/endpoint pairs that clients have raw subscribed to.
+  pub raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
+}
+
 pub struct DeviceManagerEventLoop {
   device_config_manager: Arc<DeviceConfigurationManager>,
   device_index_generator: u32,
@@ -30,6 +42,10 @@ pub struct DeviceManagerEventLoop {
   ping_timer: Arc<PingTimer>,
   /// Maps device addresses to indexes, so they can be reused on reconnect.
   device_index_map: Arc<DashMap<String, u32>>,
+  /// Device index/endpoint pairs that clients have raw subscribed to, shared
+  /// with the device manager. Notifications on these are forwarded to the
+  /// server as [RawReading] messages.
+  raw_subscriptions: Arc<DashSet<(u32, Endpoint)>>,
   /// Broadcaster that relays device events in the form of Buttplug Messages to
   /// whoever owns the Buttplug Server.
   server_sender: broadcast::Sender<ButtplugServerMessage>,
@@ -52,8 +68,7 @@ impl DeviceManagerEventLoop {
     device_config_manager: Arc<DeviceConfigurationManager>,
     server_sender: broadcast::Sender<ButtplugServerMessage>,
     device_map: Arc<DashMap<u32, Arc<ButtplugDevice>>>,
-    device_allow_list: Arc<DashSet<String>>,
-    device_deny_list: Arc<DashSet<String>>,
+    shared_state: DeviceManagerSharedState,
     ping_timer: Arc<PingTimer>,
     device_comm_receiver: mpsc::Receiver<DeviceCommunicationEvent>,
   ) -> Self {
@@ -62,12 +77,13 @@ impl DeviceManagerEventLoop {
       device_config_manager,
       server_sender,
       device_map,
-      device_allow_list,
-      device_deny_list,
+      device_allow_list: shared_state.device_allow_list,
+      device_deny_list: shared_state.device_deny_list,
       ping_timer,
       device_comm_receiver,
       device_index_generator: 0,
-      device_index_map: Arc::new(DashMap::new()),
+      device_index_map: shared_state.device_index_map,
+      raw_subscriptions: shared_state.raw_subscriptions,
       device_event_sender,
       device_event_receiver,
       scanning_in_progress: false,
@@ -176,6 +192,7 @@ impl DeviceManagerEventLoop {
       DeviceCommunicationEvent::DeviceManagerAdded(status) => {
         self.comm_manager_scanning_statuses.push(status);
       },
+      DeviceCommunicationEvent::DeviceDisconnected(address) => self.remove_device(&address),
     }
   }
 
@@ -247,21 +264,48 @@ impl DeviceManagerEventLoop {
           debug!("Server not currently available, dropping Device Added event.");
         }
       }
-      ButtplugDeviceEvent::Removed(address) => {
-        let device_index = *self.device_index_map.get(&address).unwrap().value();
-        self.device_map.remove(&device_index).unwrap();
-        if self
-          .server_sender
-          .send(DeviceRemoved::new(device_index).into())
-          .is_err()
-        {
-          debug!("Server not currently available, dropping Device Removed event.");
+      ButtplugDeviceEvent::Removed(address) => self.remove_device(&address),
+      ButtplugDeviceEvent::Notification(address, endpoint, data) => {
+        let device_index = match self.device_index_map.get(&address) {
+          Some(index) => *index.value(),
+          None => return,
+        };
+        // Protocols may subscribe to endpoints for their own use, so only
+        // pass along notifications a client actually asked for.
+        if !self.raw_subscriptions.contains(&(device_index, endpoint)) {
+          return;
+        }
+        // Subscription readings aren't replies to anything, so they get the
+        // event id.
+        let mut reading = RawReading::new(device_index, endpoint, data);
+        reading.set_id(0);
+        if self.server_sender.send(reading.into()).is_err() {
+          debug!("Server not currently available, dropping RawReading event.");
         }
       }
-      ButtplugDeviceEvent::Notification(_address, _endpoint, _data) => {
-        // TODO At some point here we need to fill this in for RawSubscribe and
-        // other sensor subscriptions.
-      }
+    }
+  }
+
+  fn remove_device(&self, address: &str) {
+    let device_index = match self.device_index_map.get(address) {
+      Some(index) => *index.value(),
+      None => return,
+    };
+    // Devices we've force disconnected may still report their own removal
+    // afterward, so only act on the first one.
+    if self.device_map.remove(&device_index).is_none() {
+      debug!("Device {} already removed, ignoring.", device_index);
+      return;
+    }
+    self
+      .raw_subscriptions
+      .retain(|(index, _)| *index != device_index);
+    if self
+      .server_sender
+      .send(DeviceRemoved::new(device_index).into())
+      .is_err()
+    {
+      debug!("Server not currently available, dropping Device Removed event.");
     }
   }
 
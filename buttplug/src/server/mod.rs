@@ -50,6 +50,8 @@ pub type ButtplugServerResultFuture = BoxFuture<'static, ButtplugServerResult>;
 pub enum ButtplugServerError {
   #[error("DeviceManager of type {0} has already been added.")]
   DeviceManagerTypeAlreadyAdded(String),
+  #[error("DeviceManager built by {0} failed to initialize.")]
+  DeviceManagerInitFailed(String),
   #[error("Buttplug Protocol of type {0} has already been added to the system.")]
   ProtocolAlreadyAdded(String),
   #[error("Buttplug Protocol of type {0} does not exist in the system and cannot be removed.")]
@@ -116,6 +116,12 @@ impl<T> ButtplugFutureStateShared<T> {
   pub fn set_reply(&self, reply: T) {
     self.lock().set_reply(reply);
   }
+
+  /// Returns true if this is the only remaining handle to the state, meaning
+  /// nothing can read the reply anymore.
+  pub fn is_abandoned(&self) -> bool {
+    Arc::strong_count(&self.state) == 1
+  }
 }
 
 impl<T> Default for ButtplugFutureStateShared<T> {
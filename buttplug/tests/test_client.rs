@@ -2,24 +2,39 @@ mod util;
 extern crate buttplug;
 
 use buttplug::{
-  client::{ButtplugClient, ButtplugClientError, ButtplugClientEvent, VibrateCommand},
+  client::{
+    ButtplugClient, ButtplugClientError, ButtplugClientEvent, ButtplugClientEventLoopExit,
+    ButtplugClientPausedCommandPolicy, ButtplugClientPingTimeoutPolicy,
+    ButtplugClientUnknownMessagePolicy, VibrateCommand, MAX_CLIENT_NAME_LENGTH,
+    MIN_WATCHDOG_TIMEOUT,
+  },
   connector::{
-    ButtplugConnector, ButtplugConnectorError, ButtplugConnectorResultFuture,
-    ButtplugInProcessClientConnector,
+    transport::ButtplugTransportIncomingMessage, ButtplugConnector, ButtplugConnectorError,
+    ButtplugConnectorResultFuture, ButtplugInProcessClientConnector,
   },
   core::{
-    errors::{ButtplugDeviceError, ButtplugError},
-    messages::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage},
+    errors::{
+      ButtplugDeviceError, ButtplugError, ButtplugHandshakeError, ButtplugMessageError,
+      ButtplugPingError,
+    },
+    messages::{
+      self, serializer::ButtplugSerializedMessage, ButtplugClientMessage,
+      ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage, ButtplugMessage,
+    },
   },
   device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
   server::ButtplugServerBuilder,
   server::comm_managers::test::{check_test_recv_value, TestDeviceCommunicationManagerBuilder},
   util::async_manager,
 };
-use futures::{future::BoxFuture, StreamExt};
+use futures::{
+  future::{self, BoxFuture},
+  select, FutureExt, StreamExt,
+};
 use futures_timer::Delay;
-use std::time::Duration;
-use tokio::sync::mpsc::Sender;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{mpsc::Sender, Notify};
+use tokio_util::sync::CancellationToken;
 use util::DelayDeviceCommunicationManagerBuilder;
 
 #[derive(Default)]
@@ -44,6 +59,58 @@ impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServ
   }
 }
 
+/// Connector that answers the handshake, then hangs on Ping until released,
+/// stalling the client event loop.
+#[derive(Default)]
+struct StallingConnector {
+  sender: Arc<std::sync::Mutex<Option<Sender<ButtplugCurrentSpecServerMessage>>>>,
+  release: Arc<Notify>,
+  sent: Arc<std::sync::Mutex<Vec<ButtplugCurrentSpecClientMessage>>>,
+}
+
+impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+  for StallingConnector
+{
+  fn connect(
+    &mut self,
+    sender: Sender<ButtplugCurrentSpecServerMessage>,
+  ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+    *self.sender.lock().unwrap() = Some(sender);
+    Box::pin(future::ready(Ok(())))
+  }
+
+  fn disconnect(&self) -> ButtplugConnectorResultFuture {
+    Box::pin(future::ready(Ok(())))
+  }
+
+  fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+    self.sent.lock().unwrap().push(msg.clone());
+    let sender = self.sender.lock().unwrap().clone().unwrap();
+    let release = self.release.clone();
+    Box::pin(async move {
+      let mut reply: ButtplugCurrentSpecServerMessage = match msg {
+        ButtplugCurrentSpecClientMessage::RequestServerInfo(_) => messages::ServerInfo::new(
+          "Stalling Server",
+          messages::BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+          0,
+        )
+        .into(),
+        ButtplugCurrentSpecClientMessage::RequestDeviceList(_) => {
+          messages::DeviceList::new(vec![]).into()
+        }
+        ButtplugCurrentSpecClientMessage::Ping(_) => {
+          release.notified().await;
+          messages::Ok::default().into()
+        }
+        _ => messages::Ok::default().into(),
+      };
+      reply.set_id(msg.id());
+      let _ = sender.send(reply).await;
+      Ok(())
+    })
+  }
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_failing_connection() {
@@ -56,6 +123,70 @@ fn test_failing_connection() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_invalid_client_name() {
+  async_manager::block_on(async {
+    let long_name = "a".repeat(MAX_CLIENT_NAME_LENGTH + 1);
+    for name in ["", "   ", "Test\nClient", long_name.as_str()].iter() {
+      let client = ButtplugClient::new(name);
+      assert!(matches!(
+        client
+          .connect(ButtplugInProcessClientConnector::default())
+          .await
+          .unwrap_err(),
+        ButtplugClientError::ButtplugError(ButtplugError::ButtplugHandshakeError(
+          ButtplugHandshakeError::InvalidClientName(..)
+        ))
+      ));
+      assert!(!client.connected());
+    }
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_handshake_name() {
+  async_manager::block_on(async {
+    // Only the handshake name is validated, labels can be anything.
+    let client = ButtplugClient::new_with_handshake_name("", "Test Client");
+    assert_eq!(client.client_name(), "");
+    assert_eq!(client.handshake_client_name(), "Test Client");
+    client
+      .connect(ButtplugInProcessClientConnector::default())
+      .await
+      .unwrap();
+    assert!(client.connected());
+  });
+}
+
+#[test]
+fn test_client_connect_cancel_during_handshake() {
+  async_manager::block_on(async {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    let mut event_stream = helper.client().event_stream();
+    let token = CancellationToken::new();
+    let (result, _) = futures::join!(helper.connect_with_cancellation(token.clone()), async {
+      // Wait for the handshake to start, then cancel instead of replying.
+      assert!(matches!(
+        helper.get_next_client_message().await,
+        ButtplugClientMessage::RequestServerInfo(..)
+      ));
+      token.cancel();
+    });
+    assert!(matches!(
+      result.unwrap_err(),
+      ButtplugClientError::ButtplugConnectorError(ButtplugConnectorError::ConnectorCancelled)
+    ));
+    assert!(!helper.client().connected());
+    // The event loop should have shut down.
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::ServerDisconnect
+    ));
+  });
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_disconnect_status() {
@@ -80,7 +211,35 @@ fn test_double_disconnect() {
       .await
       .unwrap();
     assert!(client.disconnect().await.is_ok());
-    assert!(client.disconnect().await.is_err());
+    assert!(client.disconnect().await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_concurrent_disconnect() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client
+      .connect(ButtplugInProcessClientConnector::default())
+      .await
+      .unwrap();
+    let (first, second) = futures::join!(client.disconnect(), client.disconnect());
+    assert!(first.is_ok());
+    assert!(second.is_ok());
+    assert!(!client.connected());
+    // Give the event loop plenty of time to emit anything extra.
+    client.event_loop_handle().unwrap().join().await;
+    Delay::new(Duration::from_millis(100)).await;
+    let mut disconnect_count = 0;
+    while let Some(event) = event_stream.next().now_or_never().flatten() {
+      if matches!(event, ButtplugClientEvent::ServerDisconnect) {
+        disconnect_count += 1;
+      }
+    }
+    assert_eq!(disconnect_count, 1);
+    assert!(client.disconnect().await.is_ok());
   });
 }
 
@@ -194,6 +353,106 @@ fn test_client_scanning_finished() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_client_start_scanning_managers() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    builder.add_ble_device("Massage Demo");
+    let device_manager = connector.server_ref().device_manager();
+    device_manager.add_comm_manager(builder).unwrap();
+    // The delay manager never finishes scanning, so if it were started we'd
+    // never see ScanningFinished below.
+    device_manager
+      .add_comm_manager(DelayDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    let client = ButtplugClient::new("Test Client");
+    let mut recv = client.event_stream();
+    client.connect(connector).await.unwrap();
+
+    assert!(matches!(
+      client.start_scanning_managers(vec![]).await,
+      Err(ButtplugClientError::ButtplugError(
+        ButtplugError::ButtplugMessageError(
+          ButtplugMessageError::InvalidMessageContents(_)
+        )
+      ))
+    ));
+    assert!(matches!(
+      client
+        .start_scanning_managers(vec!["NotARealCommManager".to_owned()])
+        .await,
+      Err(ButtplugClientError::ButtplugError(
+        ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::DeviceCommunicationManagerNotFound(_)
+        )
+      ))
+    ));
+    client
+      .start_scanning_managers(vec!["TestDeviceCommunicationManager".to_owned()])
+      .await
+      .unwrap();
+    // The device and the end of scanning can show up in either order.
+    let mut device_added = false;
+    let mut scanning_finished = false;
+    while !(device_added && scanning_finished) {
+      select! {
+        event = recv.next().fuse() => match event.unwrap() {
+          ButtplugClientEvent::DeviceAdded(_) => device_added = true,
+          ButtplugClientEvent::ScanningFinished => scanning_finished = true,
+          _ => {}
+        },
+        _ = Delay::new(Duration::from_secs(5)).fuse() => break,
+      };
+    }
+    assert!(device_added);
+    assert!(scanning_finished);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_event_loop_handle() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    assert!(client.event_loop_handle().is_none());
+    client
+      .connect(ButtplugInProcessClientConnector::default())
+      .await
+      .unwrap();
+    let handle = client.event_loop_handle().unwrap();
+    assert!(!handle.is_finished());
+    client.disconnect().await.unwrap();
+    assert_eq!(handle.join().await, ButtplugClientEventLoopExit::Finished);
+    assert!(handle.is_finished());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_event_loop_abort() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut recv = client.event_stream();
+    client
+      .connect(ButtplugInProcessClientConnector::default())
+      .await
+      .unwrap();
+    let handle = client.event_loop_handle().unwrap();
+    handle.abort();
+    assert_eq!(handle.join().await, ButtplugClientEventLoopExit::Aborted);
+    // Aborting the loop disconnects the client.
+    assert!(!client.connected());
+    assert!(client.ping().await.is_err());
+    while let Some(event) = recv.next().await {
+      if let ButtplugClientEvent::ServerDisconnect = event {
+        break;
+      }
+    }
+  });
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_client_ping() {
@@ -209,6 +468,224 @@ fn test_client_ping() {
   });
 }
 
+#[test]
+fn test_client_measure_latency() {
+  async_manager::block_on(async {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    assert!(helper.client().average_latency().is_none());
+    let reply_after = |delay: u64| {
+      let helper = helper.clone();
+      async move {
+        let msg = helper.get_next_client_message().await;
+        assert!(matches!(msg, ButtplugClientMessage::Ping(..)));
+        Delay::new(Duration::from_millis(delay)).await;
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+      }
+    };
+    let (latency, _) = futures::join!(helper.client().measure_latency(), reply_after(50));
+    let first = latency.unwrap();
+    assert!(first >= Duration::from_millis(50));
+    assert_eq!(helper.client().average_latency(), Some(first));
+    let (latency, _) = futures::join!(helper.client().measure_latency(), reply_after(150));
+    let second = latency.unwrap();
+    assert!(second >= Duration::from_millis(150));
+    assert_eq!(helper.client().average_latency(), Some((first + second) / 2));
+  });
+}
+
+#[test]
+fn test_client_pending_request_count() {
+  async_manager::block_on(async {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    assert_eq!(helper.client().pending_request_count(), 0);
+    helper.simulate_successful_connect().await;
+    assert_eq!(helper.client().pending_request_count(), 0);
+    let reply_both = async {
+      let first = helper.get_next_client_message().await;
+      let second = helper.get_next_client_message().await;
+      // Both requests are out, neither has been answered.
+      assert_eq!(helper.client().pending_request_count(), 2);
+      helper
+        .send_client_incoming(messages::Ok::new(first.id()).into())
+        .await;
+      helper
+        .send_client_incoming(messages::Ok::new(second.id()).into())
+        .await;
+    };
+    let (first, second, _) =
+      futures::join!(helper.client().ping(), helper.client().ping(), reply_both);
+    assert!(first.is_ok() && second.is_ok());
+    assert_eq!(helper.client().pending_request_count(), 0);
+  });
+}
+
+async fn simulate_ping_timeout(helper: &util::ChannelClientTestHelper) {
+  helper
+    .send_client_incoming(
+      messages::Error::from(ButtplugError::from(ButtplugPingError::PingedOut)).into(),
+    )
+    .await;
+  // Devices get stopped no matter what the policy is.
+  assert!(matches!(
+    helper.get_next_client_message().await,
+    ButtplugClientMessage::StopAllDevices(..)
+  ));
+}
+
+#[test]
+fn test_client_ping_timeout_policy_notify() {
+  async_manager::block_on(async {
+    let helper = util::ChannelClientTestHelper::new();
+    helper.simulate_successful_connect().await;
+    assert_eq!(
+      helper.client().ping_timeout_policy(),
+      ButtplugClientPingTimeoutPolicy::Notify
+    );
+    let mut event_stream = helper.client().event_stream();
+    simulate_ping_timeout(&helper).await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::PingTimeout
+    ));
+    assert!(helper.client().connected());
+  });
+}
+
+#[test]
+fn test_client_ping_timeout_policy_disconnect() {
+  async_manager::block_on(async {
+    let helper = util::ChannelClientTestHelper::new();
+    helper.simulate_successful_connect().await;
+    helper
+      .client()
+      .set_ping_timeout_policy(ButtplugClientPingTimeoutPolicy::Disconnect);
+    let mut event_stream = helper.client().event_stream();
+    simulate_ping_timeout(&helper).await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::PingTimeout
+    ));
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::ServerDisconnect
+    ));
+    assert!(!helper.client().connected());
+  });
+}
+
+#[test]
+fn test_client_unknown_message_policy() {
+  async_manager::block_on(async {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut event_stream = helper.client().event_stream();
+    assert_eq!(
+      helper.client().unknown_message_policy(),
+      ButtplugClientUnknownMessagePolicy::Ignore
+    );
+    // Known messages sent alongside the unknown one should still make it
+    // through, so follow it with a ScanningFinished.
+    let send_unknown = || {
+      helper.send_incoming(ButtplugTransportIncomingMessage::Message(
+        ButtplugSerializedMessage::Text(
+          r#"[{"FancyNewMessage":{"Id":0}},{"ScanningFinished":{"Id":0}}]"#.to_owned(),
+        ),
+      ))
+    };
+
+    send_unknown().await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::ScanningFinished
+    ));
+
+    helper
+      .client()
+      .set_unknown_message_policy(ButtplugClientUnknownMessagePolicy::Event);
+    send_unknown().await;
+    if let ButtplugClientEvent::UnknownMessage(msg) = event_stream.next().await.unwrap() {
+      assert_eq!(msg, r#"{"FancyNewMessage":{"Id":0}}"#);
+    } else {
+      panic!("Should've gotten an UnknownMessage event.");
+    }
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::ScanningFinished
+    ));
+
+    helper
+      .client()
+      .set_unknown_message_policy(ButtplugClientUnknownMessagePolicy::Error);
+    send_unknown().await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::Error(ButtplugError::ButtplugMessageError(
+        ButtplugMessageError::UnknownMessage(..)
+      ))
+    ));
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::ScanningFinished
+    ));
+    assert!(helper.client().connected());
+  });
+}
+
+#[test]
+fn test_client_event_loop_watchdog() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    client.set_watchdog_timeout(Some(Duration::from_millis(200)));
+    let connector = StallingConnector::default();
+    let release = connector.release.clone();
+    let sent = connector.sent.clone();
+    client.connect(connector).await.unwrap();
+    let mut event_stream = client.event_stream();
+    // Nothing is stalled yet, so the watchdog should stay quiet.
+    Delay::new(Duration::from_millis(500)).await;
+    assert!(event_stream.next().now_or_never().is_none());
+    // Hang the event loop in the connector.
+    let ping_fut = client.ping();
+    async_manager::spawn(async move {
+      let _ = ping_fut.await;
+    })
+    .unwrap();
+    let stalled = select! {
+      event = event_stream.next().fuse() => matches!(event, Some(ButtplugClientEvent::EventLoopStalled)),
+      _ = Delay::new(Duration::from_secs(2)).fuse() => false,
+    };
+    assert!(stalled);
+    let stop_sent = |sent: &Arc<std::sync::Mutex<Vec<ButtplugCurrentSpecClientMessage>>>| {
+      sent
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|msg| matches!(msg, ButtplugCurrentSpecClientMessage::StopAllDevices(..)))
+    };
+    // The loop is still stuck, so the stop command can't have gone out yet.
+    assert!(!stop_sent(&sent));
+    release.notify_one();
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(stop_sent(&sent));
+  });
+}
+
+#[test]
+fn test_client_watchdog_timeout_minimum() {
+  let client = ButtplugClient::new("Test Client");
+  assert_eq!(client.watchdog_timeout(), None);
+  // A zero timeout would have the watchdog spin, so it gets raised.
+  client.set_watchdog_timeout(Some(Duration::ZERO));
+  assert_eq!(client.watchdog_timeout(), Some(MIN_WATCHDOG_TIMEOUT));
+  client.set_watchdog_timeout(Some(Duration::from_secs(1)));
+  assert_eq!(client.watchdog_timeout(), Some(Duration::from_secs(1)));
+  client.set_watchdog_timeout(None);
+  assert_eq!(client.watchdog_timeout(), None);
+}
+
 // Tests both the stop all devices functionality, as well as both ends of the
 // command range for is_in_command_range message validation.
 #[cfg(feature = "server")]
@@ -263,6 +740,196 @@ fn test_stop_all_devices_and_device_command_range() {
   });
 }
 
+#[test]
+fn test_client_pause_resume() {
+  async_manager::block_on(async {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let device = helper
+      .add_test_device(1, "Test Device", &util::vibrate_device_attributes())
+      .await;
+    let mut event_stream = helper.client().event_stream();
+
+    helper
+      .client()
+      .pause(ButtplugClientPausedCommandPolicy::Queue, false)
+      .await
+      .unwrap();
+    assert!(helper.client().paused());
+    // Commands are held while paused.
+    let vibrate_fut = device.vibrate(VibrateCommand::Speed(0.5));
+    helper
+      .send_client_incoming(messages::ScanningFinished::default().into())
+      .await;
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    assert!(event_stream.next().now_or_never().is_none());
+    // On resume, queued commands go out, then held events are emitted.
+    helper.client().resume().await.unwrap();
+    assert!(!helper.client().paused());
+    let (result, _) = futures::join!(vibrate_fut, async {
+      let msg = helper.get_next_client_message().await;
+      assert!(matches!(msg, ButtplugClientMessage::VibrateCmd(..)));
+      helper
+        .send_client_incoming(messages::Ok::new(msg.id()).into())
+        .await;
+    });
+    assert!(result.is_ok());
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::ScanningFinished
+    ));
+
+    // Pausing with stop_devices sends a StopAllDevices before resolving.
+    let (result, _) = futures::join!(
+      helper
+        .client()
+        .pause(ButtplugClientPausedCommandPolicy::Drop, true),
+      async {
+        let msg = helper.get_next_client_message().await;
+        assert!(matches!(msg, ButtplugClientMessage::StopAllDevices(..)));
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+      }
+    );
+    assert!(result.is_ok());
+    assert!(matches!(
+      device.vibrate(VibrateCommand::Speed(0.5)).await,
+      Err(ButtplugClientError::ClientPaused)
+    ));
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    helper.client().resume().await.unwrap();
+    assert!(helper.client().connected());
+  });
+}
+
+#[test]
+fn test_client_server_capabilities() {
+  async_manager::block_on(async {
+    let helper = util::ChannelClientTestHelper::new();
+    assert!(helper.client().server_capabilities().is_empty());
+    let mut server_info = messages::ServerInfo::new(
+      "test server",
+      messages::BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      0,
+    );
+    server_info.set_capabilities(vec![
+      "SensorStreaming".to_owned(),
+      "SomethingNew".to_owned(),
+    ]);
+    helper
+      .simulate_successful_connect_with_server_info(server_info)
+      .await;
+    let capabilities = helper.client().server_capabilities();
+    assert!(capabilities.contains("SensorStreaming"));
+    assert!(capabilities.contains("SomethingNew"));
+    assert!(!capabilities.contains("FirmwareUpdate"));
+    assert_eq!(
+      capabilities.iter().cloned().collect::<Vec<String>>(),
+      vec!["SensorStreaming".to_owned(), "SomethingNew".to_owned()]
+    );
+    // Whatever the server reported goes away with the connection.
+    helper.client().disconnect().await.unwrap();
+    assert!(helper.client().server_capabilities().is_empty());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_scan_with_timeout_found() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    builder.add_ble_device("Massage Demo");
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let client = ButtplugClient::new("Test Client");
+    client.connect(connector).await.unwrap();
+    assert_eq!(
+      client
+        .scan_with_timeout(Duration::from_millis(100))
+        .await
+        .unwrap(),
+      1
+    );
+    assert_eq!(client.devices().len(), 1);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_scan_with_timeout_not_found() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    connector
+      .server_ref()
+      .device_manager()
+      .add_comm_manager(DelayDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    let client = ButtplugClient::new("Test Client");
+    client.connect(connector).await.unwrap();
+    assert!(matches!(
+      client.scan_with_timeout(Duration::from_millis(100)).await,
+      Err(ButtplugClientError::NoDevicesFound)
+    ));
+    assert!(client.devices().is_empty());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_battery_stream_skips_unsupported_and_ends_on_disconnect() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    builder.add_ble_device("Massage Demo");
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let client = ButtplugClient::new("Test Client");
+    client.connect(connector).await.unwrap();
+    client
+      .scan_for_duration(Duration::from_millis(100))
+      .await
+      .unwrap();
+    assert_eq!(client.devices().len(), 1);
+    // The test device has no battery support, so nothing should come out of
+    // the stream before it ends on disconnect.
+    let mut battery_stream = client.battery_stream(Duration::from_millis(10));
+    let disconnect = async {
+      Delay::new(Duration::from_millis(50)).await;
+      client.disconnect().await.unwrap();
+    };
+    let (reading, _) = future::join(battery_stream.next(), disconnect).await;
+    assert!(reading.is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_battery_stream_ends_on_disconnect_mid_interval() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    client
+      .connect(ButtplugInProcessClientConnector::default())
+      .await
+      .unwrap();
+    // The interval is far longer than the test, so the stream can only end in
+    // time if it stops waiting as soon as the client disconnects.
+    let mut battery_stream = client.battery_stream(Duration::from_secs(60));
+    let disconnect = async {
+      Delay::new(Duration::from_millis(50)).await;
+      client.disconnect().await.unwrap();
+    };
+    let ended = async {
+      select! {
+        reading = battery_stream.next().fuse() => reading.is_none(),
+        _ = Delay::new(Duration::from_secs(5)).fuse() => false,
+      }
+    };
+    let (ended, _) = future::join(ended, disconnect).await;
+    assert!(ended);
+  });
+}
+
 // TODO Test calling connect twice
 // TODO Test calling disconnect twice w/o connection
 // TODO Test invalid return on RequestServerInfo
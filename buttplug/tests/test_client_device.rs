@@ -1,20 +1,30 @@
 mod util;
 use buttplug::{
   client::{
-    ButtplugClient, ButtplugClientDeviceEvent, ButtplugClientError, ButtplugClientEvent,
-    VibrateCommand,
+    ButtplugClient, ButtplugClientDeviceEvent, ButtplugClientDeviceMessageType,
+    ButtplugClientDeviceSensorReading, ButtplugClientDeviceSensorType,
+    ButtplugClientDeviceSnapshot, ButtplugClientError, ButtplugClientEvent, DeviceIndex,
+    DeviceInformation, LinearCommand, OscillateCommand, RotateCommand, VibrateCommand,
   },
   connector::ButtplugInProcessClientConnector,
   core::{
-    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError},
-    messages::{self, ButtplugClientMessage},
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::{
+      self, ButtplugClientMessage, ButtplugDeviceMessage, ButtplugDeviceMessageType,
+      ButtplugMessage, DeviceMessageAttributes, DeviceMessageAttributesMap,
+    },
   },
-  server::comm_managers::test::TestDeviceCommunicationManagerBuilder,
+  device::{ButtplugDeviceEvent, DeviceWriteCmd, Endpoint},
+  server::{comm_managers::test::TestDeviceCommunicationManagerBuilder, ButtplugServerBuilder},
   util::async_manager,
 };
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 use futures_timer::Delay;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+  collections::HashMap,
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
 #[cfg(feature = "server")]
 #[test]
@@ -53,6 +63,118 @@ fn test_client_device_connected_status() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_client_reconnect_device() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut device_index = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    assert_eq!(client.device(device_index).unwrap().index(), u32::from(device_index));
+    assert!(client
+      .device(DeviceIndex::from(u32::from(device_index) + 100))
+      .is_none());
+    // Reconnecting a connected device is a no-op.
+    assert!(client.reconnect_device(device_index).await.is_ok());
+    device.disconnect().await.unwrap();
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceRemoved(_) = msg {
+        break;
+      }
+    }
+
+    // Bring the device back, along with one we've never seen. Only the device
+    // we ask for should come back.
+    let device = helper
+      .add_ble_device_with_address("Massage Demo", &device.address())
+      .await;
+    helper.add_ble_device("Massage Demo").await;
+    assert!(client.reconnect_device(device_index).await.is_ok());
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        assert_eq!(da.device_index(), device_index);
+        break;
+      }
+    }
+    assert_eq!(client.devices().len(), 1);
+
+    // If the device doesn't show up, we should time out.
+    device.disconnect().await.unwrap();
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceRemoved(_) = msg {
+        break;
+      }
+    }
+    assert!(matches!(
+      client.reconnect_device(device_index).await.unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceReconnectTimeout(..)
+      ))
+    ));
+    // Devices the server has never seen can't be reconnected.
+    assert!(matches!(
+      client
+        .reconnect_device(DeviceIndex::from(u32::from(device_index) + 100))
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceNotAvailable(..)
+      ))
+    ));
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_force_disconnect_device() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    builder.add_ble_device("Massage Demo");
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut device_index = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        device_index = Some(da.device_index());
+        break;
+      }
+    }
+    let device_index = device_index.unwrap();
+    client.force_disconnect(device_index).await.unwrap();
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceRemoved(info) = msg {
+        assert_eq!(info.device_index(), device_index);
+        break;
+      }
+    }
+    assert!(client.device(device_index).is_none());
+    assert!(matches!(
+      client.force_disconnect(device_index).await.unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceNotAvailable(..)
+      ))
+    ));
+  });
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_client_device_client_disconnected_status() {
@@ -145,8 +267,8 @@ fn test_client_device_invalid_command() {
         .vibrate(VibrateCommand::Speed(2.0))
         .await
         .unwrap_err(),
-      ButtplugClientError::ButtplugError(ButtplugError::ButtplugMessageError(
-        ButtplugMessageError::InvalidMessageContents(..)
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceCommandValueError(..)
       ))
     ));
     assert!(matches!(
@@ -170,6 +292,75 @@ fn test_client_device_invalid_command() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_invalid_command_values() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    for msg_type in [
+      ButtplugDeviceMessageType::VibrateCmd,
+      ButtplugDeviceMessageType::RotateCmd,
+      ButtplugDeviceMessageType::LinearCmd,
+    ] {
+      device_messages.insert(
+        msg_type,
+        DeviceMessageAttributes {
+          feature_count: Some(2),
+          step_count: Some(vec![20, 20]),
+          ..Default::default()
+        },
+      );
+    }
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+
+    let is_value_error = |err: ButtplugClientError, expected_index: u32| {
+      matches!(
+        err,
+        ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::DeviceCommandValueError(index, _)
+        )) if index == expected_index
+      )
+    };
+
+    for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1.5, -0.1] {
+      assert!(is_value_error(
+        test_device
+          .vibrate(VibrateCommand::Speed(value))
+          .await
+          .unwrap_err(),
+        0
+      ));
+      assert!(is_value_error(
+        test_device
+          .vibrate(VibrateCommand::SpeedVec(vec![0.5, value]))
+          .await
+          .unwrap_err(),
+        1
+      ));
+      assert!(is_value_error(
+        test_device
+          .rotate(RotateCommand::RotateVec(vec![(value, true), (0.5, false)]))
+          .await
+          .unwrap_err(),
+        0
+      ));
+      assert!(is_value_error(
+        test_device
+          .linear(LinearCommand::LinearVec(vec![(500, 0.5), (500, value)]))
+          .await
+          .unwrap_err(),
+        1
+      ));
+    }
+    // Nothing should've made it to the server.
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_client_repeated_deviceadded_message() {
@@ -248,9 +439,1337 @@ fn test_client_repeated_deviceremoved_message() {
   });
 }
 
-// TODO Test invalid messages to device
-// TODO Test invalid parameters in message
-// TODO Test device invalidation across client connections (i.e. a device shouldn't be allowed to reconnect even if index is the same)
-// TODO Test DeviceList being sent followed by repeat DeviceAdded
-// TODO Test DeviceList being sent multiple times
-// TODO Test sending device return for device that doesn't exist (in client)
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_command_dedup() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        step_count: Some(vec![20]),
+        ..Default::default()
+      },
+    );
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+    assert!(!test_device.command_dedup());
+    test_device.set_command_dedup(true);
+    assert!(test_device.command_dedup());
+
+    // Sends a vibrate command while acting as the server, returning whatever
+    // message the client sent.
+    let vibrate_and_reply = |speed: f64| {
+      let helper = helper.clone();
+      let test_device = test_device.clone();
+      async move {
+        let (result, msg) = futures::join!(
+          test_device.vibrate(VibrateCommand::Speed(speed)),
+          async {
+            let msg = helper.get_next_client_message().await;
+            helper
+              .send_client_incoming(messages::Ok::new(msg.id()).into())
+              .await;
+            msg
+          }
+        );
+        assert!(result.is_ok());
+        msg
+      }
+    };
+
+    assert!(matches!(
+      vibrate_and_reply(0.5).await,
+      ButtplugClientMessage::VibrateCmd(..)
+    ));
+    // Same value, should resolve without sending anything.
+    assert!(test_device
+      .vibrate(VibrateCommand::Speed(0.5))
+      .await
+      .is_ok());
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    // Changed value at the device's step resolution, should send.
+    assert!(matches!(
+      vibrate_and_reply(0.52).await,
+      ButtplugClientMessage::VibrateCmd(..)
+    ));
+    // Different float, but maps to the same step as the last command.
+    assert!(test_device
+      .vibrate(VibrateCommand::Speed(0.51))
+      .await
+      .is_ok());
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    // Keepalive means duplicates should still be sent every so often.
+    test_device.set_command_dedup_keepalive(Some(Duration::from_millis(50)));
+    assert!(matches!(
+      vibrate_and_reply(0.51).await,
+      ButtplugClientMessage::VibrateCmd(..)
+    ));
+    assert!(test_device
+      .vibrate(VibrateCommand::Speed(0.51))
+      .await
+      .is_ok());
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(matches!(
+      vibrate_and_reply(0.51).await,
+      ButtplugClientMessage::VibrateCmd(..)
+    ));
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_command_after_removal() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        step_count: Some(vec![20]),
+        ..Default::default()
+      },
+    );
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+    let mut event_stream = helper.client().event_stream();
+    helper
+      .send_client_incoming(messages::DeviceRemoved::new(1).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceRemoved(..)
+    ));
+    assert!(matches!(
+      test_device
+        .vibrate(VibrateCommand::Speed(0.5))
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceNotConnected(..)
+      ))
+    ));
+    // Nothing should have been sent to the server.
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_reconnect_preserves_handle() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let device_messages = util::vibrate_device_attributes();
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+    let mut event_stream = helper.client().event_stream();
+    let mut device_event_stream = test_device.event_stream();
+
+    helper
+      .send_client_incoming(messages::DeviceRemoved::new(1).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceRemoved(..)
+    ));
+    assert!(!test_device.connected());
+    assert!(test_device
+      .vibrate(VibrateCommand::Speed(0.5))
+      .await
+      .is_err());
+
+    helper
+      .send_client_incoming(messages::DeviceAdded::new(1, "Test Device", &device_messages).into())
+      .await;
+    if let ButtplugClientEvent::DeviceReconnected(device) = event_stream.next().await.unwrap() {
+      assert!(Arc::ptr_eq(&device, &test_device));
+    } else {
+      panic!("Should've gotten a DeviceReconnected event.");
+    }
+    assert!(matches!(
+      device_event_stream.next().await.unwrap(),
+      ButtplugClientDeviceEvent::DeviceRemoved
+    ));
+    assert!(matches!(
+      device_event_stream.next().await.unwrap(),
+      ButtplugClientDeviceEvent::DeviceReconnected
+    ));
+    assert!(test_device.connected());
+    assert!(Arc::ptr_eq(&helper.client().devices()[0], &test_device));
+    // The old handle can send commands again.
+    let (result, _) = futures::join!(test_device.vibrate(VibrateCommand::Speed(0.5)), async {
+      let msg = helper.get_next_client_message().await;
+      assert!(matches!(
+        msg,
+        ButtplugClientMessage::VibrateCmd(ref cmd) if cmd.device_index() == 1
+      ));
+      helper
+        .send_client_incoming(messages::Ok::new(msg.id()).into())
+        .await;
+    });
+    assert!(result.is_ok());
+
+    // A different device showing up at the same index gets a new handle.
+    helper
+      .send_client_incoming(messages::DeviceRemoved::new(1).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceRemoved(..)
+    ));
+    helper
+      .send_client_incoming(messages::DeviceAdded::new(1, "Other Device", &device_messages).into())
+      .await;
+    if let ButtplugClientEvent::DeviceAdded(device) = event_stream.next().await.unwrap() {
+      assert!(!Arc::ptr_eq(&device, &test_device));
+    } else {
+      panic!("Should've gotten a DeviceAdded event.");
+    }
+    assert!(!test_device.connected());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_last_seen() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let test_device = helper
+      .add_test_device(1, "Test Device", &util::vibrate_device_attributes())
+      .await;
+    let added_time = test_device.last_seen();
+    Delay::new(Duration::from_millis(10)).await;
+    // Failed commands shouldn't count as seeing the device.
+    let (result, _) = futures::join!(test_device.vibrate(VibrateCommand::Speed(0.5)), async {
+      let msg = helper.get_next_client_message().await;
+      let mut error = messages::Error::from(ButtplugError::from(
+        ButtplugDeviceError::DeviceNotConnected("Test Device".to_owned()),
+      ));
+      error.set_id(msg.id());
+      helper.send_client_incoming(error.into()).await;
+    });
+    assert!(result.is_err());
+    assert_eq!(test_device.last_seen(), added_time);
+    let (result, _) = futures::join!(test_device.vibrate(VibrateCommand::Speed(0.5)), async {
+      let msg = helper.get_next_client_message().await;
+      helper
+        .send_client_incoming(messages::Ok::new(msg.id()).into())
+        .await;
+    });
+    assert!(result.is_ok());
+    assert!(test_device.last_seen() > added_time);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_command_watchdog() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        ..Default::default()
+      },
+    );
+    device_messages.insert(
+      ButtplugDeviceMessageType::StopDeviceCmd,
+      DeviceMessageAttributes::default(),
+    );
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+    test_device.command_watchdog(Some(Duration::from_millis(100)));
+    // Nothing has been sent yet, so the watchdog shouldn't be armed.
+    Delay::new(Duration::from_millis(200)).await;
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    let (result, _) = futures::join!(test_device.vibrate(VibrateCommand::Speed(0.5)), async {
+      let msg = helper.get_next_client_message().await;
+      helper
+        .send_client_incoming(messages::Ok::new(msg.id()).into())
+        .await;
+    });
+    assert!(result.is_ok());
+    let msg = helper.get_next_client_message().await;
+    assert!(matches!(
+      msg,
+      ButtplugClientMessage::StopDeviceCmd(ref cmd) if cmd.device_index() == 1
+    ));
+    helper
+      .send_client_incoming(messages::Ok::new(msg.id()).into())
+      .await;
+    // The watchdog only fires once per command.
+    Delay::new(Duration::from_millis(200)).await;
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+    // Once turned off, commands no longer arm it.
+    test_device.command_watchdog(None);
+    let (result, _) = futures::join!(test_device.vibrate(VibrateCommand::Speed(0.5)), async {
+      let msg = helper.get_next_client_message().await;
+      helper
+        .send_client_incoming(messages::Ok::new(msg.id()).into())
+        .await;
+    });
+    assert!(result.is_ok());
+    Delay::new(Duration::from_millis(200)).await;
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_command_watchdog_stops_device() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector
+      .server_ref()
+      .device_manager()
+      .add_comm_manager(builder)
+      .unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    test_device.command_watchdog(Some(Duration::from_millis(100)));
+    test_device
+      .vibrate(VibrateCommand::Speed(0.5))
+      .await
+      .unwrap();
+    device.take_write_history();
+    // Nothing awaits the watchdog's stop, but it still has to reach the
+    // device.
+    Delay::new(Duration::from_millis(500)).await;
+    assert_eq!(
+      device.take_write_history(),
+      vec![
+        DeviceWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false),
+        DeviceWriteCmd::new(Endpoint::Tx, vec![0xF2, 0], false),
+      ]
+    );
+    client.disconnect().await.unwrap();
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_command_ordering() {
+  const DEVICE_COUNT: u32 = 3;
+  const COMMAND_COUNT: u32 = 300;
+  // Powers of two divide evenly in binary, so speeds come back out of JSON
+  // exactly as they went in. Must be larger than COMMAND_COUNT to stay in
+  // range.
+  const SPEED_DIVISOR: f64 = 512.0;
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut devices = vec![];
+    for i in 0..DEVICE_COUNT {
+      devices.push(
+        helper
+          .add_test_device(
+            i,
+            &format!("Test Device {}", i),
+            &util::vibrate_device_attributes(),
+          )
+          .await,
+      );
+    }
+
+    // Interleave commands across devices, then await them in reverse order.
+    // Commands should still reach the server in the order they were issued.
+    let mut command_futures: Vec<_> = (0..COMMAND_COUNT)
+      .map(|i| {
+        devices[(i % DEVICE_COUNT) as usize]
+          .vibrate(VibrateCommand::Speed(i as f64 / SPEED_DIVISOR))
+      })
+      .collect();
+    command_futures.reverse();
+    let (results, received) = futures::join!(futures::future::join_all(command_futures), async {
+      let mut received: HashMap<u32, Vec<f64>> = HashMap::new();
+      for _ in 0..COMMAND_COUNT {
+        let msg = helper.get_next_client_message().await;
+        if let ButtplugClientMessage::VibrateCmd(cmd) = &msg {
+          received
+            .entry(cmd.device_index())
+            .or_default()
+            .push(cmd.speeds()[0].speed());
+        } else {
+          panic!("Should've gotten a VibrateCmd message.");
+        }
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+      }
+      received
+    });
+    assert!(results.iter().all(|r| r.is_ok()));
+    for i in 0..DEVICE_COUNT {
+      let expected: Vec<f64> = (0..COMMAND_COUNT)
+        .filter(|cmd| cmd % DEVICE_COUNT == i)
+        .map(|cmd| cmd as f64 / SPEED_DIVISOR)
+        .collect();
+      assert_eq!(received[&i], expected);
+    }
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_info() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    // Only expose some of the Device Information Service endpoints.
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::RawReadCmd,
+      DeviceMessageAttributes {
+        endpoints: Some(vec![
+          Endpoint::RxBLEManufacturer,
+          Endpoint::RxBLEModel,
+          Endpoint::RxBLEFirmwareRevision,
+        ]),
+        ..Default::default()
+      },
+    );
+    let device = helper
+      .add_test_device(0, "Test Device", &device_messages)
+      .await;
+    let (info, _) = futures::join!(device.device_info(), async {
+      for _ in 0..3 {
+        let msg = helper.get_next_client_message().await;
+        let endpoint = if let ButtplugClientMessage::RawReadCmd(cmd) = &msg {
+          cmd.endpoint()
+        } else {
+          panic!("Should've gotten a RawReadCmd message.");
+        };
+        let data = match endpoint {
+          Endpoint::RxBLEManufacturer => b"Lovense\0\0".to_vec(),
+          Endpoint::RxBLEFirmwareRevision => b"1.2 ".to_vec(),
+          // Empty values count as missing.
+          Endpoint::RxBLEModel => vec![],
+          _ => panic!("Should only read exposed endpoints."),
+        };
+        let mut reading = messages::RawReading::new(0, endpoint, data);
+        reading.set_id(msg.id());
+        helper.send_client_incoming(reading.into()).await;
+      }
+    });
+    assert_eq!(
+      info.unwrap(),
+      DeviceInformation {
+        manufacturer: Some("Lovense".to_owned()),
+        firmware_revision: Some("1.2".to_owned()),
+        ..Default::default()
+      }
+    );
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_snapshot_identifier() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::RawReadCmd,
+      DeviceMessageAttributes {
+        endpoints: Some(vec![Endpoint::RxBLESerialNumber]),
+        ..Default::default()
+      },
+    );
+    let device = helper
+      .add_test_device(0, "Test Device", &device_messages)
+      .await;
+    assert_eq!(device.snapshot().identifier, None);
+    let (info, _) = futures::join!(device.device_info(), async {
+      let msg = helper.get_next_client_message().await;
+      let mut reading =
+        messages::RawReading::new(0, Endpoint::RxBLESerialNumber, b"ABC123".to_vec());
+      reading.set_id(msg.id());
+      helper.send_client_incoming(reading.into()).await;
+    });
+    assert_eq!(info.unwrap().serial_number, Some("ABC123".to_owned()));
+    assert_eq!(
+      helper.client().device_snapshot()[0].identifier,
+      Some("ABC123".to_owned())
+    );
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_max_command_rate() {
+  const COMMAND_COUNT: u32 = 10;
+  const MAX_RATE: u32 = 20;
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    assert_eq!(helper.client().max_command_rate(), None);
+    helper.client().set_max_command_rate(Some(MAX_RATE));
+    assert_eq!(helper.client().max_command_rate(), Some(MAX_RATE));
+    let mut devices = vec![];
+    for i in 0..2 {
+      devices.push(
+        helper
+          .add_test_device(
+            i,
+            &format!("Test Device {}", i),
+            &util::vibrate_device_attributes(),
+          )
+          .await,
+      );
+    }
+
+    // Fire off commands to both devices at once. The throttle applies to the
+    // client as a whole, so they should trickle out at the max rate.
+    let command_futures: Vec<_> = (0..COMMAND_COUNT)
+      .map(|i| {
+        devices[(i % 2) as usize].vibrate(VibrateCommand::Speed(i as f64 / COMMAND_COUNT as f64))
+      })
+      .collect();
+    let (results, arrivals) = futures::join!(futures::future::join_all(command_futures), async {
+      let mut arrivals = vec![];
+      for _ in 0..COMMAND_COUNT {
+        let msg = helper.get_next_client_message().await;
+        arrivals.push(Instant::now());
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+      }
+      arrivals
+    });
+    assert!(results.iter().all(|r| r.is_ok()));
+    // Allow a little slack for timer precision.
+    let min_interval = Duration::from_secs(1) / MAX_RATE;
+    for pair in arrivals.windows(2) {
+      assert!(pair[1] - pair[0] >= min_interval - Duration::from_millis(10));
+    }
+    assert!(
+      arrivals[arrivals.len() - 1] - arrivals[0]
+        >= min_interval * (COMMAND_COUNT - 1) - Duration::from_millis(10)
+    );
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_stop_all_devices_after_device_commands() {
+  const COMMAND_COUNT: usize = 5;
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let device = helper
+      .add_test_device(0, "Test Device", &util::vibrate_device_attributes())
+      .await;
+    // Run a few rounds, so a loop that picks between channels at random would
+    // get caught sending the stop early.
+    for _ in 0..20u8 {
+      let command_futures: Vec<_> = (0..COMMAND_COUNT)
+        .map(|i| device.vibrate(VibrateCommand::Speed((i + 1) as f64 / 10.0)))
+        .collect();
+      let stop_future = helper.client().stop_all_devices();
+      let (results, stop_result, _) = futures::join!(
+        futures::future::join_all(command_futures),
+        stop_future,
+        async {
+          for _ in 0..COMMAND_COUNT {
+            let msg = helper.get_next_client_message().await;
+            assert!(matches!(msg, ButtplugClientMessage::VibrateCmd(..)));
+            helper
+              .send_client_incoming(messages::Ok::new(msg.id()).into())
+              .await;
+          }
+          let msg = helper.get_next_client_message().await;
+          assert!(matches!(msg, ButtplugClientMessage::StopAllDevices(..)));
+          helper
+            .send_client_incoming(messages::Ok::new(msg.id()).into())
+            .await;
+        }
+      );
+      assert!(results.iter().all(|r| r.is_ok()));
+      assert!(stop_result.is_ok());
+    }
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_stop_all_devices_cancels_throttled_commands() {
+  const COMMAND_COUNT: usize = 5;
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    helper.client().set_max_command_rate(Some(2));
+    let device = helper
+      .add_test_device(0, "Test Device", &util::vibrate_device_attributes())
+      .await;
+    // The first command goes out right away, the rest wait on the throttle.
+    let command_futures: Vec<_> = (0..COMMAND_COUNT)
+      .map(|i| device.vibrate(VibrateCommand::Speed((i + 1) as f64 / 10.0)))
+      .collect();
+    let stop_future = helper.client().stop_all_devices();
+    let (results, stop_result, _) = futures::join!(
+      futures::future::join_all(command_futures),
+      stop_future,
+      async {
+        let msg = helper.get_next_client_message().await;
+        assert!(matches!(msg, ButtplugClientMessage::VibrateCmd(..)));
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+        let msg = helper.get_next_client_message().await;
+        assert!(matches!(msg, ButtplugClientMessage::StopAllDevices(..)));
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+      }
+    );
+    assert!(stop_result.is_ok());
+    assert!(results[0].is_ok());
+    assert!(results[1..]
+      .iter()
+      .all(|r| matches!(r, Err(ButtplugClientError::CommandCancelled))));
+    // Give the throttle long enough to have let every command out, then make
+    // sure none of them followed the stop.
+    Delay::new(Duration::from_millis(COMMAND_COUNT as u64 * 500)).await;
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_dropped_throttled_commands_are_not_sent() {
+  const COMMAND_COUNT: usize = 4;
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    helper.client().set_max_command_rate(Some(2));
+    let device = helper
+      .add_test_device(0, "Test Device", &util::vibrate_device_attributes())
+      .await;
+    // The first command goes out right away, the rest wait on the throttle.
+    // Give up on all of those.
+    let mut command_futures: Vec<_> = (0..COMMAND_COUNT)
+      .map(|i| device.vibrate(VibrateCommand::Speed((i + 1) as f64 / 10.0)))
+      .collect();
+    command_futures.truncate(1);
+    let (result, _) = futures::join!(command_futures.pop().unwrap(), async {
+      let msg = helper.get_next_client_message().await;
+      assert!(matches!(msg, ButtplugClientMessage::VibrateCmd(..)));
+      helper
+        .send_client_incoming(messages::Ok::new(msg.id()).into())
+        .await;
+    });
+    assert!(result.is_ok());
+    Delay::new(Duration::from_millis(COMMAND_COUNT as u64 * 500)).await;
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_oscillate() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::OscillateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(2),
+        step_count: Some(vec![20, 20]),
+        ..Default::default()
+      },
+    );
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+    assert_eq!(
+      test_device.oscillate_attributes().unwrap().feature_count,
+      Some(2)
+    );
+    let (result, msg) = futures::join!(
+      test_device.oscillate(OscillateCommand::Speed(0.5)),
+      async {
+        let msg = helper.get_next_client_message().await;
+        helper
+          .send_client_incoming(messages::Ok::new(msg.id()).into())
+          .await;
+        msg
+      }
+    );
+    assert!(result.is_ok());
+    if let ButtplugClientMessage::OscillateCmd(cmd) = msg {
+      assert_eq!(cmd.device_index(), 1);
+      assert_eq!(
+        *cmd.speeds(),
+        vec![
+          messages::OscillateSubcommand::new(0, 0.5),
+          messages::OscillateSubcommand::new(1, 0.5)
+        ]
+      );
+    } else {
+      panic!("Should've gotten an OscillateCmd, got {:?}", msg);
+    }
+    assert!(matches!(
+      test_device
+        .oscillate(OscillateCommand::SpeedVec(vec!(0.5, 0.5, 0.5)))
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceFeatureCountMismatch(2, 3)
+      ))
+    ));
+    // The device doesn't vibrate, so we shouldn't even try to send this.
+    assert!(test_device.vibrate(VibrateCommand::Speed(0.5)).await.is_err());
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_rotate_per_motor_direction() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::RotateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(2),
+        step_count: Some(vec![20, 20]),
+        ..Default::default()
+      },
+    );
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+    assert_eq!(test_device.rotate_attributes().unwrap().feature_count, Some(2));
+    assert!(test_device.oscillate_attributes().is_none());
+
+    let rotate_and_reply = |cmd: RotateCommand| {
+      let helper = helper.clone();
+      let test_device = test_device.clone();
+      async move {
+        let (result, msg) = futures::join!(test_device.rotate(cmd), async {
+          let msg = helper.get_next_client_message().await;
+          helper
+            .send_client_incoming(messages::Ok::new(msg.id()).into())
+            .await;
+          msg
+        });
+        assert!(result.is_ok());
+        if let ButtplugClientMessage::RotateCmd(mut cmd) = msg {
+          cmd.rotations.sort_by_key(|r| r.index());
+          cmd.rotations
+        } else {
+          panic!("Should've gotten a RotateCmd, got {:?}", msg);
+        }
+      }
+    };
+
+    // Rotors spinning in opposite directions.
+    let expected = vec![
+      messages::RotationSubcommand::new(0, 0.5, true),
+      messages::RotationSubcommand::new(1, 0.5, false),
+    ];
+    assert_eq!(
+      rotate_and_reply(RotateCommand::RotateVec(vec![(0.5, true), (0.5, false)])).await,
+      expected
+    );
+    let mut rotate_map = HashMap::new();
+    rotate_map.insert(0, (0.25, false));
+    rotate_map.insert(1, (0.75, true));
+    assert_eq!(
+      rotate_and_reply(RotateCommand::RotateMap(rotate_map)).await,
+      vec![
+        messages::RotationSubcommand::new(0, 0.25, false),
+        messages::RotationSubcommand::new(1, 0.75, true),
+      ]
+    );
+
+    assert!(matches!(
+      test_device
+        .rotate(RotateCommand::RotateVec(vec![(0.5, true), (0.5, false), (0.5, true)]))
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceFeatureCountMismatch(2, 3)
+      ))
+    ));
+    let mut rotate_map = HashMap::new();
+    rotate_map.insert(2, (0.5, true));
+    assert!(matches!(
+      test_device
+        .rotate(RotateCommand::RotateMap(rotate_map))
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceFeatureIndexError(2, 2)
+      ))
+    ));
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[test]
+fn test_client_device_command_builder() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(2),
+        step_count: Some(vec![20, 20]),
+        ..Default::default()
+      },
+    );
+    device_messages.insert(
+      ButtplugDeviceMessageType::RotateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(1),
+        step_count: Some(vec![20]),
+        ..Default::default()
+      },
+    );
+    let test_device = helper
+      .add_test_device(1, "Test Device", &device_messages)
+      .await;
+
+    // Sending nothing is fine, and doesn't hit the server.
+    assert!(test_device.command().send().await.is_ok());
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+
+    // Multiple vibrate targets coalesce into one message, with later targets
+    // winning.
+    let mut vibrate_map = HashMap::new();
+    vibrate_map.insert(1, 1.0);
+    let (result, msgs) = futures::join!(
+      test_device
+        .command()
+        .vibrate(VibrateCommand::SpeedVec(vec![0.5, 0.5]))
+        .rotate(RotateCommand::Rotate(0.25, true))
+        .vibrate(VibrateCommand::SpeedMap(vibrate_map))
+        .send(),
+      async {
+        let mut msgs = vec![];
+        for _ in 0..2 {
+          let msg = helper.get_next_client_message().await;
+          helper
+            .send_client_incoming(messages::Ok::new(msg.id()).into())
+            .await;
+          msgs.push(msg);
+        }
+        msgs
+      }
+    );
+    assert!(result.is_ok());
+    if let ButtplugClientMessage::VibrateCmd(cmd) = &msgs[0] {
+      assert_eq!(
+        cmd
+          .speeds()
+          .iter()
+          .map(|s| (s.index(), s.speed()))
+          .collect::<Vec<_>>(),
+        vec![(0, 0.5), (1, 1.0)]
+      );
+    } else {
+      panic!("Should've gotten a VibrateCmd, got {:?}", msgs[0]);
+    }
+    if let ButtplugClientMessage::RotateCmd(cmd) = &msgs[1] {
+      assert_eq!(cmd.rotations.len(), 1);
+      assert_eq!(cmd.rotations[0].speed(), 0.25);
+      assert!(cmd.rotations[0].clockwise());
+    } else {
+      panic!("Should've gotten a RotateCmd, got {:?}", msgs[1]);
+    }
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+
+    // If any target is invalid, nothing is sent.
+    assert!(matches!(
+      test_device
+        .command()
+        .vibrate(VibrateCommand::Speed(0.75))
+        .rotate(RotateCommand::RotateVec(vec![(0.5, true), (0.5, false)]))
+        .send()
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceFeatureCountMismatch(1, 2)
+      ))
+    ));
+    assert!(matches!(
+      test_device
+        .command()
+        .vibrate(VibrateCommand::Speed(0.75))
+        .linear(LinearCommand::Linear(500, 0.5))
+        .send()
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::MessageNotSupported(_)
+      ))
+    ));
+    assert!(helper.recv_outgoing().now_or_never().is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_oscillate_unsupported() {
+  async_manager::block_on(async {
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let _ = helper.add_ble_device("Massage Demo").await;
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    assert!(test_device.oscillate_attributes().is_none());
+    assert!(matches!(
+      test_device
+        .oscillate(OscillateCommand::Speed(0.5))
+        .await
+        .unwrap_err(),
+      ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::MessageNotSupported(..)
+      ))
+    ));
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_raw_messages_allowed() {
+  async_manager::block_on(async {
+    for allow_raw in [false, true].iter() {
+      let server = ButtplugServerBuilder::default()
+        .allow_raw_messages(*allow_raw)
+        .finish()
+        .unwrap();
+      let connector = ButtplugInProcessClientConnector::new(Some(server));
+      let builder = TestDeviceCommunicationManagerBuilder::default();
+      let helper = builder.helper();
+      connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+      let _ = helper.add_ble_device("Massage Demo").await;
+      let client = ButtplugClient::new("Test Client");
+      let mut event_stream = client.event_stream();
+      client.connect(connector).await.unwrap();
+      client.start_scanning().await.unwrap();
+      let mut client_device = None;
+      while let Some(msg) = event_stream.next().await {
+        if let ButtplugClientEvent::DeviceAdded(da) = msg {
+          client_device = Some(da);
+          break;
+        }
+      }
+      let test_device = client_device.unwrap();
+      assert_eq!(test_device.raw_messages_allowed(), *allow_raw);
+      if *allow_raw {
+        assert!(test_device
+          .raw_write(Endpoint::Tx, vec![0x0], false)
+          .await
+          .is_ok());
+      } else {
+        assert!(matches!(
+          test_device
+            .raw_write(Endpoint::Tx, vec![0x0], false)
+            .await
+            .unwrap_err(),
+          ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+            ButtplugDeviceError::DevicePermissionError(..)
+          ))
+        ));
+      }
+    }
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_subscribe_sensor() {
+  async_manager::block_on(async {
+    let server = ButtplugServerBuilder::default()
+      .allow_raw_messages(true)
+      .finish()
+      .unwrap();
+    let connector = ButtplugInProcessClientConnector::new(Some(server));
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    let sensor = ButtplugClientDeviceSensorType::Raw(Endpoint::Rx);
+    let mut readings = test_device.subscribe_sensor(sensor).await.unwrap();
+    // Notifications on endpoints we haven't subscribed to shouldn't show up.
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Tx,
+      vec![0x1],
+    ));
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x2, 0x3],
+    ));
+    assert_eq!(
+      readings.next().await.unwrap(),
+      ButtplugClientDeviceSensorReading::Raw(Endpoint::Rx, vec![0x2, 0x3])
+    );
+    test_device.unsubscribe_sensor(sensor).await.unwrap();
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x4],
+    ));
+    // Stream ends on disconnect, without seeing anything sent after the
+    // unsubscribe.
+    device.disconnect().await.unwrap();
+    assert!(readings.next().await.is_none());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_subscribe_battery_sensor() {
+  async_manager::block_on(async {
+    let server = ButtplugServerBuilder::default()
+      .allow_raw_messages(true)
+      .finish()
+      .unwrap();
+    let connector = ButtplugInProcessClientConnector::new(Some(server));
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let device = helper.add_ble_device("Massage Demo").await;
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    let test_device = client_device.unwrap();
+    let mut readings = test_device
+      .subscribe_sensor(ButtplugClientDeviceSensorType::Battery)
+      .await
+      .unwrap();
+    // Other endpoints aren't battery readings.
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::Rx,
+      vec![0x10],
+    ));
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::RxBLEBattery,
+      vec![75],
+    ));
+    assert_eq!(
+      readings.next().await.unwrap(),
+      ButtplugClientDeviceSensorReading::Battery(0.75)
+    );
+    // Out of spec values are capped at full.
+    device.send_event(ButtplugDeviceEvent::Notification(
+      device.address(),
+      Endpoint::RxBLEBattery,
+      vec![200],
+    ));
+    assert_eq!(
+      readings.next().await.unwrap(),
+      ButtplugClientDeviceSensorReading::Battery(1.0)
+    );
+    test_device
+      .unsubscribe_sensor(ButtplugClientDeviceSensorType::Battery)
+      .await
+      .unwrap();
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_subscribe_sensor_requires_raw() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    connector.server_ref().device_manager().add_comm_manager(builder).unwrap();
+    let _ = helper.add_ble_device("Massage Demo").await;
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client.connect(connector).await.unwrap();
+    client.start_scanning().await.unwrap();
+    let mut client_device = None;
+    while let Some(msg) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(da) = msg {
+        client_device = Some(da);
+        break;
+      }
+    }
+    assert!(matches!(
+      client_device
+        .unwrap()
+        .subscribe_sensor(ButtplugClientDeviceSensorType::Raw(Endpoint::Rx))
+        .await,
+      Err(ButtplugClientError::ButtplugError(
+        ButtplugError::ButtplugDeviceError(ButtplugDeviceError::DevicePermissionError(..))
+      ))
+    ));
+  });
+}
+
+// TODO Test invalid messages to device
+// TODO Test invalid parameters in message
+// TODO Test device invalidation across client connections (i.e. a device shouldn't be allowed to reconnect even if index is the same)
+// TODO Test DeviceList being sent followed by repeat DeviceAdded
+// TODO Test DeviceList being sent multiple times
+// TODO Test sending device return for device that doesn't exist (in client)
+
+#[test]
+fn test_client_max_devices() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut event_stream = helper.client().event_stream();
+    assert!(helper.client().max_devices().is_none());
+    helper.client().set_max_devices(Some(2));
+    assert_eq!(helper.client().max_devices(), Some(2));
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::StopDeviceCmd,
+      DeviceMessageAttributes::default(),
+    );
+    // Fill up to the limit.
+    for index in 0..2 {
+      helper
+        .send_client_incoming(
+          messages::DeviceAdded::new(index, "Test Device", &device_messages).into(),
+        )
+        .await;
+      assert!(matches!(
+        event_stream.next().await.unwrap(),
+        ButtplugClientEvent::DeviceAdded(..)
+      ));
+    }
+    assert_eq!(helper.client().devices().len(), 2);
+    // One past the limit should be rejected, and the server asked to stop it.
+    helper
+      .send_client_incoming(messages::DeviceAdded::new(2, "Test Device", &device_messages).into())
+      .await;
+    if let ButtplugClientEvent::DeviceRejected(info) = event_stream.next().await.unwrap() {
+      assert_eq!(info.device_index, 2);
+    } else {
+      panic!("Should've gotten a DeviceRejected event.");
+    }
+    if let ButtplugClientMessage::StopDeviceCmd(msg) = helper.get_next_client_message().await {
+      assert_eq!(msg.device_index(), 2);
+    } else {
+      panic!("Should've gotten a StopDeviceCmd.");
+    }
+    assert_eq!(helper.client().devices().len(), 2);
+    // Once a device leaves, there's room again.
+    helper
+      .send_client_incoming(messages::DeviceRemoved::new(0).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceRemoved(..)
+    ));
+    helper
+      .send_client_incoming(messages::DeviceAdded::new(3, "Test Device", &device_messages).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceAdded(..)
+    ));
+    // Removing the limit lets everything through.
+    helper.client().set_max_devices(None);
+    helper
+      .send_client_incoming(messages::DeviceAdded::new(4, "Test Device", &device_messages).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceAdded(..)
+    ));
+    assert_eq!(helper.client().devices().len(), 3);
+  });
+}
+
+#[test]
+fn test_client_max_devices_zero() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut event_stream = helper.client().event_stream();
+    // A limit of 0 is a real limit, not "unlimited".
+    helper.client().set_max_devices(Some(0));
+    assert_eq!(helper.client().max_devices(), Some(0));
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::StopDeviceCmd,
+      DeviceMessageAttributes::default(),
+    );
+    helper
+      .send_client_incoming(messages::DeviceAdded::new(0, "Test Device", &device_messages).into())
+      .await;
+    if let ButtplugClientEvent::DeviceRejected(info) = event_stream.next().await.unwrap() {
+      assert_eq!(info.device_index, 0);
+    } else {
+      panic!("Should've gotten a DeviceRejected event.");
+    }
+    assert!(matches!(
+      helper.get_next_client_message().await,
+      ButtplugClientMessage::StopDeviceCmd(..)
+    ));
+    assert!(helper.client().devices().is_empty());
+  });
+}
+
+#[test]
+fn test_client_device_snapshot() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    assert!(helper.client().device_snapshot().is_empty());
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      DeviceMessageAttributes {
+        feature_count: Some(2),
+        step_count: Some(vec![20, 20]),
+        ..Default::default()
+      },
+    );
+    device_messages.insert(
+      ButtplugDeviceMessageType::StopDeviceCmd,
+      DeviceMessageAttributes::default(),
+    );
+    // Add out of index order to make sure the snapshot keeps add order, like
+    // devices() does.
+    for index in [3, 1] {
+      helper
+        .add_test_device(index, &format!("Test Device {}", index), &device_messages)
+        .await;
+    }
+    let snapshot = helper.client().device_snapshot();
+    assert_eq!(
+      snapshot.iter().map(|s| s.index).collect::<Vec<u32>>(),
+      vec![3, 1]
+    );
+    assert_eq!(snapshot[0].name, "Test Device 3");
+    // No device information has been read, so there's nothing to identify the
+    // device by.
+    assert_eq!(snapshot[0].identifier, None);
+    assert!(snapshot[0].connected);
+    assert_eq!(
+      snapshot[0].messages[&ButtplugClientDeviceMessageType::VibrateCmd].step_count,
+      Some(vec![20, 20])
+    );
+    assert!(snapshot[0]
+      .messages
+      .contains_key(&ButtplugClientDeviceMessageType::StopDeviceCmd));
+    // Make sure it round trips through JSON, since that's what it'll usually
+    // be used for.
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let deserialized: Vec<ButtplugClientDeviceSnapshot> = serde_json::from_str(&json).unwrap();
+    assert_eq!(snapshot, deserialized);
+  });
+}
+
+#[test]
+fn test_client_devices_preserve_add_order() {
+  async_manager::block_on(async move {
+    let helper = Arc::new(util::ChannelClientTestHelper::new());
+    helper.simulate_successful_connect().await;
+    let mut device_messages = DeviceMessageAttributesMap::new();
+    device_messages.insert(
+      ButtplugDeviceMessageType::StopDeviceCmd,
+      DeviceMessageAttributes::default(),
+    );
+    let device_indexes = |client: &ButtplugClient| {
+      client
+        .devices()
+        .iter()
+        .map(|device| device.index())
+        .collect::<Vec<u32>>()
+    };
+    for index in [7, 2, 9, 0, 4] {
+      helper
+        .add_test_device(index, "Test Device", &device_messages)
+        .await;
+    }
+    assert_eq!(device_indexes(helper.client()), vec![7, 2, 9, 0, 4]);
+    let mut event_stream = helper.client().event_stream();
+    helper
+      .send_client_incoming(messages::DeviceRemoved::new(9).into())
+      .await;
+    assert!(matches!(
+      event_stream.next().await.unwrap(),
+      ButtplugClientEvent::DeviceRemoved(..)
+    ));
+    // Re-adding a removed index puts it at the end.
+    for index in [9, 1] {
+      helper
+        .add_test_device(index, "Test Device", &device_messages)
+        .await;
+    }
+    for _ in 0..5 {
+      assert_eq!(device_indexes(helper.client()), vec![7, 2, 0, 4, 9, 1]);
+    }
+  });
+}
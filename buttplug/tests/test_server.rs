@@ -2,20 +2,24 @@ mod util;
 
 use buttplug::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
     messages::{
       self, ButtplugMessageSpecVersion, ButtplugServerMessage,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
   device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
-  server::{ButtplugServer, ButtplugServerBuilder},
+  server::{ButtplugServer, ButtplugServerBuilder, ButtplugServerError},
   server::comm_managers::test::{TestDeviceCommunicationManagerBuilder, check_test_recv_value},
+  server::comm_managers::{
+    DeviceCommunicationEvent, DeviceCommunicationManager, DeviceCommunicationManagerBuilder,
+  },
   util::async_manager,
 };
 use futures::{pin_mut, Stream, StreamExt};
 use futures_timer::Delay;
 use std::time::Duration;
+use tokio::sync::mpsc::Sender;
 
 async fn setup_test_server(
   msg_union: messages::ButtplugClientMessage,
@@ -26,7 +30,7 @@ async fn setup_test_server(
   match server.parse_message(msg_union).await.unwrap() {
     ButtplugServerMessage::ServerInfo(s) => assert_eq!(
       s,
-      messages::ServerInfo::new("Buttplug Server", ButtplugMessageSpecVersion::Version2, 0)
+      messages::ServerInfo::new("Buttplug Server", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION, 0)
     ),
     _ => panic!("Should've received ok"),
   }
@@ -36,7 +40,7 @@ async fn setup_test_server(
 #[test]
 fn test_server_handshake() {
   let msg =
-    messages::RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version2).into();
+    messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into();
   async_manager::block_on(async {
     let (server, _recv) = setup_test_server(msg).await;
     assert!(server.connected());
@@ -216,6 +220,60 @@ fn test_invalid_device_index() {
   });
 }
 
+#[test]
+fn test_server_start_scanning_no_managers_named() {
+  async_manager::block_on(async {
+    let msg =
+      messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION);
+    let (server, _) = setup_test_server(msg.into()).await;
+    server
+      .device_manager()
+      .add_comm_manager(TestDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    let reply = server
+      .parse_message(messages::StartScanningManagers::new(vec![]).into())
+      .await;
+    assert!(matches!(
+      reply.unwrap_err().original_error(),
+      ButtplugError::ButtplugMessageError(ButtplugMessageError::InvalidMessageContents(_))
+    ));
+  });
+}
+
+/// Stands in for a comm manager whose backend is broken on the current machine.
+struct PanickingDeviceCommunicationManagerBuilder {}
+
+impl DeviceCommunicationManagerBuilder for PanickingDeviceCommunicationManagerBuilder {
+  fn event_sender(self, _sender: Sender<DeviceCommunicationEvent>) -> Self {
+    self
+  }
+
+  fn finish(self) -> Box<dyn DeviceCommunicationManager> {
+    panic!("Backend not available.");
+  }
+}
+
+#[test]
+fn test_server_add_comm_manager_init_failure() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let device_manager = server.device_manager();
+    assert!(matches!(
+      device_manager.add_comm_manager(PanickingDeviceCommunicationManagerBuilder {}),
+      Err(ButtplugServerError::DeviceManagerInitFailed(_))
+    ));
+    assert!(device_manager.comm_manager_names().is_empty());
+    // Managers added afterwards are unaffected.
+    device_manager
+      .add_comm_manager(TestDeviceCommunicationManagerBuilder::default())
+      .unwrap();
+    assert_eq!(
+      device_manager.comm_manager_names(),
+      vec!["TestDeviceCommunicationManager".to_owned()]
+    );
+  });
+}
+
 #[test]
 fn test_device_index_generation() {
   async_manager::block_on(async {
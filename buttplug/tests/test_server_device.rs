@@ -6,13 +6,13 @@ use buttplug::{
       self, ButtplugDeviceMessageType, ButtplugServerMessage, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
-  device::Endpoint,
+  device::{DeviceWriteCmd, Endpoint},
   server::{ButtplugServer, ButtplugServerBuilder},
   server::comm_managers::test::TestDeviceCommunicationManagerBuilder,
-  util::async_manager,
+  util::{async_manager, device_configuration::get_internal_config_version},
 };
 use futures::{pin_mut, StreamExt};
-use std::matches;
+use std::{matches, time::Duration};
 
 // Test devices that have protocols that support movements not all devices do.
 // For instance, the Onyx+ is part of a protocol that supports vibration, but
@@ -286,3 +286,229 @@ fn test_repeated_address_additions() {
     }
   });
 }
+
+#[test]
+fn test_runtime_device_configuration_merge() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let additional_config = format!(
+      r#"
+      {{
+        "version": {},
+        "protocols": {{
+          "aneros": {{
+            "btle": {{
+              "names": ["Aneros New Toy"],
+              "services": {{
+                "0000ff00-0000-1000-8000-00805f9b34fb": {{
+                  "tx": "0000ff01-0000-1000-8000-00805f9b34fb"
+                }}
+              }}
+            }},
+            "configurations": [
+              {{
+                "identifier": ["Aneros New Toy"],
+                "name": {{
+                  "en-us": "Aneros New Toy"
+                }}
+              }}
+            ]
+          }}
+        }}
+      }}
+      "#,
+      get_internal_config_version()
+    );
+    server
+      .device_manager()
+      .add_device_configuration_json(&additional_config)
+      .unwrap();
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper.add_ble_device("Aneros New Toy").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::ScanningFinished(_) = msg {
+        continue;
+      } else if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        assert_eq!(da.device_name(), "Aneros New Toy");
+        return;
+      } else {
+        panic!(
+          "Returned message was not a DeviceAdded message or timed out: {:?}",
+          msg
+        );
+      }
+    }
+  });
+}
+
+#[test]
+fn test_runtime_device_configuration_invalid_json() {
+  let server = ButtplugServer::default();
+  assert!(matches!(
+    server
+      .device_manager()
+      .add_device_configuration_json("{ \"version\": 1, \"not-protocols\": {} }"),
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceConfigurationFileError(_)
+    ))
+  ));
+  assert!(matches!(
+    server
+      .device_manager()
+      .add_device_configuration_json("this is not json"),
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceConfigurationFileError(_)
+    ))
+  ));
+}
+
+#[test]
+fn test_supported_protocols() {
+  let server = ButtplugServer::default();
+  let protocols = server.device_manager().supported_protocols();
+  let mut names: Vec<String> = protocols.iter().map(|p| p.protocol.clone()).collect();
+  names.sort();
+  assert_eq!(
+    protocols.iter().map(|p| p.protocol.clone()).collect::<Vec<String>>(),
+    names
+  );
+  let lovense = protocols.iter().find(|p| p.protocol == "lovense").unwrap();
+  assert!(lovense.identifiers.contains(&"LVS-*".to_owned()));
+  assert!(lovense.device_names.contains(&"Lovense Device".to_owned()));
+  assert!(lovense.device_names.contains(&"Lovense Edge".to_owned()));
+  let aneros = protocols.iter().find(|p| p.protocol == "aneros").unwrap();
+  assert_eq!(aneros.identifiers, vec!["Massage Demo".to_owned()]);
+  assert_eq!(aneros.device_names, vec!["Aneros Vivi".to_owned()]);
+  // Protocols without an implementation can't create devices, so shouldn't
+  // be listed even though their definitions are still loaded.
+  server.device_manager().remove_protocol("aneros").unwrap();
+  assert!(server
+    .device_manager()
+    .supported_protocols()
+    .iter()
+    .all(|p| p.protocol != "aneros"));
+}
+
+#[test]
+fn test_device_connection_parameters() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    assert!(matches!(
+      server.device_manager().device_connection_parameters(0),
+      Err(ButtplugDeviceError::DeviceNotAvailable(0))
+    ));
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(device) = msg {
+        let index = device.device_index();
+        // Test devices don't expose connection parameters, so this should
+        // come back as unsupported rather than as an error.
+        assert!(matches!(
+          server.device_manager().device_connection_parameters(index),
+          Ok(None)
+        ));
+        assert!(matches!(
+          server
+            .device_manager()
+            .request_device_connection_interval(index, Duration::from_millis(15))
+            .await,
+          Err(ButtplugError::ButtplugDeviceError(
+            ButtplugDeviceError::UnhandledCommand(_)
+          ))
+        ));
+        return;
+      }
+    }
+    panic!("Should've gotten a DeviceAdded message.");
+  });
+}
+
+#[test]
+fn test_server_lovense_osci_oscillate() {
+  async_manager::block_on(async {
+    let server = ButtplugServer::default();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    let builder = TestDeviceCommunicationManagerBuilder::default();
+    let helper = builder.helper();
+    server.device_manager().add_comm_manager(builder).unwrap();
+    let device = helper.add_ble_device("LVS-Osci").await;
+    // Answer the DeviceType query Lovense sends while initializing.
+    device.add_write_notification(Endpoint::Rx, b"O:11:000000000000;".to_vec());
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        assert_eq!(da.device_name(), "Lovense Osci");
+        assert!(da
+          .device_messages()
+          .contains_key(&ButtplugDeviceMessageType::OscillateCmd));
+        // Osci still vibrates for clients that don't know about OscillateCmd.
+        assert!(da
+          .device_messages()
+          .contains_key(&ButtplugDeviceMessageType::VibrateCmd));
+        device.take_write_history();
+        server
+          .parse_message(
+            messages::OscillateCmd::new(
+              da.device_index(),
+              vec![messages::OscillateSubcommand::new(0, 0.5)],
+            )
+            .into(),
+          )
+          .await
+          .unwrap();
+        assert_eq!(
+          device.take_write_history(),
+          vec![DeviceWriteCmd::new(
+            Endpoint::Tx,
+            b"Vibrate:10;".to_vec(),
+            false
+          )]
+        );
+        return;
+      }
+    }
+    panic!("Should've gotten a DeviceAdded message.");
+  });
+}
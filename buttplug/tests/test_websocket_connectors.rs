@@ -3,8 +3,9 @@
 #[cfg(feature = "websockets")]
 mod websocket_connector_tests {
   use buttplug::{
-    client::ButtplugClient,
+    client::{ButtplugClient, ButtplugClientError},
     connector::{
+      transport::ButtplugConnectorTransportSpecificError, ButtplugConnectorError,
       ButtplugRemoteClientConnector, ButtplugRemoteServerConnector,
       ButtplugWebsocketClientTransport, ButtplugWebsocketServerTransport,
       ButtplugWebsocketServerTransportBuilder,
@@ -13,8 +14,9 @@ mod websocket_connector_tests {
     server::ButtplugRemoteServer,
     util::async_manager,
   };
+  use futures::{pin_mut, select, FutureExt, StreamExt};
   use futures_timer::Delay;
-  use std::sync::Arc;
+  use std::{error::Error, sync::Arc};
   use std::time::Duration;
 
   #[test]
@@ -52,6 +54,50 @@ mod websocket_connector_tests {
     });
   }
 
+  #[test]
+  fn test_client_ws_client_server_ws_server_restart_same_port() {
+    async_manager::block_on(async move {
+      // Leave a connection to the port in TIME_WAIT, as a server that just
+      // shut down would. The side that closes first ends up there, so close
+      // the accepted stream before the client one.
+      let listener = std::net::TcpListener::bind("127.0.0.1:12350").unwrap();
+      let client_stream = std::net::TcpStream::connect("127.0.0.1:12350").unwrap();
+      let (server_stream, _) = listener.accept().unwrap();
+      drop(listener);
+      drop(server_stream);
+      Delay::new(Duration::from_millis(100)).await;
+      drop(client_stream);
+      Delay::new(Duration::from_millis(100)).await;
+      // Binding would now fail with "address in use" until TIME_WAIT runs
+      // out, unless SO_REUSEADDR is set.
+      let server = Arc::new(ButtplugRemoteServer::default());
+      let server_clone = server.clone();
+      let transport = ButtplugWebsocketServerTransportBuilder::default()
+        .port(12350)
+        .reuse_address(true)
+        .finish();
+      let bound_port = transport.bound_port();
+      async_manager::spawn(async move {
+        let connector = ButtplugRemoteServerConnector::<
+          ButtplugWebsocketServerTransport,
+          ButtplugServerJSONSerializer,
+        >::new(transport);
+        server_clone.start(connector).await.unwrap();
+      })
+      .unwrap();
+      assert_eq!(bound_port.await, Some(12350));
+      let connector = ButtplugRemoteClientConnector::<
+        ButtplugWebsocketClientTransport,
+        ButtplugClientJSONSerializer,
+      >::new(ButtplugWebsocketClientTransport::new_insecure_connector(
+        "ws://127.0.0.1:12350",
+      ));
+      let client = ButtplugClient::new("Test Client");
+      client.connect(connector).await.unwrap();
+      server.disconnect().await.unwrap();
+    });
+  }
+
   #[test]
   fn test_client_ws_server_server_ws_client_insecure() {
     async_manager::block_on(async move {
@@ -87,6 +133,182 @@ mod websocket_connector_tests {
       server.disconnect().await.unwrap();
     });
   }
+
+  #[test]
+  fn test_ws_server_custom_ping_payload() {
+    async_manager::block_on(async move {
+      let server = Arc::new(ButtplugRemoteServer::default());
+      let server_clone = server.clone();
+      let transport = ButtplugWebsocketServerTransportBuilder::default()
+        .port(12351)
+        .ping_payload(b"keepalive")
+        .finish();
+      let pong_stream = transport.pong_stream();
+      pin_mut!(pong_stream);
+      async_manager::spawn(async move {
+        let connector = ButtplugRemoteServerConnector::<
+          ButtplugWebsocketServerTransport,
+          ButtplugServerJSONSerializer,
+        >::new(transport);
+        server_clone.start(connector).await.unwrap();
+      })
+      .unwrap();
+      let client = ButtplugClient::new("Test Client");
+      let mut connected = false;
+      for _ in 0..10u8 {
+        let connector = ButtplugRemoteClientConnector::<
+          ButtplugWebsocketClientTransport,
+          ButtplugClientJSONSerializer,
+        >::new(ButtplugWebsocketClientTransport::new_insecure_connector(
+          "ws://127.0.0.1:12351",
+        ));
+        if client.connect(connector).await.is_ok() {
+          connected = true;
+          break;
+        }
+        Delay::new(Duration::from_secs(1)).await;
+      }
+      assert!(connected);
+      // The client echoes ping payloads back in its pongs, so we should see
+      // our payload come back within a ping interval or so.
+      let pong = select! {
+        pong = pong_stream.next().fuse() => pong,
+        _ = Delay::new(Duration::from_secs(5)).fuse() => None,
+      };
+      assert_eq!(pong, Some(b"keepalive".to_vec()));
+      server.disconnect().await.unwrap();
+    });
+  }
+
+  #[test]
+  fn test_client_transport_connected() {
+    async_manager::block_on(async move {
+      let server = Arc::new(ButtplugRemoteServer::default());
+      let server_clone = server.clone();
+      async_manager::spawn(async move {
+        let connector = ButtplugRemoteServerConnector::<
+          ButtplugWebsocketServerTransport,
+          ButtplugServerJSONSerializer,
+        >::new(ButtplugWebsocketServerTransportBuilder::default().port(12353).finish());
+        server_clone.start(connector).await.unwrap();
+      })
+      .unwrap();
+      let client = ButtplugClient::new("Test Client");
+      assert!(!client.transport_connected());
+      let mut connected = false;
+      for _ in 0..10u8 {
+        let connector = ButtplugRemoteClientConnector::<
+          ButtplugWebsocketClientTransport,
+          ButtplugClientJSONSerializer,
+        >::new(ButtplugWebsocketClientTransport::new_insecure_connector(
+          "ws://127.0.0.1:12353",
+        ));
+        if client.connect(connector).await.is_ok() {
+          connected = true;
+          break;
+        }
+        Delay::new(Duration::from_secs(1)).await;
+      }
+      assert!(connected);
+      assert!(client.transport_connected());
+      server.disconnect().await.unwrap();
+      // The transport should notice the socket closing on its own.
+      for _ in 0..50u8 {
+        if !client.transport_connected() {
+          break;
+        }
+        Delay::new(Duration::from_millis(100)).await;
+      }
+      assert!(!client.transport_connected());
+    });
+  }
+
+  #[test]
+  fn test_ws_server_ephemeral_port() {
+    async_manager::block_on(async move {
+      let server = Arc::new(ButtplugRemoteServer::default());
+      let server_clone = server.clone();
+      let transport = ButtplugWebsocketServerTransportBuilder::default()
+        .port(0)
+        .finish();
+      let bound_port = transport.bound_port();
+      async_manager::spawn(async move {
+        let connector = ButtplugRemoteServerConnector::<
+          ButtplugWebsocketServerTransport,
+          ButtplugServerJSONSerializer,
+        >::new(transport);
+        server_clone.start(connector).await.unwrap();
+      })
+      .unwrap();
+      let port = bound_port.await.unwrap();
+      assert_ne!(port, 0);
+      // The port is known once it's bound, so there's no need to retry.
+      let connector = ButtplugRemoteClientConnector::<
+        ButtplugWebsocketClientTransport,
+        ButtplugClientJSONSerializer,
+      >::new(ButtplugWebsocketClientTransport::new_insecure_connector(
+        &format!("ws://127.0.0.1:{}", port),
+      ));
+      let client = ButtplugClient::new("Test Client");
+      client.connect(connector).await.unwrap();
+      server.disconnect().await.unwrap();
+    });
+  }
+
+  #[test]
+  fn test_ws_server_bound_port_bind_error() {
+    async_manager::block_on(async move {
+      let _listener = std::net::TcpListener::bind("127.0.0.1:12354").unwrap();
+      let transport = ButtplugWebsocketServerTransportBuilder::default()
+        .port(12354)
+        .reuse_address(false)
+        .finish();
+      let bound_port = transport.bound_port();
+      let connector = ButtplugRemoteClientConnector::<
+        ButtplugWebsocketServerTransport,
+        ButtplugClientJSONSerializer,
+      >::new(transport);
+      let client = ButtplugClient::new("Test Client");
+      assert!(client.connect(connector).await.is_err());
+      assert_eq!(bound_port.await, None);
+    });
+  }
+
+  #[test]
+  fn test_ws_server_bind_error_source_chain() {
+    async_manager::block_on(async move {
+      // Hold the port so the transport can't bind it.
+      let _listener = std::net::TcpListener::bind("127.0.0.1:12352").unwrap();
+      let connector = ButtplugRemoteClientConnector::<
+        ButtplugWebsocketServerTransport,
+        ButtplugClientJSONSerializer,
+      >::new(
+        ButtplugWebsocketServerTransportBuilder::default()
+          .port(12352)
+          .reuse_address(false)
+          .finish(),
+      );
+      let client = ButtplugClient::new("Test Client");
+      let err = client.connect(connector).await.unwrap_err();
+      assert!(matches!(
+        err,
+        ButtplugClientError::ButtplugConnectorError(ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::IoError(_)
+        ))
+      ));
+      // Walk the chain down to the io::Error, as anyhow/eyre would.
+      let mut source = err.source();
+      let mut io_error = None;
+      while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+          io_error = Some(err.kind());
+          break;
+        }
+        source = err.source();
+      }
+      assert_eq!(io_error, Some(std::io::ErrorKind::AddrInUse));
+    });
+  }
 }
 
 // TODO Test disconnection event from server side
@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use buttplug::{
-  client::{ButtplugClient, ButtplugClientError},
+  client::{ButtplugClient, ButtplugClientDevice, ButtplugClientError, ButtplugClientEvent},
   connector::{
     transport::{ButtplugConnectorTransport, ButtplugTransportIncomingMessage},
     ButtplugConnectorError, ButtplugRemoteClientConnector, ButtplugRemoteServerConnector,
@@ -12,21 +12,23 @@ use buttplug::{
     serializer::{
       ButtplugClientJSONSerializer, ButtplugSerializedMessage, ButtplugServerJSONSerializer,
     },
-    ButtplugClientMessage, ButtplugCurrentSpecClientMessage, ButtplugMessage,
-    ButtplugServerMessage, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    ButtplugClientMessage, ButtplugCurrentSpecClientMessage, ButtplugDeviceMessageType,
+    ButtplugMessage, ButtplugServerMessage, DeviceMessageAttributes, DeviceMessageAttributesMap,
+    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
   },
   server::ButtplugRemoteServer,
   util::async_manager,
 };
 use futures::{
   future::{self, BoxFuture},
-  select, FutureExt,
+  select, FutureExt, StreamExt,
 };
 use std::sync::Arc;
 use tokio::sync::{
   mpsc::{channel, Receiver, Sender},
   Mutex, Notify,
 };
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 struct ChannelTransport {
@@ -141,7 +143,28 @@ impl ChannelClientTestHelper {
     self.client.connect(connector).await
   }
 
+  pub async fn connect_with_cancellation(
+    &self,
+    token: CancellationToken,
+  ) -> Result<(), ButtplugClientError> {
+    let connector = self.connector.lock().await.take().unwrap();
+    self.client.connect_with_cancellation(connector, token).await
+  }
+
   pub async fn simulate_successful_connect(&self) {
+    self
+      .simulate_successful_connect_with_server_info(messages::ServerInfo::new(
+        "test server",
+        messages::BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+        0,
+      ))
+      .await;
+  }
+
+  pub async fn simulate_successful_connect_with_server_info(
+    &self,
+    server_info: messages::ServerInfo,
+  ) {
     let client_clone = self.client.clone();
     let connector = self.connector.lock().await.take().unwrap();
     let finish_notifier = Arc::new(Notify::new());
@@ -159,16 +182,7 @@ impl ChannelClientTestHelper {
       ButtplugClientMessage::RequestServerInfo(..)
     ));
     // Just assume we get an RSI message
-    self
-      .send_client_incoming(
-        messages::ServerInfo::new(
-          "test server",
-          messages::BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
-          0,
-        )
-        .into(),
-      )
-      .await;
+    self.send_client_incoming(server_info.into()).await;
     // Wait for RequestDeviceList message.
     assert!(matches!(
       self.get_next_client_message().await,
@@ -212,6 +226,43 @@ impl ChannelClientTestHelper {
       ))
       .await;
   }
+
+  /// Has the server add a device, and returns the client's handle for it once
+  /// the client has emitted DeviceAdded. Event streams taken before this is
+  /// called will also see the DeviceAdded event.
+  pub async fn add_test_device(
+    &self,
+    index: u32,
+    name: &str,
+    attrs: &DeviceMessageAttributesMap,
+  ) -> Arc<ButtplugClientDevice> {
+    let mut event_stream = self.client.event_stream();
+    self
+      .send_client_incoming(messages::DeviceAdded::new(index, name, attrs).into())
+      .await;
+    while let Some(event) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(device) = event {
+        if device.index() == index {
+          return device;
+        }
+      }
+    }
+    panic!("Should've gotten a DeviceAdded event.");
+  }
+}
+
+/// Message attributes for a device with a single vibrator, which is all most
+/// client tests need.
+pub fn vibrate_device_attributes() -> DeviceMessageAttributesMap {
+  let mut attrs = DeviceMessageAttributesMap::new();
+  attrs.insert(
+    ButtplugDeviceMessageType::VibrateCmd,
+    DeviceMessageAttributes {
+      feature_count: Some(1),
+      ..Default::default()
+    },
+  );
+  attrs
 }
 
 pub struct ChannelServerTestHelper {